@@ -0,0 +1,226 @@
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+use crate::geometry::vector::*;
+use crate::sampling::warp::*;
+use std::f32::consts::PI;
+
+// Shape of the opening of a thin lens. Samples are returned on the unit
+// disk (or inside a shape inscribed in it) and scaled by the lens radius.
+#[derive(Clone, Debug)]
+pub enum Aperture {
+    Circular,
+    // A regular polygon formed by `blades` straight diaphragm blades,
+    // rotated by `rotation` degrees.
+    Polygon { blades: u32, rotation: f32 },
+    // Bokeh shape given by a grayscale mask over [-1, 1]^2.
+    Image(ApertureImage),
+}
+
+impl Aperture {
+    pub fn sample(&self, u: Point2<f32>) -> Point2<f32> {
+        match self {
+            Aperture::Circular => sample_uniform_disk_concentric(u),
+            Aperture::Polygon { blades, rotation } => {
+                let n = (*blades).max(3);
+                // Pick a wedge with the first dimension and reuse what is
+                // left of it to sample the wedge's triangle.
+                let scaled = u.x() * n as f32;
+                let wedge = (scaled as u32).min(n - 1);
+                let u = Point2::<f32>::elements(scaled - wedge as f32, u.y());
+
+                let vertex = |i: u32| {
+                    let phi = rotation.to_radians() + 2.0 * PI * i as f32 / n as f32;
+                    Point2::<f32>::elements(phi.cos(), phi.sin())
+                };
+                let (_, b1, b2) = sample_uniform_triangle(u);
+                vertex(wedge) * b1 + vertex(wedge + 1) * b2
+            }
+            Aperture::Image(image) => image.sample(u),
+        }
+    }
+}
+
+// A tabulated aperture mask. Pixels are chosen in proportion to their value
+// using marginal/conditional CDFs and sampled uniformly inside.
+#[derive(Clone, Debug)]
+pub struct ApertureImage {
+    width: usize,
+    height: usize,
+    // CDF over rows, then one CDF over columns per row. Each has one more
+    // entry than values, starting at 0 and ending at 1.
+    row_cdf: Vec<f32>,
+    col_cdfs: Vec<Vec<f32>>,
+}
+
+impl ApertureImage {
+    // Values are given row by row, top row first. Returns None if the mask
+    // is empty, there are not width * height values, or none is positive.
+    pub fn new(width: usize, height: usize, values: &[f32]) -> Option<Self> {
+        if width == 0 || height == 0 || Some(values.len()) != width.checked_mul(height) {
+            return None;
+        }
+        let mut row_sums = Vec::with_capacity(height);
+        let mut col_cdfs = Vec::with_capacity(height);
+        for row in values.chunks(width) {
+            let (cdf, sum) = Self::build_cdf(row);
+            row_sums.push(sum);
+            col_cdfs.push(cdf);
+        }
+        let (row_cdf, total) = Self::build_cdf(&row_sums);
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            row_cdf,
+            col_cdfs,
+        })
+    }
+
+    fn build_cdf(values: &[f32]) -> (Vec<f32>, f32) {
+        let mut cdf = Vec::with_capacity(values.len() + 1);
+        cdf.push(0.0);
+        for v in values {
+            cdf.push(cdf.last().unwrap() + v.max(0.0));
+        }
+        let sum = *cdf.last().unwrap();
+        if sum > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= sum);
+        }
+        (cdf, sum)
+    }
+
+    // Returns the bucket containing u and the position of u within it.
+    fn sample_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+        let i = cdf.partition_point(|&c| c <= u).clamp(1, cdf.len() - 1) - 1;
+        let width = cdf[i + 1] - cdf[i];
+        let offset = if width > 0.0 {
+            ((u - cdf[i]) / width).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        (i, offset)
+    }
+
+    pub fn sample(&self, u: Point2<f32>) -> Point2<f32> {
+        let (row, dy) = Self::sample_cdf(&self.row_cdf, u.y());
+        let (col, dx) = Self::sample_cdf(&self.col_cdfs[row], u.x());
+        let s = (col as f32 + dx) / self.width as f32;
+        let t = (row as f32 + dy) / self.height as f32;
+        Point2::<f32>::elements(2.0 * s - 1.0, 1.0 - 2.0 * t)
+    }
+}
+
+// A thin lens in the z = 0 plane of camera space. A lens radius of zero
+// gives a pinhole camera with everything in focus.
+#[derive(Clone, Debug)]
+pub struct ThinLens {
+    pub radius: f32,
+    pub focal_distance: f32,
+    pub aperture: Aperture,
+}
+
+impl ThinLens {
+    pub fn pinhole() -> Self {
+        Self {
+            radius: 0.0,
+            focal_distance: 1e6,
+            aperture: Aperture::Circular,
+        }
+    }
+
+    pub fn new(radius: f32, focal_distance: f32, aperture: Aperture) -> Self {
+        Self {
+            radius,
+            focal_distance,
+            aperture,
+        }
+    }
+
+    pub fn is_pinhole(&self) -> bool {
+        self.radius <= 0.0
+    }
+
+    pub fn sample_lens(&self, u: Point2<f32>) -> Point2<f32> {
+        self.aperture.sample(u) * self.radius
+    }
+
+    // Moves the origin of a camera-space pinhole ray onto the lens at p_lens
+    // and points it at the spot where the pinhole ray crosses the plane of
+    // focus, so that only that plane stays sharp.
+    pub fn focus(&self, origin: Point3<f32>, dir: Vec3<f32>, p_lens: Point2<f32>) -> Ray<f32> {
+        let ft = self.focal_distance / dir.z();
+        let p_focus = origin + dir * ft;
+        let origin =
+            Point3::<f32>::elements(origin.x() + p_lens.x(), origin.y() + p_lens.y(), origin.z());
+        Ray::<f32>::new(origin, (p_focus - origin).normalized())
+    }
+}
+
+impl Default for ThinLens {
+    fn default() -> Self {
+        Self::pinhole()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grid() -> impl Iterator<Item = Point2<f32>> {
+        (0..32).flat_map(|i| {
+            (0..32).map(move |j| {
+                Point2::<f32>::elements((i as f32 + 0.5) / 32.0, (j as f32 + 0.5) / 32.0)
+            })
+        })
+    }
+
+    #[test]
+    fn test_polygon_aperture() {
+        let hexagon = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        // Every sample lies inside the hexagon, i.e. within its inradius of
+        // the center along each edge normal.
+        let inradius = (PI / 6.0).cos();
+        for u in grid() {
+            let p = hexagon.sample(u);
+            for k in 0..6 {
+                let phi = PI / 6.0 + k as f32 * PI / 3.0;
+                let n = Point2::<f32>::elements(phi.cos(), phi.sin());
+                assert!(p.dot(&n) <= inradius + 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_aperture() {
+        // Only the top-right pixel of a 2x2 mask is open.
+        let image = ApertureImage::new(2, 2, &[0.0, 1.0, 0.0, 0.0]).unwrap();
+        for u in grid() {
+            let p = image.sample(u);
+            assert!(p.x() >= 0.0 && p.x() <= 1.0);
+            assert!(p.y() >= 0.0 && p.y() <= 1.0);
+        }
+        assert!(ApertureImage::new(1, 1, &[0.0]).is_none());
+        assert!(ApertureImage::new(0, 3, &[]).is_none());
+        assert!(ApertureImage::new(3, 0, &[]).is_none());
+        assert!(ApertureImage::new(2, 2, &[1.0; 3]).is_none());
+        assert!(ApertureImage::new(2, 2, &[1.0; 5]).is_none());
+    }
+
+    #[test]
+    fn test_focus() {
+        let lens = ThinLens::new(0.5, 4.0, Aperture::Circular);
+        let dir = Vec3::<f32>::elements(0.1, -0.2, 1.0).normalized();
+        let p_focus = dir * (4.0 / dir.z());
+        // Rays through any point on the lens meet again on the focal plane.
+        for u in grid().step_by(37) {
+            let ray = lens.focus(Vec3::<f32>::new(0.0), dir, lens.sample_lens(u));
+            let t = (4.0 - ray.origin().z()) / ray.dir().z();
+            assert!(ray.at(t).distance_to(&p_focus) < 1e-4);
+        }
+    }
+}
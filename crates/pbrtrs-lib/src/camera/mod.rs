@@ -0,0 +1,65 @@
+#![allow(dead_code)]
+
+pub mod lens;
+pub mod orthographic;
+pub mod perspective;
+pub mod projective;
+pub mod realistic;
+pub mod shutter;
+pub mod spherical;
+pub mod stereo;
+
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+
+// Everything a camera needs to turn a sample into a ray: a position on the
+// film in raster coordinates, a position on the lens in [0, 1)^2, and a time
+// sample in [0, 1) that the camera's shutter maps to a time in the exposure.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CameraSample {
+    pub p_film: Point2<f32>,
+    pub p_lens: Point2<f32>,
+    pub time: f32,
+}
+
+pub trait Camera: Send + Sync {
+    // Returns a world-space ray for the sample along with the weight of its
+    // radiance contribution to the film, or None if the sample does not
+    // produce a ray (e.g. it was blocked inside a lens system).
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)>;
+
+    // Like generate_ray, but also fills in rays offset by one pixel in x and
+    // y. The default implementation finds them by finite differences, trying
+    // a step in the other direction if the first step fails.
+    fn generate_ray_differential(
+        &self,
+        sample: &CameraSample,
+    ) -> Option<(RayDifferential<f32>, f32)> {
+        let (ray, weight) = self.generate_ray(sample)?;
+        let mut rd = RayDifferential::<f32>::new(ray);
+        let (o, d) = (ray.origin(), ray.dir());
+
+        let mut found = [false; 2];
+        for (axis, found) in found.iter_mut().enumerate() {
+            for &eps in &[0.05, -0.05] {
+                let mut shifted = *sample;
+                shifted.p_film[axis] += eps;
+                if let Some((r, _)) = self.generate_ray(&shifted) {
+                    let origin = o + (r.origin() - o) / eps;
+                    let dir = d + (r.dir() - d) / eps;
+                    if axis == 0 {
+                        rd.rx_origin = origin;
+                        rd.rx_dir = dir;
+                    } else {
+                        rd.ry_origin = origin;
+                        rd.ry_dir = dir;
+                    }
+                    *found = true;
+                    break;
+                }
+            }
+        }
+        rd.has_differentials = found[0] && found[1];
+        Some((rd, weight))
+    }
+}
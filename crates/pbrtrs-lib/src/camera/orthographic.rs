@@ -0,0 +1,154 @@
+use crate::camera::lens::*;
+use crate::camera::projective::*;
+use crate::camera::shutter::*;
+use crate::camera::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+use crate::geometry::transform::*;
+use crate::geometry::vector::*;
+
+// Parallel projection along +z in camera space. The screen window gives the
+// extent of the film in camera-space units.
+#[derive(Clone, Debug)]
+pub struct OrthographicCamera {
+    proj: ProjectiveCamera,
+    dx_camera: Vec3<f32>,
+    dy_camera: Vec3<f32>,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        film_resolution: Point2<i32>,
+        screen_window: Bounds2<f32>,
+        lens: ThinLens,
+    ) -> Self {
+        let proj = ProjectiveCamera::new(
+            camera_to_world,
+            Transform::<f32>::orthographic(0.0, 1.0),
+            screen_window,
+            film_resolution,
+            lens,
+        );
+        let origin = proj.raster_to_camera_point(&(0.0, 0.0).into());
+        let dx_camera = proj.raster_to_camera_point(&(1.0, 0.0).into()) - origin;
+        let dy_camera = proj.raster_to_camera_point(&(0.0, 1.0).into()) - origin;
+        Self {
+            proj,
+            dx_camera,
+            dy_camera,
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.proj.shutter = shutter;
+        self
+    }
+
+    pub fn projective(&self) -> &ProjectiveCamera {
+        &self.proj
+    }
+
+    fn camera_ray(&self, origin: Point3<f32>, p_lens: Point2<f32>) -> Ray<f32> {
+        let dir = Vec3::<f32>::elements(0.0, 0.0, 1.0);
+        if self.proj.lens.is_pinhole() {
+            Ray::<f32>::new(origin, dir)
+        } else {
+            self.proj.lens.focus(origin, dir, p_lens)
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)> {
+        let p_camera = self.proj.raster_to_camera_point(&sample.p_film);
+        let p_lens = self.proj.lens.sample_lens(sample.p_lens);
+        let mut ray = self.camera_ray(p_camera, p_lens);
+        ray.set_time(self.proj.sample_time(sample));
+        Some((self.proj.camera_to_world.apply_ray(&ray), 1.0))
+    }
+
+    fn generate_ray_differential(
+        &self,
+        sample: &CameraSample,
+    ) -> Option<(RayDifferential<f32>, f32)> {
+        let p_camera = self.proj.raster_to_camera_point(&sample.p_film);
+        let p_lens = self.proj.lens.sample_lens(sample.p_lens);
+        let mut ray = self.camera_ray(p_camera, p_lens);
+        ray.set_time(self.proj.sample_time(sample));
+        let rx = self.camera_ray(p_camera + self.dx_camera, p_lens);
+        let ry = self.camera_ray(p_camera + self.dy_camera, p_lens);
+
+        let rd = RayDifferential::<f32> {
+            ray,
+            has_differentials: true,
+            rx_origin: rx.origin(),
+            ry_origin: ry.origin(),
+            rx_dir: rx.dir(),
+            ry_dir: ry.dir(),
+        };
+        Some((self.proj.camera_to_world.apply_ray_differential(&rd), 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!(a.distance_to(&b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn sample(x: f32, y: f32) -> CameraSample {
+        CameraSample {
+            p_film: (x, y).into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parallel_rays() {
+        let res = Point2::<i32>::elements(100, 50);
+        let window = Bounds2::<f32>::new((-10.0, -5.0).into(), (10.0, 5.0).into());
+        let camera = OrthographicCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            window,
+            ThinLens::pinhole(),
+        );
+
+        let (ray, _) = camera.generate_ray(&sample(0.0, 0.0)).unwrap();
+        assert_close(ray.origin(), (-10.0, 5.0, 0.0).into());
+        assert_close(ray.dir(), (0.0, 0.0, 1.0).into());
+        let (ray, _) = camera.generate_ray(&sample(50.0, 25.0)).unwrap();
+        assert_close(ray.origin(), (0.0, 0.0, 0.0).into());
+
+        let (rd, _) = camera
+            .generate_ray_differential(&sample(50.0, 25.0))
+            .unwrap();
+        assert_close(rd.rx_origin, (0.2, 0.0, 0.0).into());
+        assert_close(rd.ry_origin, (0.0, -0.2, 0.0).into());
+        assert_close(rd.rx_dir, rd.ray.dir());
+    }
+
+    #[test]
+    fn test_depth_of_field() {
+        let res = Point2::<i32>::elements(16, 16);
+        let window = Bounds2::<f32>::new((-1.0, -1.0).into(), (1.0, 1.0).into());
+        let lens = ThinLens::new(0.25, 2.0, Aperture::Circular);
+        let camera = OrthographicCamera::new(Transform::<f32>::identity(), res, window, lens);
+
+        // The center of the lens sees along the pinhole ray.
+        let mut s = sample(4.0, 4.0);
+        s.p_lens = (0.5, 0.5).into();
+        let (pinhole, _) = camera.generate_ray(&s).unwrap();
+        let p_focus = pinhole.origin() + Vec3::<f32>::elements(0.0, 0.0, 2.0);
+        for &u in &[(0.2, 0.7), (0.9, 0.9), (0.0, 0.4)] {
+            s.p_lens = u.into();
+            let (ray, _) = camera.generate_ray(&s).unwrap();
+            let t = 2.0 / ray.dir().z();
+            assert_close(ray.at(t), p_focus);
+        }
+    }
+}
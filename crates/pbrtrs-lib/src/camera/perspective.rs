@@ -0,0 +1,259 @@
+use crate::camera::lens::*;
+use crate::camera::projective::*;
+use crate::camera::shutter::*;
+use crate::camera::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+use crate::geometry::transform::*;
+use crate::geometry::vector::*;
+
+// Perspective camera looking down +z in camera space. The field of view, in
+// degrees, spans the shorter axis of the screen window.
+#[derive(Clone, Debug)]
+pub struct PerspectiveCamera {
+    proj: ProjectiveCamera,
+    dx_camera: Vec3<f32>,
+    dy_camera: Vec3<f32>,
+}
+
+impl PerspectiveCamera {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        film_resolution: Point2<i32>,
+        screen_window: Bounds2<f32>,
+        fov: f32,
+        lens: ThinLens,
+    ) -> Self {
+        let proj = ProjectiveCamera::new(
+            camera_to_world,
+            Transform::<f32>::perspective(fov, 1e-2, 1000.0).unwrap(),
+            screen_window,
+            film_resolution,
+            lens,
+        );
+        let origin = proj.raster_to_camera_point(&(0.0, 0.0).into());
+        let dx_camera = proj.raster_to_camera_point(&(1.0, 0.0).into()) - origin;
+        let dy_camera = proj.raster_to_camera_point(&(0.0, 1.0).into()) - origin;
+        Self {
+            proj,
+            dx_camera,
+            dy_camera,
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.proj.shutter = shutter;
+        self
+    }
+
+    pub fn projective(&self) -> &ProjectiveCamera {
+        &self.proj
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)> {
+        let p_camera = self.proj.raster_to_camera_point(&sample.p_film);
+        let origin = Point3::<f32>::new(0.0);
+        let mut ray = if self.proj.lens.is_pinhole() {
+            Ray::<f32>::new(origin, p_camera.normalized())
+        } else {
+            let p_lens = self.proj.lens.sample_lens(sample.p_lens);
+            self.proj.lens.focus(origin, p_camera.normalized(), p_lens)
+        };
+        ray.set_time(self.proj.sample_time(sample));
+        Some((self.proj.camera_to_world.apply_ray(&ray), 1.0))
+    }
+
+    fn generate_ray_differential(
+        &self,
+        sample: &CameraSample,
+    ) -> Option<(RayDifferential<f32>, f32)> {
+        let p_camera = self.proj.raster_to_camera_point(&sample.p_film);
+        let origin = Point3::<f32>::new(0.0);
+        let dx_dir = (p_camera + self.dx_camera).normalized();
+        let dy_dir = (p_camera + self.dy_camera).normalized();
+
+        let lens = &self.proj.lens;
+        let (mut ray, rx, ry) = if lens.is_pinhole() {
+            (
+                Ray::<f32>::new(origin, p_camera.normalized()),
+                Ray::<f32>::new(origin, dx_dir),
+                Ray::<f32>::new(origin, dy_dir),
+            )
+        } else {
+            // The offset rays go through the same lens point, so they also
+            // converge on the plane of focus.
+            let p_lens = lens.sample_lens(sample.p_lens);
+            (
+                lens.focus(origin, p_camera.normalized(), p_lens),
+                lens.focus(origin, dx_dir, p_lens),
+                lens.focus(origin, dy_dir, p_lens),
+            )
+        };
+        ray.set_time(self.proj.sample_time(sample));
+
+        let rd = RayDifferential::<f32> {
+            ray,
+            has_differentials: true,
+            rx_origin: rx.origin(),
+            ry_origin: ry.origin(),
+            rx_dir: rx.dir(),
+            ry_dir: ry.dir(),
+        };
+        Some((self.proj.camera_to_world.apply_ray_differential(&rd), 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::projective::default_screen_window;
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!(a.distance_to(&b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn sample(x: f32, y: f32) -> CameraSample {
+        CameraSample {
+            p_film: (x, y).into(),
+            time: 0.25,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_directions() {
+        let res = Point2::<i32>::elements(100, 100);
+        let camera = PerspectiveCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            default_screen_window(res),
+            90.0,
+            ThinLens::pinhole(),
+        );
+
+        let (ray, weight) = camera.generate_ray(&sample(50.0, 50.0)).unwrap();
+        assert_eq!(weight, 1.0);
+        assert_eq!(ray.time(), 0.25);
+        assert_close(ray.dir(), (0.0, 0.0, 1.0).into());
+
+        // Raster y points down, so the top row looks up.
+        let (ray, _) = camera.generate_ray(&sample(50.0, 0.0)).unwrap();
+        assert_close(ray.dir(), Vec3::<f32>::elements(0.0, 1.0, 1.0).normalized());
+        let (ray, _) = camera.generate_ray(&sample(0.0, 50.0)).unwrap();
+        assert_close(
+            ray.dir(),
+            Vec3::<f32>::elements(-1.0, 0.0, 1.0).normalized(),
+        );
+    }
+
+    #[test]
+    fn test_camera_to_world() {
+        let res = Point2::<i32>::elements(200, 100);
+        let camera_to_world = Transform::<f32>::look_at(
+            (0.0, 0.0, 5.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+        )
+        .unwrap();
+        let camera = PerspectiveCamera::new(
+            camera_to_world,
+            res,
+            default_screen_window(res),
+            60.0,
+            ThinLens::pinhole(),
+        );
+
+        let (ray, _) = camera.generate_ray(&sample(100.0, 50.0)).unwrap();
+        assert_close(ray.origin(), (0.0, 0.0, 5.0).into());
+        assert_close(ray.dir(), (0.0, 0.0, -1.0).into());
+    }
+
+    #[test]
+    fn test_differentials_match_finite_differences() {
+        let res = Point2::<i32>::elements(64, 48);
+        let camera = PerspectiveCamera::new(
+            Transform::<f32>::rotate(20.0, (0.0, 1.0, 0.0).into()),
+            res,
+            default_screen_window(res),
+            45.0,
+            ThinLens::pinhole(),
+        );
+        let s = sample(10.0, 30.0);
+        let (rd, _) = camera.generate_ray_differential(&s).unwrap();
+        let (rx, _) = camera.generate_ray(&sample(11.0, 30.0)).unwrap();
+        let (ry, _) = camera.generate_ray(&sample(10.0, 31.0)).unwrap();
+        assert!(rd.has_differentials);
+        assert_close(rd.rx_dir, rx.dir());
+        assert_close(rd.ry_dir, ry.dir());
+    }
+
+    #[test]
+    fn test_shutter() {
+        let res = Point2::<i32>::elements(10, 100);
+        let camera = PerspectiveCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            default_screen_window(res),
+            60.0,
+            ThinLens::pinhole(),
+        )
+        .with_shutter(
+            Shutter::new(
+                1.0,
+                1.5,
+                ShutterCurve::Box,
+                ShutterMode::Rolling { readout_time: 0.2 },
+            )
+            .unwrap(),
+        );
+        let (top, _) = camera.generate_ray(&sample(5.0, 0.0)).unwrap();
+        let (bottom, _) = camera.generate_ray(&sample(5.0, 100.0)).unwrap();
+        assert!((top.time() - 1.125).abs() < 1e-6);
+        assert!((bottom.time() - 1.325).abs() < 1e-6);
+
+        let (rd, _) = camera
+            .generate_ray_differential(&sample(5.0, 50.0))
+            .unwrap();
+        assert!((rd.ray.time() - 1.225).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_depth_of_field() {
+        let res = Point2::<i32>::elements(32, 32);
+        let lens = ThinLens::new(0.2, 3.0, Aperture::Circular);
+        let camera = PerspectiveCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            default_screen_window(res),
+            60.0,
+            lens,
+        );
+
+        // All lens samples for one film point agree on the focal plane but
+        // spread out in front of it.
+        let mut s = sample(5.0, 20.0);
+        let (center, _) = camera.generate_ray_differential(&s).unwrap();
+        let t = 3.0 / center.ray.dir().z();
+        let p_focus = center.ray.at(t);
+        let mut spread = 0.0_f32;
+        for &u in &[(0.1, 0.1), (0.9, 0.2), (0.3, 0.8)] {
+            s.p_lens = u.into();
+            let (rd, _) = camera.generate_ray_differential(&s).unwrap();
+            let (ray, _) = camera.generate_ray(&s).unwrap();
+            assert_close(rd.ray.dir(), ray.dir());
+            assert_ne!(ray.origin(), Vec3::<f32>::new(0.0));
+
+            let t = (3.0 - ray.origin().z()) / ray.dir().z();
+            assert_close(ray.at(t), p_focus);
+            let t = (1.0 - ray.origin().z()) / ray.dir().z();
+            spread = spread.max(
+                ray.at(t)
+                    .distance_to(&center.ray.at(1.0 / center.ray.dir().z())),
+            );
+        }
+        assert!(spread > 0.01);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::camera::lens::*;
+use crate::camera::shutter::*;
+use crate::camera::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::transform::*;
+
+// Screen window that covers [-1, 1] along the shorter image axis and keeps
+// pixels square along the longer one.
+pub fn default_screen_window(film_resolution: Point2<i32>) -> Bounds2<f32> {
+    let aspect = film_resolution.x() as f32 / film_resolution.y() as f32;
+    if aspect > 1.0 {
+        Bounds2::<f32>::new((-aspect, -1.0).into(), (aspect, 1.0).into())
+    } else {
+        Bounds2::<f32>::new((-1.0, -1.0 / aspect).into(), (1.0, 1.0 / aspect).into())
+    }
+}
+
+// The transforms shared by cameras that are described by a 4x4 projection:
+// camera space is projected to screen space, the screen window is mapped to
+// the film's raster grid (with y pointing down), and the chain is inverted to
+// take raster positions back into camera space. Rays leave through a thin
+// lens, which is a pinhole unless a lens radius is set, while the shutter
+// is open.
+#[derive(Clone, Debug)]
+pub struct ProjectiveCamera {
+    pub camera_to_world: Transform<f32>,
+    pub camera_to_screen: Transform<f32>,
+    pub raster_to_camera: Transform<f32>,
+    pub screen_to_raster: Transform<f32>,
+    pub raster_to_screen: Transform<f32>,
+    pub lens: ThinLens,
+    pub shutter: Shutter,
+    pub film_resolution: Point2<i32>,
+}
+
+impl ProjectiveCamera {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        camera_to_screen: Transform<f32>,
+        screen_window: Bounds2<f32>,
+        film_resolution: Point2<i32>,
+        lens: ThinLens,
+    ) -> Self {
+        let (lo, hi) = (screen_window.p_min(), screen_window.p_max());
+        let screen_to_raster =
+            Transform::<f32>::scale(film_resolution.x() as f32, film_resolution.y() as f32, 1.0)
+                * Transform::<f32>::scale(1.0 / (hi.x() - lo.x()), 1.0 / (lo.y() - hi.y()), 1.0)
+                * Transform::<f32>::translate((-lo.x(), -hi.y(), 0.0).into());
+        let raster_to_screen = screen_to_raster.inverse();
+        let raster_to_camera = camera_to_screen.inverse() * raster_to_screen;
+        Self {
+            camera_to_world,
+            camera_to_screen,
+            raster_to_camera,
+            screen_to_raster,
+            raster_to_screen,
+            lens,
+            shutter: Shutter::default(),
+            film_resolution,
+        }
+    }
+
+    // Camera-space point on the near plane that a raster position maps to.
+    pub fn raster_to_camera_point(&self, p_film: &Point2<f32>) -> Point3<f32> {
+        self.raster_to_camera
+            .apply_point(&(p_film.x(), p_film.y(), 0.0).into())
+    }
+
+    pub fn sample_time(&self, sample: &CameraSample) -> f32 {
+        let film_y = sample.p_film.y() / self.film_resolution.y() as f32;
+        self.shutter.sample_time(sample.time, film_y)
+    }
+}
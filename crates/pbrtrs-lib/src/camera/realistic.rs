@@ -0,0 +1,587 @@
+use crate::camera::shutter::*;
+use crate::camera::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+use crate::geometry::transform::*;
+use crate::geometry::vector::*;
+use crate::sampler::lowdiscrepancy::radical_inverse;
+
+// One spherical interface of a lens system, in meters. A curvature radius
+// of zero marks the aperture stop; an index of refraction of zero means air.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LensElement {
+    pub curvature_radius: f32,
+    pub thickness: f32,
+    pub eta: f32,
+    pub aperture_radius: f32,
+}
+
+impl LensElement {
+    // Parses a lens prescription table with one interface per line, ordered
+    // from the scene side to the film side: curvature radius, thickness
+    // (distance to the next interface), index of refraction, and aperture
+    // diameter, all in millimeters. Blank lines and '#' comments are skipped.
+    pub fn parse_prescription(text: &str) -> Result<Vec<LensElement>, String> {
+        let mut elements = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            if values.len() != 4 {
+                return Err(format!(
+                    "line {}: expected 4 values, found {}",
+                    line_no + 1,
+                    values.len()
+                ));
+            }
+            elements.push(LensElement {
+                curvature_radius: values[0] / 1000.0,
+                thickness: values[1] / 1000.0,
+                eta: values[2],
+                aperture_radius: values[3] / 1000.0 / 2.0,
+            });
+        }
+        if elements.is_empty() {
+            return Err("lens prescription has no elements".to_string());
+        }
+        Ok(elements)
+    }
+
+    // Index of refraction of the medium on the film side of the interface.
+    fn medium_eta(&self) -> f32 {
+        if self.eta == 0.0 {
+            1.0
+        } else {
+            self.eta
+        }
+    }
+}
+
+// Number of radial intervals across the film over which exit pupil bounds
+// are precomputed.
+const EXIT_PUPIL_INTERVALS: usize = 64;
+
+// A camera that traces rays through a system of spherical lens elements, so
+// vignetting, distortion and defocus come from the lens design itself.
+// Camera space puts the film at z = 0 and the lens system along +z.
+#[derive(Clone, Debug)]
+pub struct RealisticCamera {
+    camera_to_world: Transform<f32>,
+    film_resolution: Point2<i32>,
+    film_extent: Bounds2<f32>,
+    film_diagonal: f32,
+    elements: Vec<LensElement>,
+    // Bounds of the exit pupil on the rear element plane for film points at
+    // increasing distance from the center, measured along +x.
+    exit_pupil_bounds: Vec<Bounds2<f32>>,
+    simple_weighting: bool,
+    shutter: Shutter,
+}
+
+impl RealisticCamera {
+    // film_diagonal is in millimeters. aperture_diameter, also in
+    // millimeters, stops the aperture down; it cannot open it beyond the
+    // prescription. The lens is moved so that focus_distance (meters) is
+    // sharp. With simple_weighting, ray weights only model vignetting and
+    // are 1 at the film center; otherwise they are radiometrically correct.
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        film_resolution: Point2<i32>,
+        film_diagonal: f32,
+        mut elements: Vec<LensElement>,
+        aperture_diameter: f32,
+        focus_distance: f32,
+        simple_weighting: bool,
+    ) -> Result<Self, String> {
+        if elements.is_empty() {
+            return Err("lens system has no elements".to_string());
+        }
+        for element in elements.iter_mut() {
+            if element.curvature_radius == 0.0 {
+                element.aperture_radius = element
+                    .aperture_radius
+                    .min(aperture_diameter / 1000.0 / 2.0);
+            }
+        }
+
+        let film_diagonal = film_diagonal / 1000.0;
+        let aspect = film_resolution.y() as f32 / film_resolution.x() as f32;
+        let x = (film_diagonal * film_diagonal / (1.0 + aspect * aspect)).sqrt();
+        let y = aspect * x;
+        let film_extent =
+            Bounds2::<f32>::new((-x / 2.0, -y / 2.0).into(), (x / 2.0, y / 2.0).into());
+
+        let mut camera = Self {
+            camera_to_world,
+            film_resolution,
+            film_extent,
+            film_diagonal,
+            elements,
+            exit_pupil_bounds: Vec::new(),
+            simple_weighting,
+            shutter: Shutter::default(),
+        };
+        let rear_thickness = camera.focus_thick_lens(focus_distance)?;
+        camera.elements.last_mut().unwrap().thickness = rear_thickness;
+
+        camera.exit_pupil_bounds = (0..EXIT_PUPIL_INTERVALS)
+            .map(|i| {
+                let r0 = i as f32 / EXIT_PUPIL_INTERVALS as f32 * film_diagonal / 2.0;
+                let r1 = (i + 1) as f32 / EXIT_PUPIL_INTERVALS as f32 * film_diagonal / 2.0;
+                camera.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+        Ok(camera)
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    fn lens_rear_z(&self) -> f32 {
+        self.elements.last().unwrap().thickness
+    }
+
+    fn lens_front_z(&self) -> f32 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_element_radius(&self) -> f32 {
+        self.elements.last().unwrap().aperture_radius
+    }
+
+    // Traces a camera-space ray leaving the film through the lens system and
+    // returns the camera-space ray that exits the front element, or None if
+    // it is blocked by an aperture or totally internally reflected.
+    pub fn trace_from_film(
+        &self,
+        o: Point3<f32>,
+        d: Vec3<f32>,
+    ) -> Option<(Point3<f32>, Vec3<f32>)> {
+        // Lens system space flips z so that the lens lies along -z.
+        let (mut o, mut d) = (flip_z(o), flip_z(d));
+        let mut element_z = 0.0;
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            element_z -= element.thickness;
+            let eta_t = if i > 0 {
+                self.elements[i - 1].medium_eta()
+            } else {
+                1.0
+            };
+            let (p, dir) = Self::interact(element, element_z, o, d, element.medium_eta(), eta_t)?;
+            o = p;
+            d = dir;
+        }
+        Some((flip_z(o), flip_z(d)))
+    }
+
+    // The reverse of trace_from_film, for rays entering the front element.
+    pub fn trace_from_scene(
+        &self,
+        o: Point3<f32>,
+        d: Vec3<f32>,
+    ) -> Option<(Point3<f32>, Vec3<f32>)> {
+        let (mut o, mut d) = (flip_z(o), flip_z(d));
+        let mut element_z = -self.lens_front_z();
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+            let eta_i = if i > 0 {
+                self.elements[i - 1].medium_eta()
+            } else {
+                1.0
+            };
+            let (p, dir) = Self::interact(element, element_z, o, d, eta_i, element.medium_eta())?;
+            o = p;
+            d = dir;
+            element_z += element.thickness;
+        }
+        Some((flip_z(o), flip_z(d)))
+    }
+
+    // Intersects the lens-space ray with the interface at element_z, checks
+    // the element's aperture, and refracts through it.
+    fn interact(
+        element: &LensElement,
+        element_z: f32,
+        o: Point3<f32>,
+        d: Vec3<f32>,
+        eta_i: f32,
+        eta_t: f32,
+    ) -> Option<(Point3<f32>, Vec3<f32>)> {
+        let is_stop = element.curvature_radius == 0.0;
+        let (t, n) = if is_stop {
+            ((element_z - o.z()) / d.z(), Vec3::<f32>::new(0.0))
+        } else {
+            let radius = element.curvature_radius;
+            intersect_spherical_element(radius, element_z + radius, o, d)?
+        };
+
+        let p_hit = o + d * t;
+        let r2 = p_hit.x() * p_hit.x() + p_hit.y() * p_hit.y();
+        if r2 > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+        if is_stop {
+            return Some((p_hit, d));
+        }
+        let wt = refract(&(-d).normalized(), &n, eta_i / eta_t)?;
+        Some((p_hit, wt))
+    }
+
+    // Finds the principal plane and focal point on one side of the lens
+    // from a ray entering parallel to the axis and the ray that leaves.
+    fn cardinal_points(o_in: Point3<f32>, o_out: Point3<f32>, d_out: Vec3<f32>) -> (f32, f32) {
+        let tf = -o_out.x() / d_out.x();
+        let fz = -(o_out + d_out * tf).z();
+        let tp = (o_in.x() - o_out.x()) / d_out.x();
+        let pz = -(o_out + d_out * tp).z();
+        (pz, fz)
+    }
+
+    fn thick_lens_approximation(&self) -> Result<([f32; 2], [f32; 2]), String> {
+        let x = 0.001 * self.film_diagonal;
+        let o_scene = Point3::<f32>::elements(x, 0.0, self.lens_front_z() + 1.0);
+        let (o, d) = self
+            .trace_from_scene(o_scene, (0.0, 0.0, -1.0).into())
+            .ok_or("paraxial ray from the scene side did not pass through the lens")?;
+        let (pz0, fz0) = Self::cardinal_points(o_scene, o, d);
+
+        let o_film = Point3::<f32>::elements(x, 0.0, self.lens_rear_z() - 1.0);
+        let (o, d) = self
+            .trace_from_film(o_film, (0.0, 0.0, 1.0).into())
+            .ok_or("paraxial ray from the film side did not pass through the lens")?;
+        let (pz1, fz1) = Self::cardinal_points(o_film, o, d);
+        Ok(([pz0, pz1], [fz0, fz1]))
+    }
+
+    // Distance between the rear element and the film that brings a point
+    // focus_distance in front of the film into focus, using a thick lens
+    // approximation of the system.
+    fn focus_thick_lens(&self, focus_distance: f32) -> Result<f32, String> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[0] - pz[0];
+        let z = -focus_distance;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        if c <= 0.0 {
+            return Err(format!("cannot focus the lens at {} m", focus_distance));
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+        Ok(self.lens_rear_z() + delta)
+    }
+
+    // Bounds on the rear element plane of the directions through which film
+    // points at radius r0..r1 along +x see out of the lens.
+    fn bound_exit_pupil(&self, r0: f32, r1: f32) -> Bounds2<f32> {
+        let rear = 1.5 * self.rear_element_radius();
+        let proj_rear = Bounds2::<f32>::new((-rear, -rear).into(), (rear, rear).into());
+
+        const SAMPLES: usize = 64 * 64;
+        let mut pupil = Bounds2::<f32>::default();
+        for i in 0..SAMPLES {
+            let s = (i as f32 + 0.5) / SAMPLES as f32;
+            let p_film = Point3::<f32>::elements(r0 + (r1 - r0) * s, 0.0, 0.0);
+            let (u0, u1) = (radical_inverse(0, i as u64), radical_inverse(1, i as u64));
+            let p_rear = Point2::<f32>::elements(-rear + 2.0 * rear * u0, -rear + 2.0 * rear * u1);
+            let p_rear3 = Point3::<f32>::elements(p_rear.x(), p_rear.y(), self.lens_rear_z());
+            if pupil.inside(&p_rear) || self.trace_from_film(p_film, p_rear3 - p_film).is_some() {
+                pupil = pupil.union_with_point(p_rear);
+            }
+        }
+        if pupil.is_empty() {
+            return proj_rear;
+        }
+        // Pad by the sample spacing to cover pupil area between samples.
+        let pad = 2.0 * proj_rear.diagonal().mag() / (SAMPLES as f32).sqrt();
+        let pad = Point2::<f32>::new(pad);
+        Bounds2::<f32>::new(pupil.p_min() - pad, pupil.p_max() + pad)
+    }
+
+    // Samples a point on the rear element plane within the exit pupil bounds
+    // for p_film, rotated to the film point's angle. Also returns the area of
+    // the bounds that were sampled.
+    fn sample_exit_pupil(&self, p_film: Point2<f32>, u: Point2<f32>) -> (Point3<f32>, f32) {
+        let r_film = p_film.mag();
+        let index = (r_film / (self.film_diagonal / 2.0) * EXIT_PUPIL_INTERVALS as f32) as usize;
+        let bounds = self.exit_pupil_bounds[index.min(EXIT_PUPIL_INTERVALS - 1)];
+        let (lo, hi) = (bounds.p_min(), bounds.p_max());
+        let p_lens = Point2::<f32>::elements(
+            lo.x() + (hi.x() - lo.x()) * u.x(),
+            lo.y() + (hi.y() - lo.y()) * u.y(),
+        );
+
+        let (sin_theta, cos_theta) = if r_film != 0.0 {
+            (p_film.y() / r_film, p_film.x() / r_film)
+        } else {
+            (0.0, 1.0)
+        };
+        let p = Point3::<f32>::elements(
+            cos_theta * p_lens.x() - sin_theta * p_lens.y(),
+            sin_theta * p_lens.x() + cos_theta * p_lens.y(),
+            self.lens_rear_z(),
+        );
+        (p, bounds.area())
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)> {
+        // The lens inverts the image, so film x is mirrored.
+        let s = Point2::<f32>::elements(
+            sample.p_film.x() / self.film_resolution.x() as f32,
+            sample.p_film.y() / self.film_resolution.y() as f32,
+        );
+        let (lo, hi) = (self.film_extent.p_min(), self.film_extent.p_max());
+        let p_film2 = Point2::<f32>::elements(
+            lo.x() + (hi.x() - lo.x()) * s.x(),
+            lo.y() + (hi.y() - lo.y()) * s.y(),
+        );
+        let p_film = Point3::<f32>::elements(-p_film2.x(), p_film2.y(), 0.0);
+
+        let (p_rear, pupil_area) = self.sample_exit_pupil(
+            Point2::<f32>::elements(p_film.x(), p_film.y()),
+            sample.p_lens,
+        );
+        let d_film = p_rear - p_film;
+        let (o, d) = self.trace_from_film(p_film, d_film)?;
+
+        let mut ray = Ray::<f32>::new(o, d.normalized());
+        ray.set_time(self.shutter.sample_time(sample.time, s.y()));
+        let ray = self.camera_to_world.apply_ray(&ray);
+
+        // Radiometric weight: cos^4 falloff times the solid angle of the
+        // sampled pupil region, which is where vignetting comes from, over
+        // the exposure time.
+        let cos_theta = d_film.normalized().z();
+        let cos4_theta = cos_theta * cos_theta * cos_theta * cos_theta;
+        let weight = if self.simple_weighting {
+            cos4_theta * pupil_area / self.exit_pupil_bounds[0].area()
+        } else {
+            self.shutter.duration() * cos4_theta * pupil_area
+                / (self.lens_rear_z() * self.lens_rear_z())
+        };
+        Some((ray, weight))
+    }
+}
+
+fn flip_z(v: Vec3<f32>) -> Vec3<f32> {
+    Vec3::<f32>::elements(v.x(), v.y(), -v.z())
+}
+
+// Intersects a ray with a sphere of the given signed radius centered on the
+// axis at z_center, choosing the hit on the lens surface (the cap facing the
+// direction the ray comes from). Returns the distance and a normal facing
+// back against the ray.
+fn intersect_spherical_element(
+    radius: f32,
+    z_center: f32,
+    o: Point3<f32>,
+    d: Vec3<f32>,
+) -> Option<(f32, Vec3<f32>)> {
+    let o_local = o - Vec3::<f32>::elements(0.0, 0.0, z_center);
+    let a = d.mag2();
+    let b = 2.0 * d.dot(&o_local);
+    let c = o_local.mag2() - radius * radius;
+    let discrim = b * b - 4.0 * a * c;
+    if discrim < 0.0 {
+        return None;
+    }
+    let root = discrim.sqrt();
+    let q = if b < 0.0 {
+        -0.5 * (b - root)
+    } else {
+        -0.5 * (b + root)
+    };
+    let (mut t0, mut t1) = (q / a, c / q);
+    if t0 > t1 {
+        std::mem::swap(&mut t0, &mut t1);
+    }
+
+    let use_closer = (d.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+    let n = (o_local + d * t)
+        .normalized()
+        .face_towards_same_hemisphere(&-d);
+    Some((t, n))
+}
+
+// Refracts the direction wi (pointing away from the surface) through a
+// surface with normal n and relative index of refraction eta = eta_i /
+// eta_t. Returns None on total internal reflection.
+fn refract(wi: &Vec3<f32>, n: &Vec3<f32>, eta: f32) -> Option<Vec3<f32>> {
+    let cos_theta_i = n.dot(wi);
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-*wi * eta + *n * (eta * cos_theta_i - cos_theta_t))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Double-Gauss F/2, US patent 2,673,491 (Tronnier), scaled to 50 mm.
+    const DGAUSS_50MM: &str = "
+        # radius  thickness  ior    aperture
+        29.475    3.76       1.67   25.2
+        84.83     0.12       1      25.2
+        19.275    4.025      1.67   23
+        40.77     3.275      1.699  23
+        12.75     5.705      1      18
+        0         4.5        0      17.1
+        -14.495   1.18       1.603  17
+        40.77     6.065      1.658  20
+        -20.385   0.19       1      20
+        437.065   3.22       1.717  20
+        -39.73    0          1      20
+    ";
+
+    fn camera(focus_distance: f32) -> RealisticCamera {
+        let elements = LensElement::parse_prescription(DGAUSS_50MM).unwrap();
+        camera_with(elements, focus_distance)
+    }
+
+    fn camera_with(elements: Vec<LensElement>, focus_distance: f32) -> RealisticCamera {
+        RealisticCamera::new(
+            Transform::<f32>::identity(),
+            (64, 64).into(),
+            35.0,
+            elements,
+            17.1,
+            focus_distance,
+            true,
+        )
+        .unwrap()
+    }
+
+    fn grid() -> impl Iterator<Item = Point2<f32>> {
+        (0..8).flat_map(|i| {
+            (0..8).map(move |j| {
+                Point2::<f32>::elements((i as f32 + 0.5) / 8.0, (j as f32 + 0.5) / 8.0)
+            })
+        })
+    }
+
+    #[test]
+    fn test_parse_prescription() {
+        let elements = LensElement::parse_prescription(DGAUSS_50MM).unwrap();
+        assert_eq!(elements.len(), 11);
+        assert_eq!(elements[5].curvature_radius, 0.0);
+        assert!((elements[0].aperture_radius - 0.0126).abs() < 1e-6);
+        assert!(LensElement::parse_prescription("1 2 3").is_err());
+        assert!(LensElement::parse_prescription("# nothing").is_err());
+        assert!(RealisticCamera::new(
+            Transform::<f32>::identity(),
+            (64, 64).into(),
+            35.0,
+            Vec::new(),
+            17.1,
+            2.0,
+            true,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_focus() {
+        // Rays from the film center that make it through the lens converge
+        // on the optical axis at the focus distance.
+        let focus_distance = 2.0;
+        let camera = camera(focus_distance);
+        let mut count = 0;
+        for u in grid() {
+            let sample = CameraSample {
+                p_film: (32.0, 32.0).into(),
+                p_lens: u,
+                time: 0.0,
+            };
+            if let Some((ray, weight)) = camera.generate_ray(&sample) {
+                assert!(weight > 0.0);
+                assert!(ray.dir().z() > 0.0);
+                let t = (focus_distance - ray.origin().z()) / ray.dir().z();
+                let p = ray.at(t);
+                assert!(p.x().hypot(p.y()) < 2e-3, "{:?}", p);
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn test_vignetting() {
+        let camera = camera(5.0);
+        let mean_weight = |p_film: Point2<f32>| {
+            grid()
+                .filter_map(|u| {
+                    let sample = CameraSample {
+                        p_film,
+                        p_lens: u,
+                        time: 0.0,
+                    };
+                    camera.generate_ray(&sample)
+                })
+                .map(|(_, w)| w)
+                .sum::<f32>()
+                / 64.0
+        };
+        let center = mean_weight((32.0, 32.0).into());
+        let corner = mean_weight((1.0, 1.0).into());
+        assert!(center > 0.0);
+        assert!(corner < 0.9 * center);
+    }
+
+    #[test]
+    fn test_air_gaps() {
+        // Writing the air gaps' index as zero changes nothing.
+        let with_air = camera(2.0);
+        let elements = LensElement::parse_prescription(DGAUSS_50MM)
+            .unwrap()
+            .into_iter()
+            .map(|e| LensElement {
+                eta: if e.eta == 1.0 { 0.0 } else { e.eta },
+                ..e
+            })
+            .collect();
+        let with_zero = camera_with(elements, 2.0);
+        assert_eq!(with_zero.lens_rear_z(), with_air.lens_rear_z());
+
+        let p_film = Point3::<f32>::elements(0.004, -0.002, 0.0);
+        let mut count = 0;
+        for u in grid() {
+            let (p_rear, _) =
+                with_zero.sample_exit_pupil(Point2::<f32>::elements(p_film.x(), p_film.y()), u);
+            let d = p_rear - p_film;
+            let (o, d_out) = match with_zero.trace_from_film(p_film, d) {
+                Some(ray) => ray,
+                None => continue,
+            };
+            assert_eq!(with_air.trace_from_film(p_film, d), Some((o, d_out)));
+
+            // Tracing back from the scene returns to the film point.
+            let (o_back, d_back) = with_zero.trace_from_scene(o + d_out * 0.1, -d_out).unwrap();
+            let t = -o_back.z() / d_back.z();
+            assert!((o_back + d_back * t).distance_to(&p_film) < 1e-5);
+            assert!(d_back.normalized().distance_to(&-d.normalized()) < 1e-4);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,186 @@
+// How the shutter's transmission varies over its open interval. Times are
+// drawn in proportion to the curve, so ray weights stay at one.
+#[derive(Clone, Debug)]
+pub enum ShutterCurve {
+    // Fully open for the whole interval.
+    Box,
+    // Opens linearly to a peak halfway through, then closes linearly.
+    Triangular,
+    // Piecewise-constant transmission over equal sub-intervals. The values
+    // need not be normalized.
+    Custom(Vec<f32>),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ShutterMode {
+    // Every pixel is exposed over the same interval.
+    Global,
+    // Rows are read out from the top of the film to the bottom, and each row
+    // starts its exposure readout_time * (row / height) after the first.
+    Rolling { readout_time: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Shutter {
+    open: f32,
+    close: f32,
+    curve: ShutterCurve,
+    mode: ShutterMode,
+    // Normalized CDF of a custom curve, with one more entry than values.
+    cdf: Vec<f32>,
+}
+
+impl Shutter {
+    // Returns None if the shutter closes before it opens or a custom curve
+    // has no positive values.
+    pub fn new(open: f32, close: f32, curve: ShutterCurve, mode: ShutterMode) -> Option<Self> {
+        if !open.is_finite() || !close.is_finite() || close < open {
+            return None;
+        }
+        let cdf = match &curve {
+            ShutterCurve::Custom(values) => {
+                let mut cdf = Vec::with_capacity(values.len() + 1);
+                cdf.push(0.0);
+                for v in values {
+                    cdf.push(cdf.last().unwrap() + v.max(0.0));
+                }
+                let sum = *cdf.last().unwrap();
+                if !(sum > 0.0 && sum.is_finite()) {
+                    return None;
+                }
+                cdf.iter_mut().for_each(|c| *c /= sum);
+                cdf
+            }
+            _ => Vec::new(),
+        };
+        Some(Self {
+            open,
+            close,
+            curve,
+            mode,
+            cdf,
+        })
+    }
+
+    pub fn open(&self) -> f32 {
+        self.open
+    }
+
+    pub fn close(&self) -> f32 {
+        self.close
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.close - self.open
+    }
+
+    // Maps a sample u in [0, 1) to a fraction of the exposure distributed
+    // according to the shutter curve.
+    fn sample_curve(&self, u: f32) -> f32 {
+        match &self.curve {
+            ShutterCurve::Box => u,
+            ShutterCurve::Triangular => {
+                if u < 0.5 {
+                    (u / 2.0).sqrt()
+                } else {
+                    1.0 - ((1.0 - u) / 2.0).sqrt()
+                }
+            }
+            ShutterCurve::Custom(_) => {
+                let n = self.cdf.len() - 1;
+                let i = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+                let width = self.cdf[i + 1] - self.cdf[i];
+                let du = if width > 0.0 {
+                    (u - self.cdf[i]) / width
+                } else {
+                    0.0
+                };
+                (i as f32 + du) / n as f32
+            }
+        }
+    }
+
+    // Time at which a ray is traced for sample u and a film position whose
+    // raster row, divided by the film height, is film_y.
+    pub fn sample_time(&self, u: f32, film_y: f32) -> f32 {
+        let offset = match self.mode {
+            ShutterMode::Global => 0.0,
+            ShutterMode::Rolling { readout_time } => readout_time * film_y.clamp(0.0, 1.0),
+        };
+        self.open + offset + self.sample_curve(u) * self.duration()
+    }
+}
+
+impl Default for Shutter {
+    // Open over [0, 1], so sample times pass through unchanged.
+    fn default() -> Self {
+        Self::new(0.0, 1.0, ShutterCurve::Box, ShutterMode::Global).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn times(shutter: &Shutter, film_y: f32) -> Vec<f32> {
+        (0..1000)
+            .map(|i| shutter.sample_time((i as f32 + 0.5) / 1000.0, film_y))
+            .collect()
+    }
+
+    #[test]
+    fn test_box() {
+        let shutter = Shutter::new(2.0, 4.0, ShutterCurve::Box, ShutterMode::Global).unwrap();
+        assert_eq!(shutter.sample_time(0.0, 0.3), 2.0);
+        assert_eq!(shutter.sample_time(0.5, 0.3), 3.0);
+        assert_eq!(Shutter::default().sample_time(0.25, 0.9), 0.25);
+    }
+
+    #[test]
+    fn test_triangular() {
+        let shutter =
+            Shutter::new(0.0, 1.0, ShutterCurve::Triangular, ShutterMode::Global).unwrap();
+        let t = times(&shutter, 0.0);
+        let mean = t.iter().sum::<f32>() / t.len() as f32;
+        assert!((mean - 0.5).abs() < 1e-3);
+        // A triangle puts 3/4 of its area in the middle half.
+        let middle = t.iter().filter(|&&t| (0.25..0.75).contains(&t)).count();
+        assert!((middle as f32 / 1000.0 - 0.75).abs() < 0.01);
+        assert!(t.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_custom() {
+        let shutter = Shutter::new(
+            0.0,
+            1.0,
+            ShutterCurve::Custom(vec![0.0, 1.0, 3.0, 0.0]),
+            ShutterMode::Global,
+        )
+        .unwrap();
+        let t = times(&shutter, 0.0);
+        assert!(t.iter().all(|&t| (0.25..=0.75).contains(&t)));
+        let late = t.iter().filter(|&&t| t >= 0.5).count();
+        assert!((late as f32 / 1000.0 - 0.75).abs() < 0.01);
+
+        let zero = ShutterCurve::Custom(vec![0.0, -1.0]);
+        assert!(Shutter::new(0.0, 1.0, zero, ShutterMode::Global).is_none());
+        let empty = ShutterCurve::Custom(Vec::new());
+        assert!(Shutter::new(0.0, 1.0, empty, ShutterMode::Global).is_none());
+        assert!(Shutter::new(1.0, 0.5, ShutterCurve::Box, ShutterMode::Global).is_none());
+    }
+
+    #[test]
+    fn test_rolling() {
+        let shutter = Shutter::new(
+            0.0,
+            0.1,
+            ShutterCurve::Box,
+            ShutterMode::Rolling { readout_time: 1.0 },
+        )
+        .unwrap();
+        assert_eq!(shutter.sample_time(0.0, 0.0), 0.0);
+        assert_eq!(shutter.sample_time(0.0, 0.5), 0.5);
+        assert!((shutter.sample_time(1.0, 1.0) - 1.1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::camera::shutter::*;
+use crate::camera::*;
+use crate::geometry::ray::*;
+use crate::geometry::transform::*;
+use crate::geometry::vector::*;
+use crate::sampling::warp::*;
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SphericalMapping {
+    // Latitude/longitude layout: x spans 360 degrees of azimuth and y spans
+    // 180 degrees from straight up to straight down.
+    EquiRect,
+    // Equal-area octahedral layout of the whole sphere in a square image.
+    Octahedral,
+}
+
+// Captures the full sphere of directions around the camera position, e.g.
+// for environment maps and 360-degree VR review. +y in camera space is up.
+#[derive(Clone, Debug)]
+pub struct SphericalCamera {
+    camera_to_world: Transform<f32>,
+    film_resolution: Point2<i32>,
+    mapping: SphericalMapping,
+    shutter: Shutter,
+}
+
+impl SphericalCamera {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        film_resolution: Point2<i32>,
+        mapping: SphericalMapping,
+    ) -> Self {
+        Self {
+            camera_to_world,
+            film_resolution,
+            mapping,
+            shutter: Shutter::default(),
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn mapping(&self) -> SphericalMapping {
+        self.mapping
+    }
+
+    // Camera-space direction seen at a raster position.
+    pub fn film_to_direction(&self, p_film: &Point2<f32>) -> Vec3<f32> {
+        let u = p_film.x() / self.film_resolution.x() as f32;
+        let v = p_film.y() / self.film_resolution.y() as f32;
+        match self.mapping {
+            SphericalMapping::EquiRect => {
+                let (sin_theta, cos_theta) = (PI * v).sin_cos();
+                let (sin_phi, cos_phi) = (2.0 * PI * u).sin_cos();
+                Vec3::<f32>::elements(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi)
+            }
+            SphericalMapping::Octahedral => {
+                let d = equal_area_square_to_sphere(wrap_equal_area_square((u, v).into()));
+                Vec3::<f32>::elements(d.x(), d.z(), d.y())
+            }
+        }
+    }
+}
+
+impl Camera for SphericalCamera {
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)> {
+        let dir = self.film_to_direction(&sample.p_film);
+        let mut ray = Ray::<f32>::new(Vec3::<f32>::new(0.0), dir);
+        let film_y = sample.p_film.y() / self.film_resolution.y() as f32;
+        ray.set_time(self.shutter.sample_time(sample.time, film_y));
+        Some((self.camera_to_world.apply_ray(&ray), 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!(a.distance_to(&b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_equirect() {
+        let res = Point2::<i32>::elements(200, 100);
+        let camera = SphericalCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            SphericalMapping::EquiRect,
+        );
+        assert_close(
+            camera.film_to_direction(&(0.0, 0.0).into()),
+            (0.0, 1.0, 0.0).into(),
+        );
+        assert_close(
+            camera.film_to_direction(&(0.0, 50.0).into()),
+            (1.0, 0.0, 0.0).into(),
+        );
+        assert_close(
+            camera.film_to_direction(&(50.0, 50.0).into()),
+            (0.0, 0.0, 1.0).into(),
+        );
+        assert_close(
+            camera.film_to_direction(&(100.0, 100.0).into()),
+            (0.0, -1.0, 0.0).into(),
+        );
+    }
+
+    #[test]
+    fn test_octahedral() {
+        let res = Point2::<i32>::elements(64, 64);
+        let camera = SphericalCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            SphericalMapping::Octahedral,
+        );
+        let sample = CameraSample {
+            p_film: (32.0, 32.0).into(),
+            ..Default::default()
+        };
+        let (ray, _) = camera.generate_ray(&sample).unwrap();
+        assert_close(ray.dir(), (0.0, 1.0, 0.0).into());
+
+        // Differentials come from finite differences and stay on the sphere.
+        let (rd, _) = camera.generate_ray_differential(&sample).unwrap();
+        assert!(rd.has_differentials);
+        assert!((rd.rx_dir.mag() - 1.0).abs() < 0.1);
+        assert!(rd.rx_dir.distance_to(&rd.ray.dir()) > 0.0);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::camera::lens::*;
+use crate::camera::perspective::*;
+use crate::camera::shutter::*;
+use crate::camera::spherical::*;
+use crate::camera::*;
+use crate::geometry::aabb::*;
+use crate::geometry::ray::*;
+use crate::geometry::transform::*;
+use crate::geometry::vector::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    // Side of the rig the eye sits on along camera-space x.
+    fn sign(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Convergence {
+    // Parallel view axes with the screen windows shifted towards each other,
+    // so the images meet at the convergence distance without keystoning.
+    OffAxis,
+    // Each eye is rotated to look at the convergence point.
+    ToedIn,
+}
+
+// A pair of perspective cameras separated by the interocular distance. The
+// rig's own camera space sits halfway between the eyes, looking down +z;
+// objects at the convergence distance appear at zero parallax.
+#[derive(Copy, Clone, Debug)]
+pub struct StereoRig {
+    camera_to_world: Transform<f32>,
+    interocular: f32,
+    convergence_distance: f32,
+    convergence: Convergence,
+}
+
+impl StereoRig {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        interocular: f32,
+        convergence_distance: f32,
+        convergence: Convergence,
+    ) -> Self {
+        Self {
+            camera_to_world,
+            interocular,
+            convergence_distance,
+            convergence,
+        }
+    }
+
+    // Camera-to-world transform of a single eye.
+    pub fn eye_to_world(&self, eye: Eye) -> Transform<f32> {
+        let offset = eye.sign() * self.interocular / 2.0;
+        let translate = Transform::<f32>::translate((offset, 0.0, 0.0).into());
+        match self.convergence {
+            Convergence::OffAxis => self.camera_to_world * translate,
+            Convergence::ToedIn => {
+                let angle = (-offset / self.convergence_distance).atan().to_degrees();
+                let rotate = Transform::<f32>::rotate(angle, (0.0, 1.0, 0.0).into());
+                self.camera_to_world * translate * rotate
+            }
+        }
+    }
+
+    // Screen window of one eye. Off-axis eyes shift the window so the
+    // point straight ahead at the convergence distance lands in the center.
+    pub fn eye_screen_window(
+        &self,
+        eye: Eye,
+        screen_window: Bounds2<f32>,
+        fov: f32,
+    ) -> Bounds2<f32> {
+        match self.convergence {
+            Convergence::OffAxis => {
+                let offset = eye.sign() * self.interocular / 2.0;
+                let tan_half_fov = (fov.to_radians() / 2.0).tan();
+                let shift = -offset / self.convergence_distance / tan_half_fov;
+                let shift = Point2::<f32>::elements(shift, 0.0);
+                Bounds2::<f32>::new(screen_window.p_min() + shift, screen_window.p_max() + shift)
+            }
+            Convergence::ToedIn => screen_window,
+        }
+    }
+
+    pub fn perspective_camera(
+        &self,
+        eye: Eye,
+        film_resolution: Point2<i32>,
+        screen_window: Bounds2<f32>,
+        fov: f32,
+        lens: ThinLens,
+    ) -> PerspectiveCamera {
+        PerspectiveCamera::new(
+            self.eye_to_world(eye),
+            film_resolution,
+            self.eye_screen_window(eye, screen_window, fov),
+            fov,
+            lens,
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    // The whole film shows one eye.
+    Single(Eye),
+    // The top half of the film shows the left eye, the bottom half the right.
+    TopBottom,
+}
+
+// Omni-directional stereo for equirectangular VR output. Every column of the
+// image is seen from a different viewpoint on a circle whose diameter is the
+// interocular distance, so that any horizontal viewing direction gets
+// correct parallax.
+#[derive(Clone, Debug)]
+pub struct OdsCamera {
+    camera_to_world: Transform<f32>,
+    film_resolution: Point2<i32>,
+    interocular: f32,
+    layout: StereoLayout,
+    shutter: Shutter,
+}
+
+impl OdsCamera {
+    pub fn new(
+        camera_to_world: Transform<f32>,
+        film_resolution: Point2<i32>,
+        interocular: f32,
+        layout: StereoLayout,
+    ) -> Self {
+        Self {
+            camera_to_world,
+            film_resolution,
+            interocular,
+            layout,
+            shutter: Shutter::default(),
+        }
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for OdsCamera {
+    fn generate_ray(&self, sample: &CameraSample) -> Option<(Ray<f32>, f32)> {
+        let (width, height) = (self.film_resolution.x(), self.film_resolution.y());
+        let (eye, p_film, eye_height) = match self.layout {
+            StereoLayout::Single(eye) => (eye, sample.p_film, height),
+            StereoLayout::TopBottom => {
+                let half = height / 2;
+                if sample.p_film.y() < half as f32 {
+                    (Eye::Left, sample.p_film, half)
+                } else {
+                    let p = sample.p_film - Point2::<f32>::elements(0.0, half as f32);
+                    (Eye::Right, p, half)
+                }
+            }
+        };
+
+        let equirect = SphericalCamera::new(
+            Transform::<f32>::identity(),
+            (width, eye_height).into(),
+            SphericalMapping::EquiRect,
+        );
+        let dir = equirect.film_to_direction(&p_film);
+
+        // Offset the viewpoint perpendicular to the horizontal direction.
+        let horizontal = Vec2::<f32>::elements(dir.x(), dir.z());
+        let origin = if horizontal.mag2() > 0.0 {
+            let h = horizontal.normalized();
+            let r = eye.sign() * self.interocular / 2.0;
+            Point3::<f32>::elements(h.y() * r, 0.0, -h.x() * r)
+        } else {
+            Point3::<f32>::new(0.0)
+        };
+
+        let mut ray = Ray::<f32>::new(origin, dir);
+        ray.set_time(
+            self.shutter
+                .sample_time(sample.time, sample.p_film.y() / height as f32),
+        );
+        Some((self.camera_to_world.apply_ray(&ray), 1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera::projective::default_screen_window;
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!(a.distance_to(&b) < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn sample(x: f32, y: f32) -> CameraSample {
+        CameraSample {
+            p_film: (x, y).into(),
+            ..Default::default()
+        }
+    }
+
+    fn check_convergence(convergence: Convergence) {
+        let rig = StereoRig::new(Transform::<f32>::identity(), 0.064, 2.0, convergence);
+        let res = Point2::<i32>::elements(64, 64);
+        let convergence_point = Vec3::<f32>::elements(0.0, 0.0, 2.0);
+        for &eye in &[Eye::Left, Eye::Right] {
+            let camera = rig.perspective_camera(
+                eye,
+                res,
+                default_screen_window(res),
+                60.0,
+                ThinLens::pinhole(),
+            );
+            let (ray, _) = camera.generate_ray(&sample(32.0, 32.0)).unwrap();
+            assert_close(ray.origin(), (eye.sign() * 0.032, 0.0, 0.0).into());
+            let t = (2.0 - ray.origin().z()) / ray.dir().z();
+            assert_close(ray.at(t), convergence_point);
+        }
+    }
+
+    #[test]
+    fn test_off_axis() {
+        check_convergence(Convergence::OffAxis);
+        // Off-axis eyes keep parallel view axes.
+        let rig = StereoRig::new(
+            Transform::<f32>::identity(),
+            0.064,
+            2.0,
+            Convergence::OffAxis,
+        );
+        let forward = Vec3::<f32>::elements(0.0, 0.0, 1.0);
+        assert_close(rig.eye_to_world(Eye::Left).apply_vector(&forward), forward);
+    }
+
+    #[test]
+    fn test_toed_in() {
+        check_convergence(Convergence::ToedIn);
+    }
+
+    #[test]
+    fn test_ods() {
+        let res = Point2::<i32>::elements(360, 360);
+        let camera = OdsCamera::new(
+            Transform::<f32>::identity(),
+            res,
+            0.064,
+            StereoLayout::TopBottom,
+        );
+        // Looking down +z from each eye.
+        let (left, _) = camera.generate_ray(&sample(90.0, 90.0)).unwrap();
+        assert_close(left.dir(), (0.0, 0.0, 1.0).into());
+        assert_close(left.origin(), (-0.032, 0.0, 0.0).into());
+        let (right, _) = camera.generate_ray(&sample(90.0, 270.0)).unwrap();
+        assert_close(right.dir(), (0.0, 0.0, 1.0).into());
+        assert_close(right.origin(), (0.032, 0.0, 0.0).into());
+
+        // Looking down +x, the left eye sits towards +z.
+        let (left, _) = camera.generate_ray(&sample(0.0, 90.0)).unwrap();
+        assert_close(left.dir(), (1.0, 0.0, 0.0).into());
+        assert_close(left.origin(), (0.0, 0.0, 0.032).into());
+    }
+}
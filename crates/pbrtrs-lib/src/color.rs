@@ -0,0 +1,273 @@
+#![allow(dead_code)]
+
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Sub};
+
+// A linear RGB triple. Which primaries and white point the values refer to
+// depends on context; the film works in its output color space.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Rgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Rgb {
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn splat(v: f32) -> Self {
+        Self::new(v, v, v)
+    }
+
+    pub fn max_component(&self) -> f32 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    pub fn average(&self) -> f32 {
+        (self.r + self.g + self.b) / 3.0
+    }
+
+    pub fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+        Self::new(f(self.r), f(self.g), f(self.b))
+    }
+}
+
+impl From<[f32; 3]> for Rgb {
+    fn from(v: [f32; 3]) -> Self {
+        Self::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Rgb> for [f32; 3] {
+    fn from(c: Rgb) -> Self {
+        [c.r, c.g, c.b]
+    }
+}
+
+impl Index<usize> for Rgb {
+    type Output = f32;
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.r,
+            1 => &self.g,
+            2 => &self.b,
+            _ => panic!("Rgb index {} out of range", i),
+        }
+    }
+}
+
+impl IndexMut<usize> for Rgb {
+    fn index_mut(&mut self, i: usize) -> &mut f32 {
+        match i {
+            0 => &mut self.r,
+            1 => &mut self.g,
+            2 => &mut self.b,
+            _ => panic!("Rgb index {} out of range", i),
+        }
+    }
+}
+
+impl Add for Rgb {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        Self::new(self.r + o.r, self.g + o.g, self.b + o.b)
+    }
+}
+
+impl AddAssign for Rgb {
+    fn add_assign(&mut self, o: Self) {
+        *self = *self + o;
+    }
+}
+
+impl Sub for Rgb {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        Self::new(self.r - o.r, self.g - o.g, self.b - o.b)
+    }
+}
+
+// Component-wise product.
+impl Mul for Rgb {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self::new(self.r * o.r, self.g * o.g, self.b * o.b)
+    }
+}
+
+impl Mul<f32> for Rgb {
+    type Output = Self;
+    fn mul(self, s: f32) -> Self {
+        Self::new(self.r * s, self.g * s, self.b * s)
+    }
+}
+
+impl MulAssign<f32> for Rgb {
+    fn mul_assign(&mut self, s: f32) {
+        *self = *self * s;
+    }
+}
+
+impl Div<f32> for Rgb {
+    type Output = Self;
+    fn div(self, s: f32) -> Self {
+        Self::new(self.r / s, self.g / s, self.b / s)
+    }
+}
+
+// The sRGB transfer function, from linear values to the nonlinear encoding
+// used by 8-bit image formats and displays.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// CIE 1931 tristimulus values, where Y is luminance.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Xyz {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Xyz {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    // The color with chromaticity (x, y) and luminance y_lum.
+    pub fn from_xy(xy: [f32; 2], y_lum: f32) -> Self {
+        if xy[1] == 0.0 {
+            return Self::default();
+        }
+        Self::new(
+            xy[0] * y_lum / xy[1],
+            y_lum,
+            (1.0 - xy[0] - xy[1]) * y_lum / xy[1],
+        )
+    }
+
+    // Chromaticity coordinates.
+    pub fn xy(&self) -> [f32; 2] {
+        let sum = self.x + self.y + self.z;
+        [self.x / sum, self.y / sum]
+    }
+}
+
+impl From<[f32; 3]> for Xyz {
+    fn from(v: [f32; 3]) -> Self {
+        Self::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Xyz> for [f32; 3] {
+    fn from(c: Xyz) -> Self {
+        [c.x, c.y, c.z]
+    }
+}
+
+// A 3x3 matrix for linear maps between color spaces.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Matrix3x3 {
+    m: [[f32; 3]; 3],
+}
+
+impl Matrix3x3 {
+    pub fn new(m: [[f32; 3]; 3]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::diagonal([1.0; 3])
+    }
+
+    pub fn diagonal(d: [f32; 3]) -> Self {
+        let mut m = [[0.0; 3]; 3];
+        for i in 0..3 {
+            m[i][i] = d[i];
+        }
+        Self { m }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.m[row][col]
+    }
+
+    pub fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        let mut r = [0.0; 3];
+        for (i, r) in r.iter_mut().enumerate() {
+            *r = (0..3).map(|j| self.m[i][j] * v[j]).sum();
+        }
+        r
+    }
+
+    pub fn mul(&self, o: &Matrix3x3) -> Matrix3x3 {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| self.m[i][k] * o.m[k][j]).sum();
+            }
+        }
+        Matrix3x3 { m }
+    }
+
+    pub fn transpose(&self) -> Matrix3x3 {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.m[j][i];
+            }
+        }
+        Matrix3x3 { m }
+    }
+
+    // None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Matrix3x3> {
+        let m = &self.m;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] as f64 * m[r1][c1] as f64 - m[r0][c1] as f64 * m[r1][c0] as f64
+        };
+        let c = [
+            [
+                cofactor(1, 2, 1, 2),
+                -cofactor(1, 2, 0, 2),
+                cofactor(1, 2, 0, 1),
+            ],
+            [
+                -cofactor(0, 2, 1, 2),
+                cofactor(0, 2, 0, 2),
+                -cofactor(0, 2, 0, 1),
+            ],
+            [
+                cofactor(0, 1, 1, 2),
+                -cofactor(0, 1, 0, 2),
+                cofactor(0, 1, 0, 1),
+            ],
+        ];
+        let det: f64 = (0..3).map(|j| m[0][j] as f64 * c[0][j]).sum();
+        if det == 0.0 {
+            return None;
+        }
+        // The inverse is the transposed cofactor matrix over the
+        // determinant.
+        let mut inv = [[0.0; 3]; 3];
+        for (i, row) in inv.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (c[j][i] / det) as f32;
+            }
+        }
+        Some(Matrix3x3 { m: inv })
+    }
+}
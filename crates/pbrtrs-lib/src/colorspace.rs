@@ -0,0 +1,228 @@
+#![allow(dead_code)]
+
+use crate::color::*;
+use crate::spectrum::*;
+
+// Chromaticities of common white points.
+pub const WHITE_D50: [f32; 2] = [0.3457, 0.3585];
+pub const WHITE_D65: [f32; 2] = [0.3127, 0.3290];
+pub const WHITE_ACES: [f32; 2] = [0.321_68, 0.337_67];
+// The equal-energy white.
+pub const WHITE_E: [f32; 2] = [1.0 / 3.0, 1.0 / 3.0];
+
+// The names accepted by RgbColorSpace::named.
+pub const COLOR_SPACE_NAMES: [&str; 5] = ["srgb", "rec2020", "dci-p3", "aces2065-1", "acescg"];
+
+// A linear RGB space given by the chromaticities of its primaries and white
+// point; (1, 1, 1) is the white point at unit luminance.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RgbColorSpace {
+    name: String,
+    primaries: [[f32; 2]; 3],
+    white: [f32; 2],
+    xyz_from_rgb: Matrix3x3,
+    rgb_from_xyz: Matrix3x3,
+}
+
+impl RgbColorSpace {
+    // Returns None if the primaries are collinear or the white has no
+    // luminance.
+    pub fn new(name: &str, r: [f32; 2], g: [f32; 2], b: [f32; 2], white: [f32; 2]) -> Option<Self> {
+        // Scale each primary so that together they add up to the white.
+        let (r_xyz, g_xyz, b_xyz) = (
+            Xyz::from_xy(r, 1.0),
+            Xyz::from_xy(g, 1.0),
+            Xyz::from_xy(b, 1.0),
+        );
+        let rgb = Matrix3x3::new([
+            [r_xyz.x, g_xyz.x, b_xyz.x],
+            [r_xyz.y, g_xyz.y, b_xyz.y],
+            [r_xyz.z, g_xyz.z, b_xyz.z],
+        ]);
+        let scale = rgb.inverse()?.apply(Xyz::from_xy(white, 1.0).into());
+        let xyz_from_rgb = rgb.mul(&Matrix3x3::diagonal(scale));
+        Some(Self {
+            name: name.to_string(),
+            primaries: [r, g, b],
+            white,
+            xyz_from_rgb,
+            rgb_from_xyz: xyz_from_rgb.inverse()?,
+        })
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        let (r, g, b, white) = match name.to_ascii_lowercase().as_str() {
+            "srgb" => ([0.64, 0.33], [0.30, 0.60], [0.15, 0.06], WHITE_D65),
+            "rec2020" => ([0.708, 0.292], [0.170, 0.797], [0.131, 0.046], WHITE_D65),
+            "dci-p3" => ([0.680, 0.320], [0.265, 0.690], [0.150, 0.060], WHITE_D65),
+            "aces2065-1" => ([0.7347, 0.2653], [0.0, 1.0], [0.0001, -0.077], WHITE_ACES),
+            "acescg" => ([0.713, 0.293], [0.165, 0.830], [0.128, 0.044], WHITE_ACES),
+            _ => return None,
+        };
+        Self::new(&name.to_ascii_lowercase(), r, g, b, white)
+    }
+
+    pub fn srgb() -> Self {
+        Self::named("srgb").unwrap()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn white(&self) -> [f32; 2] {
+        self.white
+    }
+
+    pub fn xyz_from_rgb(&self) -> Matrix3x3 {
+        self.xyz_from_rgb
+    }
+
+    pub fn rgb_from_xyz(&self) -> Matrix3x3 {
+        self.rgb_from_xyz
+    }
+
+    pub fn to_rgb(&self, xyz: Xyz) -> Rgb {
+        self.rgb_from_xyz.apply(xyz.into()).into()
+    }
+
+    pub fn to_xyz(&self, rgb: Rgb) -> Xyz {
+        self.xyz_from_rgb.apply(rgb.into()).into()
+    }
+
+    pub fn luminance(&self, rgb: Rgb) -> f32 {
+        self.to_xyz(rgb).y
+    }
+
+    // Maps RGB values in other to this space, without adapting the white.
+    pub fn from_space(&self, other: &RgbColorSpace) -> Matrix3x3 {
+        self.rgb_from_xyz.mul(&other.xyz_from_rgb)
+    }
+}
+
+// The Bradford cone response matrix used for chromatic adaptation.
+const LMS_FROM_XYZ: [[f32; 3]; 3] = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+// Von Kries chromatic adaptation: scales cone responses so that colors seen
+// under a light with white src_white look as they would under dst_white.
+// Applies to XYZ values. Returns None unless both whites have positive
+// cone responses.
+pub fn white_balance(src_white: [f32; 2], dst_white: [f32; 2]) -> Option<Matrix3x3> {
+    let lms_from_xyz = Matrix3x3::new(LMS_FROM_XYZ);
+    let src = lms_from_xyz.apply(Xyz::from_xy(src_white, 1.0).into());
+    let dst = lms_from_xyz.apply(Xyz::from_xy(dst_white, 1.0).into());
+    if !src.iter().chain(&dst).all(|&v| v > 0.0 && v.is_finite()) {
+        return None;
+    }
+    let scale = Matrix3x3::diagonal([dst[0] / src[0], dst[1] / src[1], dst[2] / src[2]]);
+    Some(
+        lms_from_xyz
+            .inverse()
+            .unwrap()
+            .mul(&scale)
+            .mul(&lms_from_xyz),
+    )
+}
+
+// The color of a spectrum, scaled so that a constant spectrum of one has
+// unit luminance.
+pub fn spectrum_to_xyz(s: &dyn Spectrum) -> Xyz {
+    let y_integral = integrate_visible(cie_y);
+    Xyz::new(
+        integrate_visible(|l| cie_x(l) * s.evaluate(l)) / y_integral,
+        integrate_visible(|l| cie_y(l) * s.evaluate(l)) / y_integral,
+        integrate_visible(|l| cie_z(l) * s.evaluate(l)) / y_integral,
+    )
+}
+
+// The white of a blackbody at temperature t (in kelvin).
+pub fn blackbody_white(t: f32) -> [f32; 2] {
+    spectrum_to_xyz(&BlackbodySpectrum::new(t)).xy()
+}
+
+// The white of CIE daylight at correlated color temperature cct, from 4000
+// to 25000 kelvin; e.g. 6504 gives D65.
+pub fn daylight_white(cct: f32) -> [f32; 2] {
+    let t = cct.clamp(4000.0, 25000.0) as f64;
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 7000.0 {
+        -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+    } else {
+        -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+    };
+    let y = -3.0 * x * x + 2.87 * x - 0.275;
+    [x as f32, y as f32]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3], eps: f32) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < eps, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_srgb() {
+        let srgb = RgbColorSpace::srgb();
+        // The standard sRGB matrix.
+        let m = srgb.xyz_from_rgb();
+        assert_close(
+            [m.get(0, 0), m.get(0, 1), m.get(0, 2)],
+            [0.4124, 0.3576, 0.1805],
+            1e-3,
+        );
+        assert_close(
+            [m.get(1, 0), m.get(1, 1), m.get(1, 2)],
+            [0.2126, 0.7152, 0.0722],
+            1e-3,
+        );
+        assert_close(
+            srgb.to_xyz(Rgb::splat(1.0)).into(),
+            [0.9505, 1.0, 1.089],
+            1e-3,
+        );
+        assert!((srgb.luminance(Rgb::new(0.0, 1.0, 0.0)) - 0.7152).abs() < 1e-3);
+
+        for name in &COLOR_SPACE_NAMES {
+            let cs = RgbColorSpace::named(name).unwrap();
+            let c = Rgb::new(0.2, 0.5, 0.9);
+            let back: [f32; 3] = cs.to_rgb(cs.to_xyz(c)).into();
+            assert_close(back, c.into(), 1e-5);
+            let round = cs.from_space(&srgb).mul(&srgb.from_space(&cs));
+            assert_close(round.apply([1.0, 2.0, 3.0]), [1.0, 2.0, 3.0], 1e-4);
+        }
+        assert!(RgbColorSpace::named("cmyk").is_none());
+        let collinear = RgbColorSpace::new("line", [0.1, 0.1], [0.2, 0.2], [0.3, 0.3], WHITE_D65);
+        assert!(collinear.is_none());
+    }
+
+    #[test]
+    fn test_white_balance() {
+        let wb = white_balance(WHITE_E, WHITE_D65).unwrap();
+        let white: [f32; 3] = Xyz::from_xy(WHITE_D65, 1.0).into();
+        assert_close(wb.apply(Xyz::from_xy(WHITE_E, 1.0).into()), white, 1e-5);
+        let same = white_balance(WHITE_D50, WHITE_D50).unwrap();
+        assert_close(same.apply([0.3, 0.6, 0.9]), [0.3, 0.6, 0.9], 1e-5);
+        assert!(white_balance([0.3, 0.0], WHITE_D65).is_none());
+        assert!(white_balance([f32::NAN, 0.3], WHITE_D65).is_none());
+    }
+
+    #[test]
+    fn test_illuminant_whites() {
+        let d65 = daylight_white(6504.0);
+        assert!((d65[0] - 0.3127).abs() < 1e-3 && (d65[1] - 0.3290).abs() < 1e-3);
+        // D65 lies slightly above the Planckian locus, towards green.
+        let b = blackbody_white(6500.0);
+        assert!((b[0] - 0.3135).abs() < 3e-3 && (b[1] - 0.3236).abs() < 3e-3);
+        let e = spectrum_to_xyz(&ConstantSpectrum(1.0));
+        assert!((e.y - 1.0).abs() < 1e-5);
+        assert!((e.xy()[0] - 1.0 / 3.0).abs() < 5e-3);
+    }
+}
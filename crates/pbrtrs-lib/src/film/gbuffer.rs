@@ -0,0 +1,657 @@
+use crate::color::*;
+use crate::film::rgb::*;
+use crate::film::*;
+use crate::filter::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+use crate::image::exr::{self, PixelType, WriteOptions};
+use crate::image::*;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// An arbitrary output variable: something besides radiance recorded per
+// pixel for compositing.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Aov {
+    // The fraction of samples whose camera ray hit something.
+    Alpha,
+    // Camera-space z of the first intersection.
+    Depth,
+    Position,
+    // Geometric and shading normals.
+    Normal,
+    ShadingNormal,
+    Albedo,
+    // At most MAX_ID.
+    ObjectId,
+    MaterialId,
+    // Radiance from one light, indexed as in the film's light names. Only
+    // GBufferFilm::with_lights adds these.
+    Light(usize),
+}
+
+impl Aov {
+    fn n_components(self) -> usize {
+        match self {
+            Aov::Alpha | Aov::Depth | Aov::ObjectId | Aov::MaterialId => 1,
+            _ => 3,
+        }
+    }
+
+    // Full-precision storage for values that half floats would round.
+    fn pixel_type(self) -> PixelType {
+        match self {
+            Aov::Depth | Aov::Position => PixelType::Float,
+            Aov::ObjectId | Aov::MaterialId => PixelType::Uint,
+            _ => PixelType::Half,
+        }
+    }
+}
+
+// The largest object or material ID the film can record: IDs pass through
+// f32 images, which hold integers exactly only up to 2^24.
+pub const MAX_ID: u32 = 1 << 24;
+
+// How an AOV's samples are combined into a pixel value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AovFilter {
+    // Weighted by the camera sample's filter weight, like the beauty pass.
+    Filtered,
+    // A plain average, for values where the negative lobes of some filters
+    // make no sense.
+    Box,
+    // The sample nearest the pixel center, for values such as IDs that must
+    // not be blended.
+    Closest,
+}
+
+// What the camera ray saw at its first intersection.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FirstHit {
+    pub position: Point3<f32>,
+    pub depth: f32,
+    pub normal: Vec3<f32>,
+    pub shading_normal: Vec3<f32>,
+    pub albedo: Rgb,
+    // Set through with_ids, which keeps them within MAX_ID.
+    object_id: u32,
+    material_id: u32,
+}
+
+impl FirstHit {
+    // Returns None if either ID is above MAX_ID.
+    pub fn with_ids(mut self, object_id: u32, material_id: u32) -> Option<Self> {
+        if object_id > MAX_ID || material_id > MAX_ID {
+            return None;
+        }
+        self.object_id = object_id;
+        self.material_id = material_id;
+        Some(self)
+    }
+
+    pub fn object_id(&self) -> u32 {
+        self.object_id
+    }
+
+    pub fn material_id(&self) -> u32 {
+        self.material_id
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GBufferSample<'a> {
+    pub rgb: Rgb,
+    // None if the camera ray escaped; such samples only count towards the
+    // beauty, alpha and light channels.
+    pub hit: Option<FirstHit>,
+    // One entry per light name of the film.
+    pub light_contributions: &'a [Rgb],
+}
+
+// Where each AOV's accumulators live among a pixel's f64 values. Each AOV
+// has one auxiliary value (weight sum, sample count, or squared distance of
+// the closest sample) followed by its components.
+#[derive(Clone, Debug)]
+struct AovLayout {
+    aovs: Vec<(Aov, AovFilter, usize)>,
+    stride: usize,
+}
+
+impl AovLayout {
+    fn new(aovs: &[(Aov, AovFilter)]) -> Self {
+        let mut offset = 0;
+        let aovs = aovs
+            .iter()
+            .map(|&(aov, filter)| {
+                let entry = (aov, filter, offset);
+                offset += 1 + aov.n_components();
+                entry
+            })
+            .collect();
+        Self {
+            aovs,
+            stride: offset,
+        }
+    }
+
+    fn empty_pixels(&self, n: usize) -> Vec<f64> {
+        let mut values = vec![0.0; n * self.stride];
+        for pixel in values.chunks_mut(self.stride.max(1)) {
+            for &(_, filter, offset) in &self.aovs {
+                if filter == AovFilter::Closest {
+                    pixel[offset] = f64::INFINITY;
+                }
+            }
+        }
+        values
+    }
+
+    // d2 is the squared distance of the sample from the pixel center.
+    fn add(&self, pixel: &mut [f64], sample: &GBufferSample, d2: f32, weight: f32) {
+        for &(aov, filter, offset) in &self.aovs {
+            let v = match aov_value(aov, sample) {
+                Some(v) => v,
+                None => continue,
+            };
+            let (aux, values) = pixel[offset..=offset + aov.n_components()]
+                .split_first_mut()
+                .unwrap();
+            match filter {
+                AovFilter::Filtered | AovFilter::Box => {
+                    let w = if filter == AovFilter::Box {
+                        1.0
+                    } else {
+                        weight
+                    };
+                    *aux += w as f64;
+                    for (s, v) in values.iter_mut().zip(&v) {
+                        *s += (v * w) as f64;
+                    }
+                }
+                AovFilter::Closest => {
+                    if (d2 as f64) < *aux {
+                        *aux = d2 as f64;
+                        for (s, v) in values.iter_mut().zip(&v) {
+                            *s = *v as f64;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn merge(&self, pixel: &mut [f64], other: &[f64]) {
+        for &(aov, filter, offset) in &self.aovs {
+            let range = offset..=offset + aov.n_components();
+            match filter {
+                AovFilter::Closest => {
+                    if other[offset] < pixel[offset] {
+                        pixel[range.clone()].copy_from_slice(&other[range]);
+                    }
+                }
+                _ => {
+                    for i in range {
+                        pixel[i] += other[i];
+                    }
+                }
+            }
+        }
+    }
+
+    // The pixel's value of the AOV; zero if no sample contributed.
+    fn resolve(&self, pixel: &[f64], index: usize) -> [f32; 3] {
+        let (aov, filter, offset) = self.aovs[index];
+        let mut v = [0.0; 3];
+        let aux = pixel[offset];
+        for (c, v) in v.iter_mut().enumerate().take(aov.n_components()) {
+            let s = pixel[offset + 1 + c];
+            *v = match filter {
+                AovFilter::Closest if aux.is_finite() => s as f32,
+                AovFilter::Filtered | AovFilter::Box if aux != 0.0 => (s / aux) as f32,
+                _ => 0.0,
+            };
+        }
+        v
+    }
+}
+
+fn aov_value(aov: Aov, sample: &GBufferSample) -> Option<[f32; 3]> {
+    let vec3 = |v: Vec3<f32>| [v.x(), v.y(), v.z()];
+    let id = |id: u32| [id as f32, 0.0, 0.0];
+    let rgb = |c: Rgb| [c.r, c.g, c.b];
+    let hit = sample.hit.as_ref();
+    match aov {
+        Aov::Alpha => Some([if hit.is_some() { 1.0 } else { 0.0 }, 0.0, 0.0]),
+        Aov::Depth => hit.map(|h| [h.depth, 0.0, 0.0]),
+        Aov::Position => hit.map(|h| vec3(h.position)),
+        Aov::Normal => hit.map(|h| vec3(h.normal)),
+        Aov::ShadingNormal => hit.map(|h| vec3(h.shading_normal)),
+        Aov::Albedo => hit.map(|h| rgb(h.albedo)),
+        Aov::ObjectId => hit.map(|h| id(h.object_id)),
+        Aov::MaterialId => hit.map(|h| id(h.material_id)),
+        Aov::Light(i) => Some(rgb(sample.light_contributions.get(i).cloned()?)),
+    }
+}
+
+// A film that records AOVs from each camera ray's first intersection next
+// to the beauty pass, for output as a multi-layer EXR. The beauty pass,
+// including splats, is an ordinary RgbFilm.
+pub struct GBufferFilm {
+    rgb: RgbFilm,
+    light_names: Vec<String>,
+    requested: Vec<(Aov, AovFilter)>,
+    layout: AovLayout,
+    pixels: Mutex<Vec<f64>>,
+}
+
+impl GBufferFilm {
+    // Records alpha, depth, position, both normals, albedo and the IDs.
+    // Depth and IDs take the sample closest to the pixel center; the rest
+    // are box filtered.
+    pub fn new(
+        full_resolution: Point2<i32>,
+        crop_window: Bounds2<f32>,
+        filter: Arc<dyn Filter>,
+    ) -> Self {
+        let requested = vec![
+            (Aov::Alpha, AovFilter::Filtered),
+            (Aov::Depth, AovFilter::Closest),
+            (Aov::Position, AovFilter::Box),
+            (Aov::Normal, AovFilter::Box),
+            (Aov::ShadingNormal, AovFilter::Box),
+            (Aov::Albedo, AovFilter::Box),
+            (Aov::ObjectId, AovFilter::Closest),
+            (Aov::MaterialId, AovFilter::Closest),
+        ];
+        Self {
+            rgb: RgbFilm::new(full_resolution, crop_window, filter),
+            light_names: Vec::new(),
+            requested,
+            layout: AovLayout::new(&[]),
+            pixels: Mutex::new(Vec::new()),
+        }
+        .rebuild()
+    }
+
+    pub fn with_max_component_value(mut self, max_component_value: f32) -> Self {
+        self.rgb = self.rgb.with_max_component_value(max_component_value);
+        self
+    }
+
+    // Records the AOV with the given filtering, replacing any earlier
+    // setting for it. Light AOVs go through with_lights instead.
+    pub fn with_aov(mut self, aov: Aov, filter: AovFilter) -> Self {
+        assert!(
+            !matches!(aov, Aov::Light(_)),
+            "light AOVs are added with with_lights"
+        );
+        match self.requested.iter_mut().find(|(a, _)| *a == aov) {
+            Some(entry) => entry.1 = filter,
+            None => self.requested.push((aov, filter)),
+        }
+        self.rebuild()
+    }
+
+    pub fn without_aov(mut self, aov: Aov) -> Self {
+        self.requested.retain(|(a, _)| *a != aov);
+        self.rebuild()
+    }
+
+    // Records one RGB layer per light, named light.<name>.
+    pub fn with_lights(mut self, names: &[&str], filter: AovFilter) -> Self {
+        self.requested.retain(|(a, _)| !matches!(a, Aov::Light(_)));
+        self.light_names = names.iter().map(|n| n.to_string()).collect();
+        for i in 0..names.len() {
+            self.requested.push((Aov::Light(i), filter));
+        }
+        self.rebuild()
+    }
+
+    fn rebuild(mut self) -> Self {
+        self.layout = AovLayout::new(&self.requested);
+        let n = pixel_count(&self.rgb.pixel_bounds());
+        self.pixels = Mutex::new(self.layout.empty_pixels(n));
+        self
+    }
+
+    pub fn full_resolution(&self) -> Point2<i32> {
+        self.rgb.full_resolution()
+    }
+
+    pub fn pixel_bounds(&self) -> Bounds2<i32> {
+        self.rgb.pixel_bounds()
+    }
+
+    pub fn filter(&self) -> &dyn Filter {
+        self.rgb.filter()
+    }
+
+    pub fn rgb_film(&self) -> &RgbFilm {
+        &self.rgb
+    }
+
+    // p_film is the sample's raster position, which the Closest filter
+    // compares to the pixel center.
+    pub fn add_sample(
+        &self,
+        pixel: Point2<i32>,
+        p_film: Point2<f32>,
+        sample: &GBufferSample,
+        weight: f32,
+    ) {
+        self.rgb.add_sample(pixel, sample.rgb, weight);
+        if let Some(i) = pixel_offset(&self.pixel_bounds(), pixel) {
+            let stride = self.layout.stride;
+            let d2 = center_distance2(pixel, p_film);
+            let mut pixels = self.pixels.lock().unwrap();
+            self.layout.add(
+                &mut pixels[i * stride..(i + 1) * stride],
+                sample,
+                d2,
+                weight,
+            );
+        }
+    }
+
+    pub fn add_splat(&self, p: Point2<f32>, rgb: Rgb) {
+        self.rgb.add_splat(p, rgb);
+    }
+
+    pub fn tile(&self, bounds: Bounds2<i32>) -> GBufferTile {
+        let rgb = self.rgb.tile(bounds);
+        let n = pixel_count(&rgb.bounds());
+        GBufferTile {
+            layout: self.layout.clone(),
+            aovs: self.layout.empty_pixels(n),
+            rgb,
+        }
+    }
+
+    pub fn merge_tile(&self, tile: GBufferTile) {
+        let stride = self.layout.stride;
+        {
+            let mut pixels = self.pixels.lock().unwrap();
+            for (p, tile_pixel) in super::pixels(&tile.rgb.bounds()).zip(tile.aovs.chunks(stride)) {
+                if let Some(i) = pixel_offset(&self.pixel_bounds(), p) {
+                    self.layout
+                        .merge(&mut pixels[i * stride..(i + 1) * stride], tile_pixel);
+                }
+            }
+        }
+        self.rgb.merge_tile(tile.rgb);
+    }
+
+    fn channel_names(&self, aov: Aov) -> Vec<String> {
+        let xyz = |layer: &str| {
+            vec![
+                format!("{}.X", layer),
+                format!("{}.Y", layer),
+                format!("{}.Z", layer),
+            ]
+        };
+        let rgb = |layer: &str| {
+            vec![
+                format!("{}.R", layer),
+                format!("{}.G", layer),
+                format!("{}.B", layer),
+            ]
+        };
+        match aov {
+            Aov::Alpha => vec!["A".into()],
+            Aov::Depth => vec!["Z".into()],
+            Aov::Position => xyz("P"),
+            Aov::Normal => xyz("N"),
+            Aov::ShadingNormal => xyz("Ns"),
+            Aov::Albedo => rgb("albedo"),
+            Aov::ObjectId => vec!["objectId".into()],
+            Aov::MaterialId => vec!["materialId".into()],
+            Aov::Light(i) => rgb(&format!("light.{}", self.light_names[i])),
+        }
+    }
+
+    // The beauty pass as R, G, B followed by every recorded AOV.
+    pub fn get_image(&self, splat_scale: f32) -> Image {
+        let mut names = vec!["R".to_string(), "G".to_string(), "B".to_string()];
+        for &(aov, _, _) in &self.layout.aovs {
+            names.extend(self.channel_names(aov));
+        }
+        let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+        let bounds = self.pixel_bounds();
+        let d = bounds.diagonal();
+        let mut image = Image::new((d.x(), d.y()).into(), &names);
+
+        let stride = self.layout.stride;
+        let pixels = self.pixels.lock().unwrap();
+        for (i, p) in super::pixels(&bounds).enumerate() {
+            let pixel = &pixels[i * stride..(i + 1) * stride];
+            let q = p - bounds.p_min();
+            image.set_rgb(q, self.rgb.get_pixel_rgb(p, splat_scale));
+            let mut c = 3;
+            for (index, &(aov, _, _)) in self.layout.aovs.iter().enumerate() {
+                let v = self.layout.resolve(pixel, index);
+                for v in v.iter().take(aov.n_components()) {
+                    image.set_channel(q, c, *v);
+                    c += 1;
+                }
+            }
+        }
+        image
+    }
+
+    // EXR options that keep depth and positions at full float precision and
+    // IDs as integers.
+    pub fn exr_options(&self) -> WriteOptions {
+        let mut options = WriteOptions::default();
+        for &(aov, _, _) in &self.layout.aovs {
+            for name in self.channel_names(aov) {
+                options = options.with_channel_type(&name, aov.pixel_type());
+            }
+        }
+        options
+    }
+
+    // Writes all channels to an EXR file, or the beauty pass and alpha to
+    // any other format.
+    pub fn write_image(
+        &self,
+        path: &Path,
+        metadata: &ImageMetadata,
+        splat_scale: f32,
+    ) -> io::Result<()> {
+        let image = self.get_image(splat_scale);
+        if extension(path) == "exr" {
+            let mut w = BufWriter::new(std::fs::File::create(path)?);
+            return exr::write(&image, metadata, &self.exr_options(), &mut w);
+        }
+        let beauty = image
+            .select_channels(&["R", "G", "B", "A"])
+            .or_else(|| image.select_channels(&["R", "G", "B"]))
+            .unwrap();
+        beauty.write(path, metadata)
+    }
+}
+
+fn center_distance2(pixel: Point2<i32>, p_film: Point2<f32>) -> f32 {
+    let dx = p_film.x() - (pixel.x() as f32 + 0.5);
+    let dy = p_film.y() - (pixel.y() as f32 + 0.5);
+    dx * dx + dy * dy
+}
+
+// A G-buffer counterpart of FilmTile.
+pub struct GBufferTile {
+    layout: AovLayout,
+    aovs: Vec<f64>,
+    rgb: FilmTile,
+}
+
+impl GBufferTile {
+    pub fn bounds(&self) -> Bounds2<i32> {
+        self.rgb.bounds()
+    }
+
+    pub fn add_sample(
+        &mut self,
+        pixel: Point2<i32>,
+        p_film: Point2<f32>,
+        sample: &GBufferSample,
+        weight: f32,
+    ) {
+        self.rgb.add_sample(pixel, sample.rgb, weight);
+        if let Some(i) = pixel_offset(&self.rgb.bounds(), pixel) {
+            let stride = self.layout.stride;
+            let d2 = center_distance2(pixel, p_film);
+            self.layout.add(
+                &mut self.aovs[i * stride..(i + 1) * stride],
+                sample,
+                d2,
+                weight,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+
+    fn film() -> GBufferFilm {
+        let window = Bounds2::<f32>::new((0.0, 0.0).into(), (1.0, 1.0).into());
+        GBufferFilm::new((4, 3).into(), window, Arc::new(BoxFilter::default()))
+    }
+
+    fn hit(depth: f32, id: u32) -> FirstHit {
+        FirstHit {
+            position: Point3::<f32>::elements(1.0, 2.0, depth),
+            depth,
+            normal: Vec3::<f32>::elements(0.0, 0.0, 1.0),
+            shading_normal: Vec3::<f32>::elements(0.0, 1.0, 0.0),
+            albedo: Rgb::splat(depth / 10.0),
+            ..FirstHit::default()
+        }
+        .with_ids(id, id + 1)
+        .unwrap()
+    }
+
+    fn channel(image: &Image, p: (i32, i32), name: &str) -> f32 {
+        image.get_channel(p.into(), image.channel_index(name).unwrap())
+    }
+
+    #[test]
+    fn test_filtering() {
+        let film = film()
+            .with_aov(Aov::Albedo, AovFilter::Filtered)
+            .with_lights(&["key", "fill"], AovFilter::Filtered);
+        let lights = [Rgb::splat(1.0), Rgb::splat(2.0)];
+        let sample = |hit, rgb| GBufferSample {
+            rgb,
+            hit,
+            light_contributions: &lights,
+        };
+        let pixel = (1, 2).into();
+        film.add_sample(
+            pixel,
+            (1.6, 2.5).into(),
+            &sample(Some(hit(4.0, 7)), Rgb::splat(3.0)),
+            3.0,
+        );
+        film.add_sample(
+            pixel,
+            (1.3, 2.9).into(),
+            &sample(Some(hit(8.0, 9)), Rgb::splat(3.0)),
+            1.0,
+        );
+        film.add_sample(
+            pixel,
+            (1.5, 2.5).into(),
+            &sample(None, Rgb::splat(7.0)),
+            4.0,
+        );
+
+        let image = film.get_image(1.0);
+        assert_eq!(image.get_rgb(pixel), Rgb::splat(5.0));
+        assert_eq!(channel(&image, (1, 2), "A"), 0.5);
+        // Closest to the center, ignoring the escaped sample.
+        assert_eq!(channel(&image, (1, 2), "Z"), 4.0);
+        assert_eq!(channel(&image, (1, 2), "objectId"), 7.0);
+        assert_eq!(channel(&image, (1, 2), "materialId"), 8.0);
+        // Box filtered regardless of the weights.
+        assert_eq!(channel(&image, (1, 2), "P.Z"), 6.0);
+        assert_eq!(channel(&image, (1, 2), "Ns.Y"), 1.0);
+        // Filtered by weight.
+        assert_eq!(channel(&image, (1, 2), "albedo.G"), 0.5);
+        assert_eq!(channel(&image, (1, 2), "light.fill.B"), 2.0);
+        // Untouched pixels are zero.
+        assert_eq!(channel(&image, (0, 0), "Z"), 0.0);
+        assert_eq!(channel(&image, (0, 0), "A"), 0.0);
+
+        let film = film.without_aov(Aov::Position);
+        assert!(film.get_image(1.0).channel_index("P.X").is_none());
+    }
+
+    #[test]
+    fn test_tiles() {
+        let direct = film();
+        let tiled = film();
+        let mut tile = tiled.tile(tiled.pixel_bounds());
+        for (i, p) in pixels(&direct.pixel_bounds()).enumerate() {
+            for k in 0..3 {
+                let sample = GBufferSample {
+                    rgb: Rgb::splat(k as f32),
+                    hit: Some(hit((i * 3 + k) as f32, k as u32)),
+                    light_contributions: &[],
+                };
+                let p_film = Point2::<f32>::elements(p.x() as f32 + 0.1 * k as f32, p.y() as f32);
+                direct.add_sample(p, p_film, &sample, 1.0);
+                tile.add_sample(p, p_film, &sample, 1.0);
+            }
+        }
+        tiled.merge_tile(tile);
+        assert_eq!(direct.get_image(1.0), tiled.get_image(1.0));
+    }
+
+    #[test]
+    fn test_exr_layers() {
+        let film = film().with_lights(&["sun"], AovFilter::Filtered);
+        let sample = GBufferSample {
+            rgb: Rgb::new(0.5, 0.25, 1.0),
+            hit: Some(hit(1234.567, MAX_ID - 1)),
+            light_contributions: &[Rgb::splat(0.5)],
+        };
+        film.add_sample((3, 1).into(), (3.5, 1.5).into(), &sample, 1.0);
+
+        let mut buf = Vec::new();
+        let image = film.get_image(1.0);
+        exr::write(
+            &image,
+            &ImageMetadata::default(),
+            &film.exr_options(),
+            &mut buf,
+        )
+        .unwrap();
+        let (read_back, _) = exr::read(&mut &buf[..]).unwrap();
+        assert_eq!(read_back.n_channels(), image.n_channels());
+        assert_eq!(channel(&read_back, (3, 1), "Z"), 1234.567);
+        assert_eq!(channel(&read_back, (3, 1), "objectId"), (MAX_ID - 1) as f32);
+        assert_eq!(channel(&read_back, (3, 1), "materialId"), MAX_ID as f32);
+        assert_eq!(channel(&read_back, (3, 1), "light.sun.G"), 0.5);
+        assert_eq!(read_back.get_rgb((3, 1).into()), sample.rgb);
+    }
+
+    #[test]
+    fn test_id_limit() {
+        let hit = FirstHit::default();
+        assert_eq!(hit.with_ids(MAX_ID, 0).unwrap().object_id(), MAX_ID);
+        assert!(hit.with_ids(MAX_ID + 1, 0).is_none());
+        assert_eq!(hit.with_ids(0, MAX_ID).unwrap().material_id(), MAX_ID);
+        assert!(hit.with_ids(0, MAX_ID + 1).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_light_aov() {
+        film().with_aov(Aov::Light(0), AovFilter::Box);
+    }
+}
@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+
+pub mod gbuffer;
+pub mod progressive;
+pub mod rgb;
+pub mod spectral;
+
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Pixels covered by a crop window given in [0, 1]^2 over the full image.
+// Pixel bounds are half-open: p_max is one past the last pixel.
+pub fn crop_pixel_bounds(full_resolution: Point2<i32>, crop_window: Bounds2<f32>) -> Bounds2<i32> {
+    let (lo, hi) = (crop_window.p_min(), crop_window.p_max());
+    let (w, h) = (full_resolution.x() as f32, full_resolution.y() as f32);
+    Bounds2::<i32>::new(
+        ((w * lo.x()).ceil() as i32, (h * lo.y()).ceil() as i32).into(),
+        ((w * hi.x()).ceil() as i32, (h * hi.y()).ceil() as i32).into(),
+    )
+}
+
+// Row-major index of pixel p within half-open bounds, or None outside.
+pub fn pixel_offset(bounds: &Bounds2<i32>, p: Point2<i32>) -> Option<usize> {
+    let (lo, hi) = (bounds.p_min(), bounds.p_max());
+    if p.x() < lo.x() || p.y() < lo.y() || p.x() >= hi.x() || p.y() >= hi.y() {
+        return None;
+    }
+    let width = (hi.x() - lo.x()) as usize;
+    Some((p.y() - lo.y()) as usize * width + (p.x() - lo.x()) as usize)
+}
+
+// Number of pixels within half-open bounds.
+pub fn pixel_count(bounds: &Bounds2<i32>) -> usize {
+    let d = bounds.diagonal();
+    (d.x().max(0) * d.y().max(0)) as usize
+}
+
+// Iterates over the pixels of half-open bounds in row-major order.
+pub fn pixels(bounds: &Bounds2<i32>) -> impl Iterator<Item = Point2<i32>> {
+    let (lo, hi) = (bounds.p_min(), bounds.p_max());
+    (lo.y()..hi.y()).flat_map(move |y| (lo.x()..hi.x()).map(move |x| Point2::<i32>::elements(x, y)))
+}
+
+// An f64 that many threads can add to without a lock.
+#[derive(Debug, Default)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub fn new(v: f64) -> Self {
+        Self(AtomicU64::new(v.to_bits()))
+    }
+
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, v: f64) {
+        self.0.store(v.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, v: f64) {
+        let mut old = self.0.load(Ordering::Relaxed);
+        loop {
+            let new = (f64::from_bits(old) + v).to_bits();
+            match self
+                .0
+                .compare_exchange_weak(old, new, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => old = current,
+            }
+        }
+    }
+}
+
+// Running mean and variance of a stream of values, updated one value at a
+// time with Welford's algorithm, which stays accurate where the naive sum
+// of squares would cancel.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VarianceEstimator {
+    n: u64,
+    mean: f64,
+    // Sum of squared differences from the current mean.
+    m2: f64,
+}
+
+impl VarianceEstimator {
+    pub fn add(&mut self, v: f32) {
+        self.n += 1;
+        let delta = v as f64 - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (v as f64 - self.mean);
+    }
+
+    // Combines with an estimator that saw other values (Chan et al.).
+    pub fn merge(&mut self, other: &VarianceEstimator) {
+        if other.n == 0 {
+            return;
+        }
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.n as f64 / n as f64;
+        self.m2 += other.m2 + delta * delta * (self.n as f64 * other.n as f64) / n as f64;
+        self.n = n;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.n
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.mean as f32
+    }
+
+    // The unbiased sample variance.
+    pub fn variance(&self) -> f32 {
+        if self.n > 1 {
+            (self.m2 / (self.n - 1) as f64) as f32
+        } else {
+            0.0
+        }
+    }
+
+    // Standard error of the mean relative to the mean: zero when all values
+    // agree, infinite when they vary around a zero mean or there are fewer
+    // than two values to tell.
+    pub fn relative_error(&self) -> f32 {
+        if self.n < 2 {
+            return f32::INFINITY;
+        }
+        let std_error = (self.variance() as f64 / self.n as f64).sqrt();
+        if std_error == 0.0 {
+            return 0.0;
+        }
+        (std_error / self.mean.abs()) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_variance_estimator() {
+        let values = [3.0, 1.5, 4.0, 1.0, 5.5, 9.0, 2.5, 6.0];
+        let mut all = VarianceEstimator::default();
+        values.iter().for_each(|&v| all.add(v));
+        let mean = values.iter().sum::<f32>() / 8.0;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / 7.0;
+        assert_eq!(all.count(), 8);
+        assert!((all.mean() - mean).abs() < 1e-6);
+        assert!((all.variance() - variance).abs() < 1e-5);
+        assert!((all.relative_error() - (variance / 8.0).sqrt() / mean).abs() < 1e-6);
+
+        // Merging two halves gives the same result.
+        let (mut a, mut b) = (VarianceEstimator::default(), VarianceEstimator::default());
+        values[..3].iter().for_each(|&v| a.add(v));
+        values[3..].iter().for_each(|&v| b.add(v));
+        a.merge(&b);
+        assert_eq!(a.count(), 8);
+        assert!((a.mean() - mean).abs() < 1e-6);
+        assert!((a.variance() - variance).abs() < 1e-5);
+
+        // A large offset does not hurt the accuracy.
+        let mut offset = VarianceEstimator::default();
+        values.iter().for_each(|&v| offset.add(v + 1e6));
+        assert!((offset.variance() - variance).abs() < 1e-2);
+
+        let mut constant = VarianceEstimator::default();
+        constant.add(2.0);
+        assert_eq!(constant.relative_error(), f32::INFINITY);
+        constant.add(2.0);
+        assert_eq!(constant.relative_error(), 0.0);
+        assert_eq!(VarianceEstimator::default().relative_error(), f32::INFINITY);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::color::*;
+use crate::film::rgb::*;
+use crate::film::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::sampler::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Progress reported after each pass.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct PassInfo {
+    // Starting from one.
+    pub pass: u32,
+    // Samples taken so far by the pixels that are still active.
+    pub samples_per_pixel: u32,
+    pub active_pixels: usize,
+    pub total_samples: u64,
+    pub done: bool,
+}
+
+// Drives rendering into an RgbFilm in passes of a few samples per pixel, so
+// that partial images can be written between passes. Every pixel takes up to
+// the sampler's samples_per_pixel, so that no sample index repeats one the
+// sampler already produced. In adaptive mode, pixels whose relative error
+// (see VarianceEstimator) drops below a threshold stop taking samples.
+#[derive(Copy, Clone, Debug)]
+pub struct ProgressiveRenderer {
+    pass_samples: u32,
+    // Error threshold and the samples every pixel takes before it may stop.
+    adaptive: Option<(f32, u32)>,
+    threads: usize,
+    tile_size: i32,
+}
+
+impl Default for ProgressiveRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressiveRenderer {
+    // Takes all the samples in a single pass.
+    pub fn new() -> Self {
+        Self {
+            pass_samples: u32::MAX,
+            adaptive: None,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 16,
+        }
+    }
+
+    pub fn with_pass_samples(mut self, pass_samples: u32) -> Self {
+        self.pass_samples = pass_samples.max(1);
+        self
+    }
+
+    // Returns None unless the threshold is finite and non-negative and
+    // min_samples is positive.
+    pub fn with_adaptive(mut self, error_threshold: f32, min_samples: u32) -> Option<Self> {
+        if !(error_threshold.is_finite() && error_threshold >= 0.0) || min_samples == 0 {
+            return None;
+        }
+        self.adaptive = Some((error_threshold, min_samples));
+        Some(self)
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Calls sample for each pixel sample after starting it on the sampler,
+    // recording the returned radiance and weight, and on_pass after every
+    // pass. Returns the last pass's info.
+    pub fn render(
+        &self,
+        film: &RgbFilm,
+        sampler: &dyn Sampler,
+        sample: impl Fn(&mut dyn Sampler, Point2<i32>) -> (Rgb, f32) + Sync,
+        mut on_pass: impl FnMut(&PassInfo),
+    ) -> PassInfo {
+        let max_samples = sampler.samples_per_pixel();
+        let bounds = film.pixel_bounds();
+        let mut active = vec![true; pixel_count(&bounds)];
+        let tiles = self.tiles(&bounds);
+        let mut info = PassInfo::default();
+        loop {
+            let start = info.samples_per_pixel;
+            let end = start.saturating_add(self.pass_samples).min(max_samples);
+            let next_tile = AtomicUsize::new(0);
+            let (active_ref, tiles, sample) = (&active, &tiles, &sample);
+            std::thread::scope(|scope| {
+                for _ in 0..self.threads {
+                    let mut sampler = sampler.clone_for_thread();
+                    let next_tile = &next_tile;
+                    scope.spawn(move || {
+                        while let Some(tile_bounds) =
+                            tiles.get(next_tile.fetch_add(1, Ordering::Relaxed))
+                        {
+                            let mut tile = film.tile(*tile_bounds);
+                            for p in pixels(tile_bounds) {
+                                if !active_ref[pixel_offset(&bounds, p).unwrap()] {
+                                    continue;
+                                }
+                                for index in start..end {
+                                    sampler.start_pixel_sample(p, index, 0);
+                                    let (rgb, weight) = sample(sampler.as_mut(), p);
+                                    tile.add_sample(p, rgb, weight);
+                                }
+                            }
+                            film.merge_tile(tile);
+                        }
+                    });
+                }
+            });
+
+            let n_active = active.iter().filter(|&&a| a).count();
+            info.pass += 1;
+            info.samples_per_pixel = end;
+            info.total_samples += n_active as u64 * (end - start) as u64;
+            if let Some((threshold, min_samples)) = self.adaptive {
+                if end >= min_samples {
+                    for (p, a) in pixels(&bounds).zip(active.iter_mut()) {
+                        if *a && film.pixel_variance(p).relative_error() < threshold {
+                            *a = false;
+                        }
+                    }
+                }
+            }
+            info.active_pixels = active.iter().filter(|&&a| a).count();
+            info.done = end >= max_samples || info.active_pixels == 0;
+            on_pass(&info);
+            if info.done {
+                return info;
+            }
+        }
+    }
+
+    fn tiles(&self, bounds: &Bounds2<i32>) -> Vec<Bounds2<i32>> {
+        let (lo, hi) = (bounds.p_min(), bounds.p_max());
+        let mut tiles = Vec::new();
+        for y in (lo.y()..hi.y()).step_by(self.tile_size as usize) {
+            for x in (lo.x()..hi.x()).step_by(self.tile_size as usize) {
+                tiles.push(Bounds2::<i32>::new(
+                    (x, y).into(),
+                    (
+                        (x + self.tile_size).min(hi.x()),
+                        (y + self.tile_size).min(hi.y()),
+                    )
+                        .into(),
+                ));
+            }
+        }
+        tiles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+    use crate::sampler::independent::*;
+    use std::sync::Arc;
+
+    fn test_film() -> RgbFilm {
+        let window = Bounds2::<f32>::new((0.0, 0.0).into(), (1.0, 1.0).into());
+        RgbFilm::new((20, 12).into(), window, Arc::new(BoxFilter::default()))
+    }
+
+    #[test]
+    fn test_progressive() {
+        let film = test_film();
+        let sampler = IndependentSampler::new(8, 0);
+        let mut passes = Vec::new();
+        let info = ProgressiveRenderer::new()
+            .with_pass_samples(3)
+            .with_threads(3)
+            .render(
+                &film,
+                &sampler,
+                |_, p| (Rgb::splat(p.x() as f32), 1.0),
+                |info| passes.push((info.samples_per_pixel, film.to_rgb(1.0)[5])),
+            );
+        assert_eq!(info.pass, 3);
+        assert!(info.done);
+        assert_eq!(info.total_samples, 20 * 12 * 8);
+        // Intermediate images are complete at every pass.
+        assert_eq!(
+            passes,
+            [
+                (3, Rgb::splat(5.0)),
+                (6, Rgb::splat(5.0)),
+                (8, Rgb::splat(5.0))
+            ]
+        );
+        assert_eq!(film.pixel_variance((19, 11).into()).count(), 8);
+    }
+
+    #[test]
+    fn test_adaptive() {
+        // The left half is constant and converges at once; the right half
+        // is uniform noise that never reaches the threshold.
+        let film = test_film();
+        let sampler = IndependentSampler::new(64, 0);
+        let mut active = Vec::new();
+        let info = ProgressiveRenderer::new()
+            .with_pass_samples(4)
+            .with_adaptive(0.01, 8)
+            .unwrap()
+            .render(
+                &film,
+                &sampler,
+                |sampler, p| {
+                    let v = if p.x() < 10 {
+                        1.0
+                    } else {
+                        2.0 * sampler.get_1d()
+                    };
+                    (Rgb::splat(v), 1.0)
+                },
+                |info| active.push(info.active_pixels),
+            );
+        assert_eq!(info.pass, 16);
+        assert_eq!(&active[..3], [240, 120, 120]);
+        assert_eq!(info.total_samples, 120 * 8 + 120 * 64);
+        assert_eq!(film.pixel_variance((3, 4).into()).count(), 8);
+        assert_eq!(film.pixel_variance((13, 4).into()).count(), 64);
+        let noisy = film.get_pixel_rgb((13, 4).into(), 1.0).g;
+        assert!((noisy - 1.0).abs() < 0.3);
+
+        // With nothing left to sample, rendering stops early.
+        let film = test_film();
+        let info = ProgressiveRenderer::new()
+            .with_pass_samples(4)
+            .with_adaptive(0.01, 4)
+            .unwrap()
+            .render(&film, &sampler, |_, _| (Rgb::splat(0.5), 1.0), |_| {});
+        assert_eq!((info.pass, info.active_pixels), (1, 0));
+
+        // A single sample says nothing about the error, so even constant
+        // pixels take a second one.
+        let film = test_film();
+        let mut active = Vec::new();
+        ProgressiveRenderer::new()
+            .with_pass_samples(1)
+            .with_adaptive(0.01, 1)
+            .unwrap()
+            .render(
+                &film,
+                &sampler,
+                |_, _| (Rgb::splat(0.5), 1.0),
+                |info| active.push(info.active_pixels),
+            );
+        assert_eq!(active, [240, 0]);
+    }
+
+    #[test]
+    fn test_adaptive_settings() {
+        let renderer = ProgressiveRenderer::new();
+        assert!(renderer.with_adaptive(0.0, 1).is_some());
+        assert!(renderer.with_adaptive(-0.01, 8).is_none());
+        assert!(renderer.with_adaptive(f32::NAN, 8).is_none());
+        assert!(renderer.with_adaptive(f32::INFINITY, 8).is_none());
+        assert!(renderer.with_adaptive(0.01, 0).is_none());
+    }
+}
@@ -0,0 +1,319 @@
+use crate::color::*;
+use crate::film::*;
+use crate::filter::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::image::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Pixel {
+    rgb_sum: [f64; 3],
+    weight_sum: f64,
+    // Of the samples' average over the channels, unweighted.
+    variance: VarianceEstimator,
+}
+
+impl Pixel {
+    fn add(&mut self, rgb: Rgb, weight: f32) {
+        for c in 0..3 {
+            self.rgb_sum[c] += (rgb[c] * weight) as f64;
+        }
+        self.weight_sum += weight as f64;
+        self.variance.add(rgb.average());
+    }
+
+    fn merge(&mut self, other: &Pixel) {
+        for c in 0..3 {
+            self.rgb_sum[c] += other.rgb_sum[c];
+        }
+        self.weight_sum += other.weight_sum;
+        self.variance.merge(&other.variance);
+    }
+}
+
+// Scales rgb down so that no component exceeds max, keeping its hue. Rare
+// very bright samples (fireflies) otherwise dominate their pixels.
+fn clamp_rgb(rgb: Rgb, max: f32) -> Rgb {
+    let m = rgb.max_component();
+    if m > max {
+        rgb * (max / m)
+    } else {
+        rgb
+    }
+}
+
+// Accumulates RGB radiance samples over the pixels of a crop window. Each
+// pixel keeps the weighted sum of its samples and of their filter weights,
+// whose ratio is the reconstructed value. Camera samples land in exactly
+// one pixel, with the filter applied by warping the sample positions (see
+// get_camera_sample); splats from light paths are spread over every pixel
+// within the filter's radius.
+pub struct RgbFilm {
+    full_resolution: Point2<i32>,
+    pixel_bounds: Bounds2<i32>,
+    filter: Arc<dyn Filter>,
+    max_component_value: f32,
+    pixels: Mutex<Vec<Pixel>>,
+    splats: Vec<[AtomicF64; 3]>,
+}
+
+impl RgbFilm {
+    pub fn new(
+        full_resolution: Point2<i32>,
+        crop_window: Bounds2<f32>,
+        filter: Arc<dyn Filter>,
+    ) -> Self {
+        let pixel_bounds = crop_pixel_bounds(full_resolution, crop_window);
+        let n = pixel_count(&pixel_bounds);
+        Self {
+            full_resolution,
+            pixel_bounds,
+            filter,
+            max_component_value: f32::INFINITY,
+            pixels: Mutex::new(vec![Pixel::default(); n]),
+            splats: (0..n).map(|_| Default::default()).collect(),
+        }
+    }
+
+    pub fn with_max_component_value(mut self, max_component_value: f32) -> Self {
+        self.max_component_value = max_component_value;
+        self
+    }
+
+    pub fn full_resolution(&self) -> Point2<i32> {
+        self.full_resolution
+    }
+
+    pub fn pixel_bounds(&self) -> Bounds2<i32> {
+        self.pixel_bounds
+    }
+
+    pub fn filter(&self) -> &dyn Filter {
+        self.filter.as_ref()
+    }
+
+    // Adds one camera sample to its pixel. Prefer filling a FilmTile per
+    // thread and merging it, which takes the lock once per tile.
+    pub fn add_sample(&self, pixel: Point2<i32>, rgb: Rgb, weight: f32) {
+        if let Some(i) = pixel_offset(&self.pixel_bounds, pixel) {
+            let rgb = clamp_rgb(rgb, self.max_component_value);
+            self.pixels.lock().unwrap()[i].add(rgb, weight);
+        }
+    }
+
+    // A tile for the part of bounds inside the film, to be filled by one
+    // thread and handed back to merge_tile.
+    pub fn tile(&self, bounds: Bounds2<i32>) -> FilmTile {
+        let bounds = bounds.intersect(&self.pixel_bounds);
+        FilmTile {
+            bounds,
+            max_component_value: self.max_component_value,
+            pixels: vec![Pixel::default(); pixel_count(&bounds)],
+        }
+    }
+
+    pub fn merge_tile(&self, tile: FilmTile) {
+        let mut pixels = self.pixels.lock().unwrap();
+        for (p, tile_pixel) in super::pixels(&tile.bounds).zip(&tile.pixels) {
+            if let Some(i) = pixel_offset(&self.pixel_bounds, p) {
+                pixels[i].merge(tile_pixel);
+            }
+        }
+    }
+
+    // Adds a contribution at raster position p to all the pixels within the
+    // filter's radius, weighted by the filter. Splats are not normalized by
+    // the filter weight; they are scaled when the image is read back.
+    pub fn add_splat(&self, p: Point2<f32>, rgb: Rgb) {
+        let rgb = clamp_rgb(rgb, self.max_component_value);
+        let radius = self.filter.radius();
+        let p_discrete = p + Point2::<f32>::new(0.5);
+        let lo = (p_discrete - radius).floor();
+        let hi = (p_discrete + radius).floor();
+        let splat_bounds = Bounds2::<i32>::new(
+            (lo.x() as i32, lo.y() as i32).into(),
+            (hi.x() as i32 + 1, hi.y() as i32 + 1).into(),
+        )
+        .intersect(&self.pixel_bounds);
+        for pi in super::pixels(&splat_bounds) {
+            let offset = p - Point2::<f32>::elements(pi.x() as f32 + 0.5, pi.y() as f32 + 0.5);
+            let weight = self.filter.evaluate(offset);
+            if weight != 0.0 {
+                let splat = &self.splats[pixel_offset(&self.pixel_bounds, pi).unwrap()];
+                for c in 0..3 {
+                    splat[c].add((rgb[c] * weight) as f64);
+                }
+            }
+        }
+    }
+
+    // Reconstructed value of a pixel: the filter-weighted average of its
+    // samples plus its splats times splat_scale (typically one over the
+    // number of light paths per pixel).
+    pub fn get_pixel_rgb(&self, pixel: Point2<i32>, splat_scale: f32) -> Rgb {
+        let i = pixel_offset(&self.pixel_bounds, pixel).expect("pixel outside the film");
+        let pixel = self.pixels.lock().unwrap()[i];
+        let mut rgb = Rgb::default();
+        for c in 0..3 {
+            if pixel.weight_sum != 0.0 {
+                rgb[c] = (pixel.rgb_sum[c] / pixel.weight_sum) as f32;
+            }
+            rgb[c] += splat_scale * self.splats[i][c].load() as f32;
+        }
+        rgb
+    }
+
+    // Statistics of the pixel's camera samples (not splats), e.g. for
+    // deciding whether it needs more samples.
+    pub fn pixel_variance(&self, pixel: Point2<i32>) -> VarianceEstimator {
+        let i = pixel_offset(&self.pixel_bounds, pixel).expect("pixel outside the film");
+        self.pixels.lock().unwrap()[i].variance
+    }
+
+    // Every pixel of the crop window, row by row.
+    pub fn to_rgb(&self, splat_scale: f32) -> Vec<Rgb> {
+        super::pixels(&self.pixel_bounds)
+            .map(|p| self.get_pixel_rgb(p, splat_scale))
+            .collect()
+    }
+
+    // The crop window as an RGB image for writing out.
+    pub fn get_image(&self, splat_scale: f32) -> Image {
+        let d = self.pixel_bounds.diagonal();
+        Image::from_rgb((d.x(), d.y()).into(), &self.to_rgb(splat_scale))
+    }
+}
+
+// Sample sums for a rectangle of pixels owned by one thread, so that
+// threads only contend for the film when they merge a finished tile.
+#[derive(Clone, Debug)]
+pub struct FilmTile {
+    bounds: Bounds2<i32>,
+    max_component_value: f32,
+    pixels: Vec<Pixel>,
+}
+
+impl FilmTile {
+    pub fn bounds(&self) -> Bounds2<i32> {
+        self.bounds
+    }
+
+    pub fn add_sample(&mut self, pixel: Point2<i32>, rgb: Rgb, weight: f32) {
+        if let Some(i) = pixel_offset(&self.bounds, pixel) {
+            self.pixels[i].add(clamp_rgb(rgb, self.max_component_value), weight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+    use crate::filter::gaussian::*;
+
+    fn full_window() -> Bounds2<f32> {
+        Bounds2::<f32>::new((0.0, 0.0).into(), (1.0, 1.0).into())
+    }
+
+    fn film(res: (i32, i32)) -> RgbFilm {
+        RgbFilm::new(res.into(), full_window(), Arc::new(BoxFilter::default()))
+    }
+
+    #[test]
+    fn test_crop_window() {
+        let crop = Bounds2::<f32>::new((0.25, 0.1).into(), (0.75, 0.5).into());
+        let film = RgbFilm::new((100, 50).into(), crop, Arc::new(BoxFilter::default()));
+        assert_eq!(film.pixel_bounds().p_min(), (25, 5).into());
+        assert_eq!(film.pixel_bounds().p_max(), (75, 25).into());
+        // Samples outside the crop window are dropped.
+        film.add_sample((0, 0).into(), Rgb::splat(1.0), 1.0);
+        assert_eq!(film.to_rgb(1.0).len(), 50 * 20);
+    }
+
+    #[test]
+    fn test_weighted_average() {
+        let film = film((4, 4));
+        film.add_sample((1, 2).into(), Rgb::new(1.0, 2.0, 3.0), 1.0);
+        film.add_sample((1, 2).into(), Rgb::new(3.0, 2.0, 1.0), 3.0);
+        assert_eq!(
+            film.get_pixel_rgb((1, 2).into(), 1.0),
+            Rgb::new(2.5, 2.0, 1.5)
+        );
+        assert_eq!(film.get_pixel_rgb((0, 0).into(), 1.0), Rgb::default());
+
+        let variance = film.pixel_variance((1, 2).into());
+        assert_eq!(variance.count(), 2);
+        assert_eq!(variance.mean(), 2.0);
+        assert_eq!(film.pixel_variance((0, 0).into()).count(), 0);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let film = film((2, 2)).with_max_component_value(5.0);
+        film.add_sample((0, 0).into(), Rgb::new(10.0, 1.0, 0.0), 1.0);
+        assert_eq!(
+            film.get_pixel_rgb((0, 0).into(), 1.0),
+            Rgb::new(5.0, 0.5, 0.0)
+        );
+        let mut tile = film.tile(film.pixel_bounds());
+        tile.add_sample((1, 1).into(), Rgb::new(0.0, 20.0, 0.0), 1.0);
+        film.merge_tile(tile);
+        assert_eq!(
+            film.get_pixel_rgb((1, 1).into(), 1.0),
+            Rgb::new(0.0, 5.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_parallel_tiles() {
+        let film = film((16, 16));
+        std::thread::scope(|scope| {
+            for ty in 0..4 {
+                for tx in 0..4 {
+                    let film = &film;
+                    scope.spawn(move || {
+                        let bounds = Bounds2::<i32>::new(
+                            (tx * 4, ty * 4).into(),
+                            (tx * 4 + 4, ty * 4 + 4).into(),
+                        );
+                        let mut tile = film.tile(bounds);
+                        for p in pixels(&tile.bounds()) {
+                            let v = (p.y() * 16 + p.x()) as f32;
+                            tile.add_sample(p, Rgb::splat(v), 0.5);
+                            tile.add_sample(p, Rgb::splat(v), 0.5);
+                        }
+                        film.merge_tile(tile);
+                    });
+                }
+            }
+        });
+        let image = film.to_rgb(1.0);
+        for (i, rgb) in image.iter().enumerate() {
+            assert_eq!(*rgb, Rgb::splat(i as f32));
+        }
+    }
+
+    #[test]
+    fn test_splats() {
+        // A box filter only splats into the pixel containing the point.
+        let film = film((4, 4));
+        film.add_splat((2.3, 1.6).into(), Rgb::splat(2.0));
+        let image = film.to_rgb(0.5);
+        for (i, rgb) in image.iter().enumerate() {
+            let expected = if i == 4 + 2 { 1.0 } else { 0.0 };
+            assert_eq!(*rgb, Rgb::splat(expected));
+        }
+
+        // A wider filter spreads the splat, in proportion to the filter.
+        let filter = Arc::new(GaussianFilter::default());
+        let film = RgbFilm::new((8, 8).into(), full_window(), filter.clone());
+        film.add_splat((4.0, 4.0).into(), Rgb::splat(1.0));
+        let total: f32 = film.to_rgb(1.0).iter().map(|c| c.g).sum();
+        assert!((total - filter.integral()).abs() < 0.05 * filter.integral());
+        assert_eq!(
+            film.get_pixel_rgb((3, 3).into(), 1.0),
+            film.get_pixel_rgb((4, 4).into(), 1.0)
+        );
+    }
+}
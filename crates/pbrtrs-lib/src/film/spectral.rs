@@ -0,0 +1,345 @@
+use crate::color::*;
+use crate::colorspace::*;
+use crate::film::rgb::*;
+use crate::filter::*;
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::image::*;
+use crate::spectrum::*;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+enum SensorCurves {
+    Xyz,
+    Rgb([PiecewiseLinearSpectrum; 3]),
+}
+
+// How the camera turns spectral radiance into three values: either the CIE
+// matching functions, giving XYZ directly, or a camera's measured RGB
+// sensitivities, with a matrix to XYZ fitted to them.
+#[derive(Clone, Debug)]
+pub struct PixelSensor {
+    curves: SensorCurves,
+    // Scales the response so that a constant spectrum gives one in the
+    // green (or Y) channel.
+    normalization: f32,
+    xyz_from_sensor_rgb: Matrix3x3,
+    imaging_ratio: f32,
+}
+
+impl PixelSensor {
+    pub fn xyz() -> Self {
+        Self {
+            curves: SensorCurves::Xyz,
+            normalization: 1.0 / integrate_visible(cie_y),
+            xyz_from_sensor_rgb: Matrix3x3::identity(),
+            imaging_ratio: 1.0,
+        }
+    }
+
+    // A sensor with the given spectral sensitivities. Real sensors are not
+    // a linear transform of the matching functions, so the matrix to XYZ is
+    // the least-squares fit over the visible range. Returns None if the
+    // green curve has no response or the curves are linearly dependent.
+    pub fn rgb(
+        r: PiecewiseLinearSpectrum,
+        g: PiecewiseLinearSpectrum,
+        b: PiecewiseLinearSpectrum,
+    ) -> Option<Self> {
+        let curves = [r, g, b];
+        let g_integral = integrate_visible(|l| curves[1].evaluate(l));
+        if !(g_integral > 0.0 && g_integral.is_finite()) {
+            return None;
+        }
+        let y_integral = integrate_visible(cie_y) as f64;
+        // Solve M * sensor(l) ~ xyz(l) via the normal equations
+        // M = (sum xyz sensor^T) (sum sensor sensor^T)^-1.
+        let (mut xs, mut ss) = ([[0.0f64; 3]; 3], [[0.0f64; 3]; 3]);
+        for l in LAMBDA_MIN as i32..=LAMBDA_MAX as i32 {
+            let l = l as f32;
+            let s = curves
+                .clone()
+                .map(|c| c.evaluate(l) as f64 / g_integral as f64);
+            let x = [cie_x(l), cie_y(l), cie_z(l)].map(|v| v as f64 / y_integral);
+            for i in 0..3 {
+                for j in 0..3 {
+                    xs[i][j] += x[i] * s[j];
+                    ss[i][j] += s[i] * s[j];
+                }
+            }
+        }
+        let to_f32 = |m: [[f64; 3]; 3]| Matrix3x3::new(m.map(|row| row.map(|v| v as f32)));
+        let ss_inv = to_f32(ss).inverse()?;
+        Some(Self {
+            normalization: 1.0 / g_integral,
+            xyz_from_sensor_rgb: to_f32(xs).mul(&ss_inv),
+            curves: SensorCurves::Rgb(curves),
+            imaging_ratio: 1.0,
+        })
+    }
+
+    // Overall scale of the response, e.g. exposure time times ISO / 100.
+    pub fn with_imaging_ratio(mut self, imaging_ratio: f32) -> Self {
+        self.imaging_ratio = imaging_ratio;
+        self
+    }
+
+    pub fn xyz_from_sensor_rgb(&self) -> Matrix3x3 {
+        self.xyz_from_sensor_rgb
+    }
+
+    // The sensor's response to radiance l at the sampled wavelengths: a
+    // Monte Carlo estimate of the integral of l against each curve.
+    pub fn to_sensor_rgb(&self, l: &SampledSpectrum, lambda: &SampledWavelengths) -> Rgb {
+        let l = l.safe_div(&lambda.pdf());
+        let mut rgb = Rgb::default();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            let response = match &self.curves {
+                SensorCurves::Xyz => [cie_x(lambda[i]), cie_y(lambda[i]), cie_z(lambda[i])],
+                SensorCurves::Rgb(c) => [
+                    c[0].evaluate(lambda[i]),
+                    c[1].evaluate(lambda[i]),
+                    c[2].evaluate(lambda[i]),
+                ],
+            };
+            rgb += Rgb::from(response) * l[i];
+        }
+        rgb * (self.imaging_ratio * self.normalization / N_SPECTRUM_SAMPLES as f32)
+    }
+}
+
+// A film for spectral rendering. Each sample's radiance at its wavelengths
+// goes through the sensor, is white balanced, and is accumulated in the
+// output color space by an RgbFilm.
+pub struct SpectralFilm {
+    rgb: RgbFilm,
+    sensor: PixelSensor,
+    color_space: RgbColorSpace,
+    // From the scene's white to the color space's, in XYZ.
+    white_balance: Matrix3x3,
+    output_rgb_from_sensor_rgb: Matrix3x3,
+}
+
+impl SpectralFilm {
+    pub fn new(
+        full_resolution: Point2<i32>,
+        crop_window: Bounds2<f32>,
+        filter: Arc<dyn Filter>,
+        sensor: PixelSensor,
+        color_space: RgbColorSpace,
+    ) -> Self {
+        Self {
+            rgb: RgbFilm::new(full_resolution, crop_window, filter),
+            sensor,
+            color_space,
+            white_balance: Matrix3x3::identity(),
+            output_rgb_from_sensor_rgb: Matrix3x3::identity(),
+        }
+        .update_matrix()
+    }
+
+    // Adapts colors so that the scene illuminant, with chromaticity
+    // src_white, comes out as the white of the output color space. Returns
+    // None if src_white is not a usable white, such as one with y = 0.
+    pub fn with_white_balance(mut self, src_white: [f32; 2]) -> Option<Self> {
+        self.white_balance = white_balance(src_white, self.color_space.white())?;
+        Some(self.update_matrix())
+    }
+
+    pub fn with_max_component_value(mut self, max_component_value: f32) -> Self {
+        self.rgb = self.rgb.with_max_component_value(max_component_value);
+        self
+    }
+
+    fn update_matrix(mut self) -> Self {
+        self.output_rgb_from_sensor_rgb = self
+            .color_space
+            .rgb_from_xyz()
+            .mul(&self.white_balance)
+            .mul(&self.sensor.xyz_from_sensor_rgb());
+        self
+    }
+
+    pub fn color_space(&self) -> &RgbColorSpace {
+        &self.color_space
+    }
+
+    pub fn sensor(&self) -> &PixelSensor {
+        &self.sensor
+    }
+
+    pub fn rgb_film(&self) -> &RgbFilm {
+        &self.rgb
+    }
+
+    pub fn pixel_bounds(&self) -> Bounds2<i32> {
+        self.rgb.pixel_bounds()
+    }
+
+    // The sample's color in the output color space.
+    pub fn to_output_rgb(&self, l: &SampledSpectrum, lambda: &SampledWavelengths) -> Rgb {
+        let sensor_rgb = self.sensor.to_sensor_rgb(l, lambda);
+        self.output_rgb_from_sensor_rgb
+            .apply(sensor_rgb.into())
+            .into()
+    }
+
+    pub fn add_sample(
+        &self,
+        pixel: Point2<i32>,
+        l: &SampledSpectrum,
+        lambda: &SampledWavelengths,
+        weight: f32,
+    ) {
+        self.rgb
+            .add_sample(pixel, self.to_output_rgb(l, lambda), weight);
+    }
+
+    pub fn add_splat(&self, p: Point2<f32>, l: &SampledSpectrum, lambda: &SampledWavelengths) {
+        self.rgb.add_splat(p, self.to_output_rgb(l, lambda));
+    }
+
+    pub fn tile(&self, bounds: Bounds2<i32>) -> FilmTile {
+        self.rgb.tile(bounds)
+    }
+
+    pub fn add_tile_sample(
+        &self,
+        tile: &mut FilmTile,
+        pixel: Point2<i32>,
+        l: &SampledSpectrum,
+        lambda: &SampledWavelengths,
+        weight: f32,
+    ) {
+        tile.add_sample(pixel, self.to_output_rgb(l, lambda), weight);
+    }
+
+    pub fn merge_tile(&self, tile: FilmTile) {
+        self.rgb.merge_tile(tile);
+    }
+
+    pub fn get_image(&self, splat_scale: f32) -> Image {
+        self.rgb.get_image(splat_scale)
+    }
+
+    // Records the output color space with the image.
+    pub fn metadata(&self) -> ImageMetadata {
+        ImageMetadata {
+            strings: vec![(
+                "colorSpace".to_string(),
+                self.color_space.name().to_string(),
+            )],
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+
+    fn film(sensor: PixelSensor) -> SpectralFilm {
+        let window = Bounds2::<f32>::new((0.0, 0.0).into(), (1.0, 1.0).into());
+        SpectralFilm::new(
+            (1, 1).into(),
+            window,
+            Arc::new(BoxFilter::default()),
+            sensor,
+            RgbColorSpace::srgb(),
+        )
+    }
+
+    // Renders a single pixel lit by spectrum s with stratified wavelengths.
+    fn render(film: &SpectralFilm, s: &dyn Spectrum) -> Rgb {
+        let n = 4096;
+        for k in 0..n {
+            let lambda = SampledWavelengths::sample_visible((k as f32 + 0.5) / n as f32);
+            film.add_sample((0, 0).into(), &s.sample(&lambda), &lambda, 1.0);
+        }
+        film.rgb_film().get_pixel_rgb((0, 0).into(), 1.0)
+    }
+
+    fn assert_close(a: Rgb, b: Rgb, eps: f32) {
+        for c in 0..3 {
+            assert!((a[c] - b[c]).abs() < eps, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_white_balance() {
+        // The equal-energy spectrum is reddish in sRGB unless balanced.
+        let e = ConstantSpectrum(1.0);
+        let rgb = render(&film(PixelSensor::xyz()), &e);
+        assert!(rgb.r > 1.0 && rgb.b < 1.0);
+        assert!((RgbColorSpace::srgb().luminance(rgb) - 1.0).abs() < 0.01);
+        let balanced = film(PixelSensor::xyz())
+            .with_white_balance(WHITE_E)
+            .unwrap();
+        assert_close(render(&balanced, &e), Rgb::splat(1.0), 0.01);
+        assert!(film(PixelSensor::xyz())
+            .with_white_balance([0.3, 0.0])
+            .is_none());
+
+        // Likewise for a warm blackbody.
+        let light = BlackbodySpectrum::new(3000.0);
+        let balanced = film(PixelSensor::xyz())
+            .with_white_balance(blackbody_white(3000.0))
+            .unwrap();
+        let rgb = render(&balanced, &light);
+        assert_close(rgb, Rgb::splat(rgb.g), 0.01 * rgb.g);
+    }
+
+    #[test]
+    fn test_rgb_sensor() {
+        // Sensitivities that are a mix of the matching functions are fitted
+        // exactly, so the sensor sees what the XYZ sensor sees.
+        let curve = |f: fn(f32) -> f32| PiecewiseLinearSpectrum::from_function(f).unwrap();
+        let r = curve(|l| cie_x(l) + 0.2 * cie_y(l));
+        let g = curve(|l| 0.1 * cie_x(l) + cie_y(l));
+        let b = curve(|l| 0.3 * cie_y(l) + cie_z(l));
+        let sensor = PixelSensor::rgb(r.clone(), g, b.clone())
+            .unwrap()
+            .with_imaging_ratio(2.0);
+
+        let light = BlackbodySpectrum::new(4500.0);
+        let expected = render(&film(PixelSensor::xyz().with_imaging_ratio(2.0)), &light);
+        assert_close(render(&film(sensor), &light), expected, 2e-3);
+
+        let flat = curve(|_| 0.0);
+        assert!(PixelSensor::rgb(r.clone(), flat, b).is_none());
+        assert!(PixelSensor::rgb(r.clone(), r.clone(), r).is_none());
+    }
+
+    // CIE illuminant D65 from 360 to 830 nm in 10 nm steps.
+    const D65: [f32; 48] = [
+        46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+        117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046,
+        100.0, 96.3342, 95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268,
+        80.2146, 82.2778, 78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927,
+        46.4182, 66.8054, 63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+    ];
+
+    #[test]
+    fn test_output_space() {
+        let window = Bounds2::<f32>::new((0.0, 0.0).into(), (1.0, 1.0).into());
+        let film = SpectralFilm::new(
+            (1, 1).into(),
+            window,
+            Arc::new(BoxFilter::default()),
+            PixelSensor::xyz(),
+            RgbColorSpace::named("acescg").unwrap(),
+        )
+        .with_white_balance(WHITE_D65)
+        .unwrap();
+        // D65 light at unit luminance maps to the ACES white.
+        let lambdas: Vec<f32> = (0..D65.len()).map(|i| 360.0 + 10.0 * i as f32).collect();
+        let d65 = PiecewiseLinearSpectrum::new(&lambdas, &D65).unwrap();
+        let y = spectrum_to_xyz(&d65).y;
+        let d65 = PiecewiseLinearSpectrum::new(&lambdas, &D65.map(|v| v / y)).unwrap();
+        assert_close(render(&film, &d65), Rgb::splat(1.0), 2e-3);
+        let white = film.color_space().to_xyz(Rgb::splat(1.0)).xy();
+        assert!((white[0] - WHITE_ACES[0]).abs() < 1e-4);
+        assert_eq!(film.metadata().strings[0].1, "acescg");
+    }
+}
@@ -0,0 +1,48 @@
+use crate::filter::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+
+// Weights every sample within the radius equally.
+#[derive(Copy, Clone, Debug)]
+pub struct BoxFilter {
+    radius: Vec2<f32>,
+}
+
+impl BoxFilter {
+    pub fn new(radius: Vec2<f32>) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    // Covers exactly one pixel.
+    fn default() -> Self {
+        Self::new(Vec2::<f32>::new(0.5))
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> Vec2<f32> {
+        self.radius
+    }
+
+    fn evaluate(&self, p: Point2<f32>) -> f32 {
+        if p.x().abs() <= self.radius.x() && p.y().abs() <= self.radius.y() {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn integral(&self) -> f32 {
+        4.0 * self.radius.x() * self.radius.y()
+    }
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample {
+        let r = self.radius;
+        FilterSample {
+            p: Point2::<f32>::elements((2.0 * u.x() - 1.0) * r.x(), (2.0 * u.y() - 1.0) * r.y()),
+            weight: self.integral(),
+        }
+    }
+}
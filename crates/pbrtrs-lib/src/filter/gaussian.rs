@@ -0,0 +1,81 @@
+use crate::filter::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+use std::f32::consts::PI;
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    (-x * x / (2.0 * sigma * sigma)).exp() / (2.0 * PI * sigma * sigma).sqrt()
+}
+
+// Abramowitz and Stegun's approximation 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f32) -> f32 {
+    let (a1, a2, a3, a4, a5, p) = (
+        0.254_829_6,
+        -0.284_496_74,
+        1.421_413_7,
+        -1.453_152_1,
+        1.061_405_4,
+        0.327_591_1,
+    );
+    let t = 1.0 / (1.0 + p * x.abs());
+    let y = 1.0 - (((((a5 * t + a4) * t) + a3) * t + a2) * t + a1) * t * (-x * x).exp();
+    y.copysign(x)
+}
+
+fn gaussian_integral(x0: f32, x1: f32, sigma: f32) -> f32 {
+    let scale = 1.0 / (sigma * 2.0_f32.sqrt());
+    0.5 * (erf(x1 * scale) - erf(x0 * scale))
+}
+
+// A Gaussian shifted down so that it reaches zero at the radius.
+#[derive(Clone, Debug)]
+pub struct GaussianFilter {
+    radius: Vec2<f32>,
+    sigma: f32,
+    exp_x: f32,
+    exp_y: f32,
+    sampler: FilterSampler,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: Vec2<f32>, sigma: f32) -> Self {
+        let (exp_x, exp_y) = (gaussian(radius.x(), sigma), gaussian(radius.y(), sigma));
+        let evaluate = move |p: Point2<f32>| {
+            (gaussian(p.x(), sigma) - exp_x).max(0.0) * (gaussian(p.y(), sigma) - exp_y).max(0.0)
+        };
+        Self {
+            radius,
+            sigma,
+            exp_x,
+            exp_y,
+            sampler: FilterSampler::new(radius, evaluate),
+        }
+    }
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        Self::new(Vec2::<f32>::new(1.5), 0.5)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> Vec2<f32> {
+        self.radius
+    }
+
+    fn evaluate(&self, p: Point2<f32>) -> f32 {
+        (gaussian(p.x(), self.sigma) - self.exp_x).max(0.0)
+            * (gaussian(p.y(), self.sigma) - self.exp_y).max(0.0)
+    }
+
+    fn integral(&self) -> f32 {
+        let (rx, ry) = (self.radius.x(), self.radius.y());
+        (gaussian_integral(-rx, rx, self.sigma) - 2.0 * rx * self.exp_x)
+            * (gaussian_integral(-ry, ry, self.sigma) - 2.0 * ry * self.exp_y)
+    }
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample {
+        self.sampler.sample(u)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::filter::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+use std::f32::consts::PI;
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+fn windowed_sinc(x: f32, radius: f32, tau: f32) -> f32 {
+    if x.abs() > radius {
+        return 0.0;
+    }
+    sinc(x) * sinc(x / tau)
+}
+
+// A sinc windowed by a wider sinc with tau lobes.
+#[derive(Clone, Debug)]
+pub struct LanczosSincFilter {
+    radius: Vec2<f32>,
+    tau: f32,
+    integral: f32,
+    sampler: FilterSampler,
+}
+
+impl LanczosSincFilter {
+    pub fn new(radius: Vec2<f32>, tau: f32) -> Self {
+        // There is no closed form, so integrate each axis numerically.
+        let integrate = |r: f32| {
+            let n = 4096;
+            let dx = 2.0 * r / n as f32;
+            (0..n)
+                .map(|i| windowed_sinc(-r + (i as f32 + 0.5) * dx, r, tau))
+                .sum::<f32>()
+                * dx
+        };
+        let integral = integrate(radius.x()) * integrate(radius.y());
+        let evaluate = move |p: Point2<f32>| {
+            windowed_sinc(p.x(), radius.x(), tau) * windowed_sinc(p.y(), radius.y(), tau)
+        };
+        Self {
+            radius,
+            tau,
+            integral,
+            sampler: FilterSampler::new(radius, evaluate),
+        }
+    }
+}
+
+impl Default for LanczosSincFilter {
+    fn default() -> Self {
+        Self::new(Vec2::<f32>::new(4.0), 3.0)
+    }
+}
+
+impl Filter for LanczosSincFilter {
+    fn radius(&self) -> Vec2<f32> {
+        self.radius
+    }
+
+    fn evaluate(&self, p: Point2<f32>) -> f32 {
+        windowed_sinc(p.x(), self.radius.x(), self.tau)
+            * windowed_sinc(p.y(), self.radius.y(), self.tau)
+    }
+
+    fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample {
+        self.sampler.sample(u)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::filter::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+
+// Mitchell and Netravali's cubic; larger b blurs, larger c rings.
+#[derive(Clone, Debug)]
+pub struct MitchellFilter {
+    radius: Vec2<f32>,
+    b: f32,
+    c: f32,
+    sampler: FilterSampler,
+}
+
+// Integrates to one over [-2, 2].
+fn mitchell_1d(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+impl MitchellFilter {
+    pub fn new(radius: Vec2<f32>, b: f32, c: f32) -> Self {
+        let evaluate = move |p: Point2<f32>| {
+            mitchell_1d(2.0 * p.x() / radius.x(), b, c)
+                * mitchell_1d(2.0 * p.y() / radius.y(), b, c)
+        };
+        Self {
+            radius,
+            b,
+            c,
+            sampler: FilterSampler::new(radius, evaluate),
+        }
+    }
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        Self::new(Vec2::<f32>::new(2.0), 1.0 / 3.0, 1.0 / 3.0)
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> Vec2<f32> {
+        self.radius
+    }
+
+    fn evaluate(&self, p: Point2<f32>) -> f32 {
+        mitchell_1d(2.0 * p.x() / self.radius.x(), self.b, self.c)
+            * mitchell_1d(2.0 * p.y() / self.radius.y(), self.b, self.c)
+    }
+
+    fn integral(&self) -> f32 {
+        self.radius.x() * self.radius.y() / 4.0
+    }
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample {
+        self.sampler.sample(u)
+    }
+}
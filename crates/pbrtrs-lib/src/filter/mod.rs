@@ -0,0 +1,173 @@
+#![allow(dead_code)]
+
+pub mod box_filter;
+pub mod gaussian;
+pub mod lanczos;
+pub mod mitchell;
+pub mod triangle;
+
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+use crate::sampling::piecewise::*;
+
+// An offset from the pixel center and its weight, f(p) / pdf(p).
+#[derive(Copy, Clone, Debug)]
+pub struct FilterSample {
+    pub p: Point2<f32>,
+    pub weight: f32,
+}
+
+// A pixel reconstruction filter centered on the origin.
+pub trait Filter: Send + Sync {
+    // Half-widths along x and y beyond which the filter is zero.
+    fn radius(&self) -> Vec2<f32>;
+
+    fn evaluate(&self, p: Point2<f32>) -> f32;
+
+    fn integral(&self) -> f32;
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample;
+}
+
+// Samples filters that have no analytic method from a table of their
+// magnitude.
+#[derive(Clone, Debug)]
+pub struct FilterSampler {
+    f: Vec<f32>,
+    nx: usize,
+    distrib: PiecewiseConstant2D,
+}
+
+impl FilterSampler {
+    // Tabulation density, in entries per unit of filter radius.
+    const RESOLUTION: f32 = 32.0;
+
+    pub fn new(radius: Vec2<f32>, filter: impl Fn(Point2<f32>) -> f32) -> Self {
+        assert!(
+            radius.x() > 0.0 && radius.y() > 0.0,
+            "filter radius must be positive"
+        );
+        let domain = Bounds2::<f32>::new(-radius, radius);
+        let nx = ((Self::RESOLUTION * radius.x()) as usize).max(1);
+        let ny = ((Self::RESOLUTION * radius.y()) as usize).max(1);
+        let mut f = Vec::with_capacity(nx * ny);
+        for y in 0..ny {
+            for x in 0..nx {
+                let p = Point2::<f32>::elements(
+                    -radius.x() + (x as f32 + 0.5) / nx as f32 * 2.0 * radius.x(),
+                    -radius.y() + (y as f32 + 0.5) / ny as f32 * 2.0 * radius.y(),
+                );
+                f.push(filter(p));
+            }
+        }
+        let magnitude: Vec<f32> = f.iter().map(|v| v.abs()).collect();
+        let distrib = PiecewiseConstant2D::new(&magnitude, nx, ny, domain)
+            .expect("filter values must be finite");
+        Self { f, nx, distrib }
+    }
+
+    pub fn sample(&self, u: Point2<f32>) -> FilterSample {
+        let (p, pdf, (x, y)) = self.distrib.sample(u);
+        FilterSample {
+            p,
+            weight: self.f[y * self.nx + x] / pdf,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+    use crate::filter::gaussian::*;
+    use crate::filter::lanczos::*;
+    use crate::filter::mitchell::*;
+    use crate::filter::triangle::*;
+    use crate::rng::*;
+
+    fn filters() -> Vec<Box<dyn Filter>> {
+        let r = Vec2::<f32>::elements(1.5, 2.0);
+        vec![
+            Box::new(BoxFilter::default()),
+            Box::new(BoxFilter::new(r)),
+            Box::new(TriangleFilter::default()),
+            Box::new(TriangleFilter::new(r)),
+            Box::new(GaussianFilter::default()),
+            Box::new(GaussianFilter::new(r, 0.8)),
+            Box::new(MitchellFilter::default()),
+            Box::new(MitchellFilter::new(r, 0.5, 0.25)),
+            Box::new(LanczosSincFilter::default()),
+            Box::new(LanczosSincFilter::new(r, 2.0)),
+        ]
+    }
+
+    // Midpoint rule over the filter's extent.
+    fn integrate(filter: &dyn Filter) -> f32 {
+        let r = filter.radius();
+        let n = 400;
+        let (dx, dy) = (2.0 * r.x() / n as f32, 2.0 * r.y() / n as f32);
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let p = Point2::<f32>::elements(
+                    -r.x() + (i as f32 + 0.5) * dx,
+                    -r.y() + (j as f32 + 0.5) * dy,
+                );
+                sum += filter.evaluate(p);
+            }
+        }
+        sum * dx * dy
+    }
+
+    #[test]
+    fn test_integrals() {
+        for filter in filters() {
+            let (expected, numeric) = (filter.integral(), integrate(filter.as_ref()));
+            assert!(
+                (expected - numeric).abs() < 2e-3 * expected,
+                "{} vs {}",
+                expected,
+                numeric
+            );
+        }
+        // Closed forms.
+        let r = Vec2::<f32>::elements(1.5, 2.0);
+        assert_eq!(BoxFilter::new(r).integral(), 12.0);
+        assert_eq!(TriangleFilter::new(r).integral(), 9.0);
+        assert_eq!(
+            MitchellFilter::new(r, 1.0 / 3.0, 1.0 / 3.0).integral(),
+            0.75
+        );
+    }
+
+    #[test]
+    fn test_sampling() {
+        let mut rng = Rng::new(0, 4);
+        for filter in filters() {
+            let r = filter.radius();
+            let n = 100_000;
+            let mut sum = 0.0;
+            for _ in 0..n {
+                let u = Point2::<f32>::elements(rng.uniform_f32(), rng.uniform_f32());
+                let s = filter.sample(u);
+                assert!(s.p.x().abs() <= r.x() && s.p.y().abs() <= r.y());
+                sum += s.weight;
+            }
+            // Weights average out to the integral.
+            let mean = sum / n as f32;
+            assert!(
+                (mean - filter.integral()).abs() < 2e-2 * filter.integral(),
+                "{} vs {}",
+                mean,
+                filter.integral()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "filter radius must be positive")]
+    fn test_zero_radius() {
+        GaussianFilter::new(Vec2::<f32>::elements(1.0, 0.0), 0.5);
+    }
+}
@@ -0,0 +1,54 @@
+use crate::filter::*;
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+
+// A separable tent that reaches zero at the radius.
+#[derive(Copy, Clone, Debug)]
+pub struct TriangleFilter {
+    radius: Vec2<f32>,
+}
+
+impl TriangleFilter {
+    pub fn new(radius: Vec2<f32>) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for TriangleFilter {
+    fn default() -> Self {
+        Self::new(Vec2::<f32>::new(2.0))
+    }
+}
+
+// Samples x in [-r, r] with density proportional to r - |x|.
+fn sample_tent(u: f32, r: f32) -> f32 {
+    if u < 0.5 {
+        -r + r * (2.0 * u).sqrt()
+    } else {
+        r - r * (2.0 * (1.0 - u)).sqrt()
+    }
+}
+
+impl Filter for TriangleFilter {
+    fn radius(&self) -> Vec2<f32> {
+        self.radius
+    }
+
+    fn evaluate(&self, p: Point2<f32>) -> f32 {
+        (self.radius.x() - p.x().abs()).max(0.0) * (self.radius.y() - p.y().abs()).max(0.0)
+    }
+
+    fn integral(&self) -> f32 {
+        self.radius.x().powi(2) * self.radius.y().powi(2)
+    }
+
+    fn sample(&self, u: Point2<f32>) -> FilterSample {
+        FilterSample {
+            p: Point2::<f32>::elements(
+                sample_tent(u.x(), self.radius.x()),
+                sample_tent(u.y(), self.radius.y()),
+            ),
+            weight: self.integral(),
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use crate::geometry::point::*;
+use crate::geometry::ray::*;
 use crate::geometry::vector::*;
 
-struct Bounds<T, const N: usize>
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Bounds<T, const N: usize>
 where
     T: Numeric,
 {
@@ -24,6 +26,14 @@ where
         Self { p_min: p, p_max: p }
     }
 
+    pub fn p_min(&self) -> Point<T, N> {
+        self.p_min
+    }
+
+    pub fn p_max(&self) -> Point<T, N> {
+        self.p_max
+    }
+
     // Returns a new AABB that has been expanded to contain the
     // given point.
     pub fn union_with_point(&self, p: Point<T, N>) -> Self {
@@ -46,6 +56,119 @@ where
             p_max: Point::<T, N>::from_min_components(&self.p_max, &bb.p_max),
         }
     }
+
+    // True if p_min > p_max along any axis, e.g. for the default bounds or
+    // the intersection of two disjoint boxes.
+    pub fn is_empty(&self) -> bool {
+        (0..N).any(|i| self.p_min[i] > self.p_max[i])
+    }
+
+    pub fn overlaps(&self, bb: &Self) -> bool {
+        !self.intersect(bb).is_empty()
+    }
+
+    pub fn inside(&self, p: &Point<T, N>) -> bool {
+        (0..N).all(|i| p[i] >= self.p_min[i] && p[i] <= self.p_max[i])
+    }
+
+    pub fn diagonal(&self) -> Vector<T, N> {
+        self.p_max - self.p_min
+    }
+
+    pub fn maximum_extent(&self) -> usize {
+        self.diagonal().max_component().0
+    }
+}
+
+pub type Bounds2<T> = Bounds<T, 2>;
+pub type Bounds3<T> = Bounds<T, 3>;
+
+impl<T> Bounds2<T>
+where
+    T: Numeric,
+{
+    pub fn area(&self) -> T {
+        if self.is_empty() {
+            return T::default();
+        }
+        let d = self.diagonal();
+        d.x() * d.y()
+    }
+}
+
+impl<T> Bounds3<T>
+where
+    T: Numeric,
+{
+    pub fn surface_area(&self) -> T {
+        if self.is_empty() {
+            return T::default();
+        }
+        let d = self.diagonal();
+        let half = d.x() * d.y() + d.x() * d.z() + d.y() * d.z();
+        half + half
+    }
+
+    pub fn volume(&self) -> T {
+        if self.is_empty() {
+            return T::default();
+        }
+        let d = self.diagonal();
+        d.x() * d.y() * d.z()
+    }
+}
+
+impl<T> Bounds3<T>
+where
+    T: NumericFloat,
+{
+    // Splits a triangle at the plane p[axis] = pos and returns the bounds of
+    // the parts on either side, each clipped to this box. Spatial-split BVH
+    // builders call this with the current bounds of a triangle reference, so
+    // repeated splits keep tightening the reference instead of falling back
+    // to the full triangle bounds.
+    pub fn split_triangle(&self, v: &[Point3<T>; 3], axis: usize, pos: T) -> (Self, Self) {
+        let (mut left, mut right) = (Self::default(), Self::default());
+        for i in 0..3 {
+            let (a, b) = (v[i], v[(i + 1) % 3]);
+            if a[axis] <= pos {
+                left = left.union_with_point(a);
+            }
+            if a[axis] >= pos {
+                right = right.union_with_point(a);
+            }
+            if (a[axis] < pos && b[axis] > pos) || (a[axis] > pos && b[axis] < pos) {
+                let t = (pos - a[axis]) / (b[axis] - a[axis]);
+                let mut p = a.lerp(t, &b);
+                p[axis] = pos;
+                left = left.union_with_point(p);
+                right = right.union_with_point(p);
+            }
+        }
+        (left.intersect(self), right.intersect(self))
+    }
+
+    // Slab test against the ray segment [0, t_max]. Returns the parametric
+    // entry and exit distances if the ray overlaps the box.
+    pub fn intersect_ray(&self, ray: &Ray<T>) -> Option<(T, T)> {
+        let (mut t0, mut t1) = (T::default(), ray.t_max());
+        let (o, d) = (ray.origin(), ray.dir());
+        for i in 0..3 {
+            let inv_d = d[i].m_recip();
+            let mut t_near = (self.p_min[i] - o[i]) * inv_d;
+            let mut t_far = (self.p_max[i] - o[i]) * inv_d;
+            if t_near > t_far {
+                std::mem::swap(&mut t_near, &mut t_far);
+            }
+            // NaN slabs (origin on a slab plane of a flat axis) are ignored.
+            t0 = if t_near > t0 { t_near } else { t0 };
+            t1 = if t_far < t1 { t_far } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
 }
 
 impl<T, const N: usize> Default for Bounds<T, N>
@@ -60,3 +183,72 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intersect_ray() {
+        let bb = Bounds3::<f32>::new((-1.0, -1.0, -1.0).into(), (1.0, 1.0, 1.0).into());
+        let ray = Ray::<f32>::new((0.0, 0.0, -5.0).into(), (0.0, 0.0, 1.0).into());
+        assert_eq!(bb.intersect_ray(&ray), Some((4.0, 6.0)));
+
+        let ray = Ray::<f32>::new((0.0, 2.0, -5.0).into(), (0.0, 0.0, 1.0).into());
+        assert_eq!(bb.intersect_ray(&ray), None);
+
+        let mut ray = Ray::<f32>::new((0.0, 0.0, -5.0).into(), (0.0, 0.0, 1.0).into());
+        ray.set_t_max(3.0);
+        assert_eq!(bb.intersect_ray(&ray), None);
+    }
+
+    #[test]
+    fn test_measures() {
+        let bb = Bounds3::<f32>::new((0.0, 0.0, 0.0).into(), (1.0, 2.0, 3.0).into());
+        assert_eq!(bb.surface_area(), 22.0);
+        assert_eq!(bb.volume(), 6.0);
+        assert_eq!(bb.maximum_extent(), 2);
+        assert!(Bounds3::<f32>::default().is_empty());
+        assert_eq!(Bounds3::<f32>::default().surface_area(), 0.0);
+        assert_eq!(Bounds3::<f32>::default().volume(), 0.0);
+
+        let px = Bounds2::<i32>::new((0, 0).into(), (4, 3).into());
+        assert_eq!(px.area(), 12);
+        assert_eq!(Bounds2::<i32>::default().area(), 0);
+        let disjoint = Bounds2::<i32>::new((5, 5).into(), (6, 6).into());
+        assert_eq!(px.intersect(&disjoint).area(), 0);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let a = Bounds3::<f32>::new((0.0, 0.0, 0.0).into(), (1.0, 1.0, 1.0).into());
+        let b = Bounds3::<f32>::new((0.5, 0.5, 0.5).into(), (2.0, 2.0, 2.0).into());
+        let c = Bounds3::<f32>::new((1.5, 0.0, 0.0).into(), (2.0, 1.0, 1.0).into());
+        assert!(a.overlaps(&b));
+        assert!(!a.overlaps(&c));
+        assert!(a.inside(&(0.5, 0.5, 0.5).into()));
+        assert!(!a.inside(&(1.5, 0.5, 0.5).into()));
+    }
+
+    #[test]
+    fn test_split_triangle() {
+        // A long diagonal sliver whose full bounds are far larger than the
+        // triangle itself.
+        let v = [
+            Vec3::<f32>::elements(0.0, 0.0, 0.0),
+            Vec3::<f32>::elements(4.0, 4.0, 0.0),
+            Vec3::<f32>::elements(4.0, 4.0, 0.1),
+        ];
+        let full = Bounds3::<f32>::default()
+            .union_with_point(v[0])
+            .union_with_point(v[1])
+            .union_with_point(v[2]);
+
+        let (left, right) = full.split_triangle(&v, 0, 2.0);
+        assert_eq!(left.p_max().x(), 2.0);
+        assert_eq!(right.p_min().x(), 2.0);
+        assert_eq!(left.p_max().y(), 2.0);
+        assert_eq!(right.p_min().y(), 2.0);
+        assert!(left.surface_area() + right.surface_area() < full.surface_area());
+    }
+}
@@ -4,4 +4,9 @@ pub mod aabb;
 pub mod numeric;
 pub mod point;
 pub mod ray;
+pub mod ray_packet;
+pub mod transform;
+// The vector code indexes its fixed-size arrays directly.
+#[allow(clippy::needless_range_loop, clippy::unnecessary_cast)]
 pub mod vector;
+pub mod wide_bounds;
@@ -17,6 +17,7 @@ pub trait Numeric:
 {
     fn m_max_value() -> Self;
     fn m_min_value() -> Self;
+    fn m_one() -> Self;
     fn m_abs(self) -> Self;
     fn m_div_euclid(self, rhs: Self) -> Self;
     fn m_rem_euclid(self, rhs: Self) -> Self;
@@ -81,6 +82,9 @@ macro_rules! impl_numeric {
             fn m_min_value() -> Self {
                 $t::MIN
             }
+            fn m_one() -> Self {
+                1 as $t
+            }
             fn m_abs(self) -> Self {
                 self.abs()
             }
@@ -1,7 +1,8 @@
 use crate::geometry::point::*;
 use crate::geometry::vector::*;
 
-struct Ray<T>
+#[derive(Copy, Clone, Debug)]
+pub struct Ray<T>
 where
     T: NumericFloat,
 {
@@ -28,6 +29,30 @@ where
     pub fn at(&self, t: T) -> Point3<T> {
         self.origin + self.dir * t
     }
+
+    pub fn origin(&self) -> Point3<T> {
+        self.origin
+    }
+
+    pub fn dir(&self) -> Vec3<T> {
+        self.dir
+    }
+
+    pub fn t_max(&self) -> T {
+        self.t_max
+    }
+
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    pub fn set_t_max(&mut self, t_max: T) {
+        self.t_max = t_max;
+    }
+
+    pub fn set_time(&mut self, time: T) {
+        self.time = time;
+    }
 }
 
 impl<T> Default for Ray<T>
@@ -43,3 +68,40 @@ where
         }
     }
 }
+
+// A ray carrying two offset rays, one pixel over in x and y on the film, so
+// that texture lookups can estimate the footprint of a sample.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RayDifferential<T>
+where
+    T: NumericFloat,
+{
+    pub ray: Ray<T>,
+    pub has_differentials: bool,
+    pub rx_origin: Point3<T>,
+    pub ry_origin: Point3<T>,
+    pub rx_dir: Vec3<T>,
+    pub ry_dir: Vec3<T>,
+}
+
+impl<T> RayDifferential<T>
+where
+    T: NumericFloat,
+{
+    pub fn new(ray: Ray<T>) -> Self {
+        Self {
+            ray,
+            ..Default::default()
+        }
+    }
+
+    // Shrinks the offsets to the spacing of samples when each pixel takes
+    // several of them, e.g. s = 1 / sqrt(spp).
+    pub fn scale_differentials(&mut self, s: T) {
+        let (o, d) = (self.ray.origin(), self.ray.dir());
+        self.rx_origin = o + (self.rx_origin - o) * s;
+        self.ry_origin = o + (self.ry_origin - o) * s;
+        self.rx_dir = d + (self.rx_dir - d) * s;
+        self.ry_dir = d + (self.ry_dir - d) * s;
+    }
+}
@@ -0,0 +1,197 @@
+use crate::geometry::aabb::*;
+use crate::geometry::ray::*;
+use crate::geometry::vector::*;
+
+// W rays stored as structure-of-arrays with a bitmask of lanes that are
+// still being traced. Coherent rays (e.g. neighbouring primary camera rays)
+// visit the same nodes, so a traversal can fetch each node once and test it
+// against the whole packet.
+#[derive(Copy, Clone, Debug)]
+pub struct RayPacket<T, const W: usize>
+where
+    T: NumericFloat,
+{
+    origin: [[T; W]; 3],
+    dir: [[T; W]; 3],
+    inv_dir: [[T; W]; 3],
+
+    t_max: [T; W],
+    time: [T; W],
+    active: u32,
+}
+
+pub type RayPacket8<T> = RayPacket<T, 8>;
+pub type RayPacket16<T> = RayPacket<T, 16>;
+
+impl<T, const W: usize> RayPacket<T, W>
+where
+    T: NumericFloat,
+{
+    // The active mask has one bit per lane; checked at compile time for
+    // every packet width in use.
+    const LANES_FIT_MASK: () = assert!(W <= 32, "ray packets hold at most 32 lanes");
+
+    // Packs up to W rays into consecutive lanes; the remaining lanes start
+    // out inactive.
+    pub fn from_rays(rays: &[Ray<T>]) -> Self {
+        let () = Self::LANES_FIT_MASK;
+        assert!(rays.len() <= W);
+        let mut packet = Self {
+            origin: [[T::default(); W]; 3],
+            dir: [[T::default(); W]; 3],
+            inv_dir: [[T::default(); W]; 3],
+            t_max: [T::default(); W],
+            time: [T::default(); W],
+            active: 0,
+        };
+        for (lane, ray) in rays.iter().enumerate() {
+            packet.set(lane, ray);
+        }
+        packet
+    }
+
+    pub fn set(&mut self, lane: usize, ray: &Ray<T>) {
+        let (o, d) = (ray.origin(), ray.dir());
+        for axis in 0..3 {
+            self.origin[axis][lane] = o[axis];
+            self.dir[axis][lane] = d[axis];
+            self.inv_dir[axis][lane] = d[axis].m_recip();
+        }
+        self.t_max[lane] = ray.t_max();
+        self.time[lane] = ray.time();
+        self.active |= 1 << lane;
+    }
+
+    pub fn get(&self, lane: usize) -> Ray<T> {
+        let mut ray = Ray::<T>::new(
+            Vec3::<T>::elements(
+                self.origin[0][lane],
+                self.origin[1][lane],
+                self.origin[2][lane],
+            ),
+            Vec3::<T>::elements(self.dir[0][lane], self.dir[1][lane], self.dir[2][lane]),
+        );
+        ray.set_t_max(self.t_max[lane]);
+        ray.set_time(self.time[lane]);
+        ray
+    }
+
+    pub fn active(&self) -> u32 {
+        self.active
+    }
+
+    pub fn active_count(&self) -> u32 {
+        self.active.count_ones()
+    }
+
+    pub fn is_active(&self, lane: usize) -> bool {
+        self.active & (1 << lane) != 0
+    }
+
+    pub fn deactivate(&mut self, lane: usize) {
+        self.active &= !(1 << lane);
+    }
+
+    pub fn t_max(&self, lane: usize) -> T {
+        self.t_max[lane]
+    }
+
+    // Shrinks the segment of a lane after a closer hit has been found.
+    pub fn set_t_max(&mut self, lane: usize, t_max: T) {
+        self.t_max[lane] = t_max;
+    }
+
+    // A packet is coherent when all active rays point into the same octant,
+    // so they agree on the near/far child order at every node. Incoherent
+    // packets should be split up and traced one ray at a time.
+    pub fn is_coherent(&self) -> bool {
+        let mut octant = None;
+        for lane in (0..W).filter(|&lane| self.is_active(lane)) {
+            let mut lane_octant = 0;
+            for axis in 0..3 {
+                if self.dir[axis][lane] < T::default() {
+                    lane_octant |= 1 << axis;
+                }
+            }
+            match octant {
+                None => octant = Some(lane_octant),
+                Some(o) if o != lane_octant => return false,
+                _ => {}
+            }
+        }
+        true
+    }
+
+    // Iterates the active lanes as individual rays, the fallback path for
+    // packets that have lost coherence.
+    pub fn rays(&self) -> impl Iterator<Item = (usize, Ray<T>)> + '_ {
+        (0..W)
+            .filter(move |&lane| self.is_active(lane))
+            .map(move |lane| (lane, self.get(lane)))
+    }
+
+    // Tests every active ray against a single box, so a node is fetched once
+    // for the whole packet. Bit i of the returned mask is set if lane i hits.
+    pub fn intersect_bounds(&self, bb: &Bounds3<T>) -> u32 {
+        let (p_min, p_max) = (bb.p_min(), bb.p_max());
+        let mut t0 = [T::default(); W];
+        let mut t1 = self.t_max;
+        for axis in 0..3 {
+            let (lo, hi) = (p_min[axis], p_max[axis]);
+            let (o, inv_d) = (&self.origin[axis], &self.inv_dir[axis]);
+            for lane in 0..W {
+                let a = (lo - o[lane]) * inv_d[lane];
+                let b = (hi - o[lane]) * inv_d[lane];
+                let (t_near, t_far) = if a < b { (a, b) } else { (b, a) };
+                t0[lane] = if t_near > t0[lane] { t_near } else { t0[lane] };
+                t1[lane] = if t_far < t1[lane] { t_far } else { t1[lane] };
+            }
+        }
+
+        let mut mask = 0;
+        for lane in 0..W {
+            if t0[lane] <= t1[lane] {
+                mask |= 1 << lane;
+            }
+        }
+        mask & self.active
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packet_matches_single_rays() {
+        let bb = Bounds3::<f32>::new((-1.0, -1.0, 4.0).into(), (1.0, 1.0, 5.0).into());
+        let rays: Vec<Ray<f32>> = (0..6)
+            .map(|i| {
+                let x = i as f32 * 0.1;
+                Ray::<f32>::new((0.0, 0.0, 0.0).into(), (x, 0.0, 1.0).into())
+            })
+            .collect();
+        let packet = RayPacket8::<f32>::from_rays(&rays);
+
+        assert_eq!(packet.active_count(), 6);
+        assert!(packet.is_coherent());
+        let mask = packet.intersect_bounds(&bb);
+        assert_ne!(mask, 0);
+        assert_ne!(mask, packet.active());
+        for (lane, ray) in packet.rays() {
+            assert_eq!(bb.intersect_ray(&ray).is_some(), mask & (1 << lane) != 0);
+        }
+    }
+
+    #[test]
+    fn test_coherence() {
+        let mut packet = RayPacket8::<f32>::from_rays(&[
+            Ray::<f32>::new((0.0, 0.0, 0.0).into(), (1.0, 1.0, 1.0).into()),
+            Ray::<f32>::new((0.0, 0.0, 0.0).into(), (-1.0, 1.0, 1.0).into()),
+        ]);
+        assert!(!packet.is_coherent());
+        packet.deactivate(1);
+        assert!(packet.is_coherent());
+        assert_eq!(packet.rays().count(), 1);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::geometry::point::*;
+use crate::geometry::ray::*;
+use crate::geometry::vector::*;
+use std::ops;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Matrix4x4<T>
+where
+    T: NumericFloat,
+{
+    m: [[T; 4]; 4],
+}
+
+impl<T> Matrix4x4<T>
+where
+    T: NumericFloat,
+{
+    pub fn new(m: [[T; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        let mut m = [[T::default(); 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = T::m_one();
+        }
+        Self { m }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> T {
+        self.m[row][col]
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self {
+            m: std::array::from_fn(|i| std::array::from_fn(|j| self.m[j][i])),
+        }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. Returns None for
+    // singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let mut pivot = col;
+            for row in col + 1..4 {
+                if a[row][col].m_abs() > a[pivot][col].m_abs() {
+                    pivot = row;
+                }
+            }
+            if a[pivot][col] == T::default() {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = a[col][col].m_recip();
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self { m: inv })
+    }
+}
+
+impl<T> ops::Mul for Matrix4x4<T>
+where
+    T: NumericFloat,
+{
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        let mut m = [[T::default(); 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                for k in 0..4 {
+                    *v += self.m[i][k] * other.m[k][j];
+                }
+            }
+        }
+        Self { m }
+    }
+}
+
+// An affine or projective transformation. The inverse is kept alongside the
+// matrix so that inverting a transform never requires a matrix inversion.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform<T>
+where
+    T: NumericFloat,
+{
+    m: Matrix4x4<T>,
+    m_inv: Matrix4x4<T>,
+}
+
+impl<T> Transform<T>
+where
+    T: NumericFloat,
+{
+    pub fn new(m: Matrix4x4<T>, m_inv: Matrix4x4<T>) -> Self {
+        Self { m, m_inv }
+    }
+
+    pub fn from_matrix(m: Matrix4x4<T>) -> Option<Self> {
+        m.inverse().map(|m_inv| Self { m, m_inv })
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            m: Matrix4x4::<T>::identity(),
+            m_inv: Matrix4x4::<T>::identity(),
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4x4<T> {
+        &self.m
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.m_inv,
+            m_inv: self.m,
+        }
+    }
+
+    pub fn translate(delta: Vec3<T>) -> Self {
+        let (o, z) = (T::m_one(), T::default());
+        let m = Matrix4x4::<T>::new([
+            [o, z, z, delta.x()],
+            [z, o, z, delta.y()],
+            [z, z, o, delta.z()],
+            [z, z, z, o],
+        ]);
+        let m_inv = Matrix4x4::<T>::new([
+            [o, z, z, -delta.x()],
+            [z, o, z, -delta.y()],
+            [z, z, o, -delta.z()],
+            [z, z, z, o],
+        ]);
+        Self { m, m_inv }
+    }
+
+    pub fn scale(x: T, y: T, z: T) -> Self {
+        let (o, n) = (T::m_one(), T::default());
+        let m = Matrix4x4::<T>::new([[x, n, n, n], [n, y, n, n], [n, n, z, n], [n, n, n, o]]);
+        let m_inv = Matrix4x4::<T>::new([
+            [x.m_recip(), n, n, n],
+            [n, y.m_recip(), n, n],
+            [n, n, z.m_recip(), n],
+            [n, n, n, o],
+        ]);
+        Self { m, m_inv }
+    }
+
+    // Rotation by theta degrees about an arbitrary axis.
+    pub fn rotate(theta: T, axis: Vec3<T>) -> Self {
+        let a = axis.normalized();
+        let (sin_theta, cos_theta) = theta.m_to_radians().m_sin_cos();
+        let (o, z) = (T::m_one(), T::default());
+        let (ax, ay, az) = (a.x(), a.y(), a.z());
+        let m = Matrix4x4::<T>::new([
+            [
+                ax * ax + (o - ax * ax) * cos_theta,
+                ax * ay * (o - cos_theta) - az * sin_theta,
+                ax * az * (o - cos_theta) + ay * sin_theta,
+                z,
+            ],
+            [
+                ax * ay * (o - cos_theta) + az * sin_theta,
+                ay * ay + (o - ay * ay) * cos_theta,
+                ay * az * (o - cos_theta) - ax * sin_theta,
+                z,
+            ],
+            [
+                ax * az * (o - cos_theta) - ay * sin_theta,
+                ay * az * (o - cos_theta) + ax * sin_theta,
+                az * az + (o - az * az) * cos_theta,
+                z,
+            ],
+            [z, z, z, o],
+        ]);
+        Self {
+            m,
+            m_inv: m.transpose(),
+        }
+    }
+
+    // Camera-to-world transform of a camera at pos looking towards look.
+    // The camera looks down +z with +y up. Returns None if pos and look
+    // coincide or up is parallel to the view direction.
+    pub fn look_at(pos: Point3<T>, look: Point3<T>, up: Vec3<T>) -> Option<Self> {
+        if (look - pos).mag2() == T::default() {
+            return None;
+        }
+        let dir = (look - pos).normalized();
+        let right = up.normalized().cross(&dir);
+        if right.mag2() == T::default() {
+            return None;
+        }
+        let right = right.normalized();
+        let new_up = dir.cross(&right);
+        let finite = |v: Vec3<T>| v.x().m_is_finite() && v.y().m_is_finite() && v.z().m_is_finite();
+        if !(finite(dir) && finite(right) && finite(new_up)) {
+            return None;
+        }
+        let (o, z) = (T::m_one(), T::default());
+        let camera_to_world = Matrix4x4::<T>::new([
+            [right.x(), new_up.x(), dir.x(), pos.x()],
+            [right.y(), new_up.y(), dir.y(), pos.y()],
+            [right.z(), new_up.z(), dir.z(), pos.z()],
+            [z, z, z, o],
+        ]);
+        Self::from_matrix(camera_to_world)
+    }
+
+    // Projects camera space onto the z = 1 plane scaled so that a field of
+    // view of fov degrees maps to [-1, 1], with depth remapped so that
+    // z = near goes to 0 and z = far goes to 1. Returns None if near is zero
+    // or equal to far.
+    pub fn perspective(fov: T, near: T, far: T) -> Option<Self> {
+        let (o, z) = (T::m_one(), T::default());
+        if near == z || near == far {
+            return None;
+        }
+        let persp = Matrix4x4::<T>::new([
+            [o, z, z, z],
+            [z, o, z, z],
+            [z, z, far / (far - near), -far * near / (far - near)],
+            [z, z, o, z],
+        ]);
+        let two = o + o;
+        let inv_tan = (fov.m_to_radians() / two).m_tan().m_recip();
+        Some(Self::scale(inv_tan, inv_tan, o) * Self::from_matrix(persp)?)
+    }
+
+    pub fn orthographic(near: T, far: T) -> Self {
+        let o = T::m_one();
+        Self::scale(o, o, (far - near).m_recip())
+            * Self::translate(Vec3::<T>::elements(T::default(), T::default(), -near))
+    }
+
+    pub fn apply_point(&self, p: &Point3<T>) -> Point3<T> {
+        let m = &self.m.m;
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let xp = m[0][0] * x + m[0][1] * y + m[0][2] * z + m[0][3];
+        let yp = m[1][0] * x + m[1][1] * y + m[1][2] * z + m[1][3];
+        let zp = m[2][0] * x + m[2][1] * y + m[2][2] * z + m[2][3];
+        let wp = m[3][0] * x + m[3][1] * y + m[3][2] * z + m[3][3];
+        if wp == T::m_one() {
+            Point3::<T>::elements(xp, yp, zp)
+        } else {
+            Point3::<T>::elements(xp, yp, zp) / wp
+        }
+    }
+
+    pub fn apply_vector(&self, v: &Vec3<T>) -> Vec3<T> {
+        let m = &self.m.m;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+        Vec3::<T>::elements(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            m[2][0] * x + m[2][1] * y + m[2][2] * z,
+        )
+    }
+
+    pub fn apply_ray(&self, r: &Ray<T>) -> Ray<T> {
+        let mut ray = Ray::<T>::new(self.apply_point(&r.origin()), self.apply_vector(&r.dir()));
+        ray.set_t_max(r.t_max());
+        ray.set_time(r.time());
+        ray
+    }
+
+    pub fn apply_ray_differential(&self, r: &RayDifferential<T>) -> RayDifferential<T> {
+        RayDifferential::<T> {
+            ray: self.apply_ray(&r.ray),
+            has_differentials: r.has_differentials,
+            rx_origin: self.apply_point(&r.rx_origin),
+            ry_origin: self.apply_point(&r.ry_origin),
+            rx_dir: self.apply_vector(&r.rx_dir),
+            ry_dir: self.apply_vector(&r.ry_dir),
+        }
+    }
+}
+
+impl<T> ops::Mul for Transform<T>
+where
+    T: NumericFloat,
+{
+    type Output = Self;
+
+    // Composition: (a * b) applies b first, then a.
+    fn mul(self, other: Self) -> Self::Output {
+        Self {
+            m: self.m * other.m,
+            m_inv: other.m_inv * self.m_inv,
+        }
+    }
+}
+
+impl<T> Default for Transform<T>
+where
+    T: NumericFloat,
+{
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(a: Vec3<f32>, b: Vec3<f32>) {
+        assert!(a.distance_to(&b) < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let t = Transform::<f32>::translate((1.0, 2.0, 3.0).into())
+            * Transform::<f32>::rotate(30.0, (1.0, 1.0, 0.0).into())
+            * Transform::<f32>::scale(2.0, 3.0, 4.0);
+        let inv = t.matrix().inverse().unwrap();
+        let product = *t.matrix() * inv;
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product.get(i, j) - expected).abs() < 1e-5);
+            }
+        }
+
+        let p = Vec3::<f32>::elements(0.5, -1.0, 2.0);
+        assert_close(t.inverse().apply_point(&t.apply_point(&p)), p);
+        assert!(Matrix4x4::<f32>::new([[0.0; 4]; 4]).inverse().is_none());
+    }
+
+    #[test]
+    fn test_apply() {
+        let t = Transform::<f32>::translate((1.0, 0.0, 0.0).into());
+        assert_close(
+            t.apply_point(&(0.0, 0.0, 0.0).into()),
+            (1.0, 0.0, 0.0).into(),
+        );
+        assert_close(
+            t.apply_vector(&(0.0, 1.0, 0.0).into()),
+            (0.0, 1.0, 0.0).into(),
+        );
+
+        let r = Transform::<f32>::rotate(90.0, (0.0, 0.0, 1.0).into());
+        assert_close(
+            r.apply_vector(&(1.0, 0.0, 0.0).into()),
+            (0.0, 1.0, 0.0).into(),
+        );
+    }
+
+    #[test]
+    fn test_look_at() {
+        let t = Transform::<f32>::look_at(
+            (0.0, 0.0, -5.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+        )
+        .unwrap();
+        assert_close(
+            t.apply_point(&(0.0, 0.0, 0.0).into()),
+            (0.0, 0.0, -5.0).into(),
+        );
+        assert_close(
+            t.apply_vector(&(0.0, 0.0, 1.0).into()),
+            (0.0, 0.0, 1.0).into(),
+        );
+        assert_close(
+            t.apply_vector(&(1.0, 0.0, 0.0).into()),
+            (1.0, 0.0, 0.0).into(),
+        );
+
+        let p = Point3::<f32>::elements(1.0, 2.0, 3.0);
+        let up = Vec3::<f32>::elements(0.0, 1.0, 0.0);
+        assert!(Transform::<f32>::look_at(p, p, up).is_none());
+        assert!(Transform::<f32>::look_at(p, (1.0, 5.0, 3.0).into(), up).is_none());
+        let far = Point3::<f32>::elements(f32::INFINITY, 0.0, 0.0);
+        assert!(Transform::<f32>::look_at(p, far, up).is_none());
+    }
+
+    #[test]
+    fn test_perspective() {
+        let p = Transform::<f32>::perspective(90.0, 1.0, 10.0).unwrap();
+        assert_close(
+            p.apply_point(&(1.0, 1.0, 1.0).into()),
+            (1.0, 1.0, 0.0).into(),
+        );
+        assert_close(
+            p.apply_point(&(10.0, 0.0, 10.0).into()),
+            (1.0, 0.0, 1.0).into(),
+        );
+        assert!(Transform::<f32>::perspective(90.0, 0.0, 10.0).is_none());
+        assert!(Transform::<f32>::perspective(90.0, 2.0, 2.0).is_none());
+    }
+}
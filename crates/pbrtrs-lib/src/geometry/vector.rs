@@ -148,6 +148,26 @@ where
     }
 }
 
+impl<T, const N: usize> ops::Index<usize> for Vector<T, N>
+where
+    T: Numeric,
+{
+    type Output = T;
+
+    fn index(&self, i: usize) -> &Self::Output {
+        &self.data[i]
+    }
+}
+
+impl<T, const N: usize> ops::IndexMut<usize> for Vector<T, N>
+where
+    T: Numeric,
+{
+    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
+        &mut self.data[i]
+    }
+}
+
 impl<T, const N: usize> ops::Add for Vector<T, N>
 where
     T: Numeric,
@@ -0,0 +1,143 @@
+use crate::geometry::aabb::*;
+use crate::geometry::ray::*;
+use crate::geometry::vector::*;
+
+// Structure-of-arrays storage for the child bounds of a W-wide node. Every
+// axis keeps its W slab planes contiguous so that the slab test below runs
+// the same arithmetic over all lanes at once, which the compiler lowers to
+// packed SIMD instructions for W = 4 and W = 8.
+#[derive(Copy, Clone, Debug)]
+pub struct WideBounds<T, const W: usize>
+where
+    T: NumericFloat,
+{
+    p_min: [[T; W]; 3],
+    p_max: [[T; W]; 3],
+}
+
+pub type WideBounds4<T> = WideBounds<T, 4>;
+pub type WideBounds8<T> = WideBounds<T, 8>;
+
+impl<T, const W: usize> WideBounds<T, W>
+where
+    T: NumericFloat,
+{
+    // The hit mask has one bit per lane; checked at compile time for every
+    // width in use.
+    const LANES_FIT_MASK: () = assert!(W <= 32, "wide bounds hold at most 32 lanes");
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Packs up to W bounds into consecutive lanes. Unused lanes stay empty
+    // and never report a hit.
+    pub fn from_bounds(bounds: &[Bounds3<T>]) -> Self {
+        assert!(bounds.len() <= W);
+        let mut wide = Self::default();
+        for (lane, bb) in bounds.iter().enumerate() {
+            wide.set(lane, bb);
+        }
+        wide
+    }
+
+    pub fn set(&mut self, lane: usize, bb: &Bounds3<T>) {
+        let (p_min, p_max) = (bb.p_min(), bb.p_max());
+        for axis in 0..3 {
+            self.p_min[axis][lane] = p_min[axis];
+            self.p_max[axis][lane] = p_max[axis];
+        }
+    }
+
+    // Empty lanes give empty bounds.
+    pub fn get(&self, lane: usize) -> Bounds3<T> {
+        if !self.is_occupied(lane) {
+            return Bounds3::<T>::default();
+        }
+        Bounds3::<T>::new(
+            Vec3::<T>::elements(
+                self.p_min[0][lane],
+                self.p_min[1][lane],
+                self.p_min[2][lane],
+            ),
+            Vec3::<T>::elements(
+                self.p_max[0][lane],
+                self.p_max[1][lane],
+                self.p_max[2][lane],
+            ),
+        )
+    }
+
+    // Tests the ray against all W boxes at once. Bit i of the returned mask
+    // is set if lane i is hit; the array holds the entry distance per lane
+    // so that callers can visit children front to back.
+    pub fn intersect_ray(&self, ray: &Ray<T>) -> (u32, [T; W]) {
+        let () = Self::LANES_FIT_MASK;
+        let (o, d) = (ray.origin(), ray.dir());
+        let inv_d = Vec3::<T>::elements(d.x().m_recip(), d.y().m_recip(), d.z().m_recip());
+
+        let mut t0 = [T::default(); W];
+        let mut t1 = [ray.t_max(); W];
+        for axis in 0..3 {
+            let (o, inv_d) = (o[axis], inv_d[axis]);
+            let (mins, maxs) = (&self.p_min[axis], &self.p_max[axis]);
+            for lane in 0..W {
+                let a = (mins[lane] - o) * inv_d;
+                let b = (maxs[lane] - o) * inv_d;
+                let (t_near, t_far) = if a < b { (a, b) } else { (b, a) };
+                t0[lane] = if t_near > t0[lane] { t_near } else { t0[lane] };
+                t1[lane] = if t_far < t1[lane] { t_far } else { t1[lane] };
+            }
+        }
+
+        let mut mask = 0;
+        for lane in 0..W {
+            if self.is_occupied(lane) && t0[lane] <= t1[lane] {
+                mask |= 1 << lane;
+            }
+        }
+        (mask, t0)
+    }
+
+    fn is_occupied(&self, lane: usize) -> bool {
+        self.p_min[0][lane] <= self.p_max[0][lane]
+    }
+}
+
+impl<T, const W: usize> Default for WideBounds<T, W>
+where
+    T: NumericFloat,
+{
+    fn default() -> Self {
+        // Empty lanes have inverted slabs and are masked out after the test.
+        Self {
+            p_min: [[T::m_max_value(); W]; 3],
+            p_max: [[T::m_min_value(); W]; 3],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_scalar() {
+        let boxes = [
+            Bounds3::<f32>::new((-1.0, -1.0, 2.0).into(), (1.0, 1.0, 3.0).into()),
+            Bounds3::<f32>::new((2.0, 2.0, 2.0).into(), (3.0, 3.0, 3.0).into()),
+            Bounds3::<f32>::new((-1.0, -1.0, -3.0).into(), (1.0, 1.0, -2.0).into()),
+        ];
+        let wide = WideBounds4::<f32>::from_bounds(&boxes);
+        let ray = Ray::<f32>::new((0.0, 0.0, 0.0).into(), (0.0, 0.0, 1.0).into());
+
+        let (mask, t_near) = wide.intersect_ray(&ray);
+        assert_eq!(mask, 0b0001);
+        assert_eq!(t_near[0], 2.0);
+        for (lane, bb) in boxes.iter().enumerate() {
+            assert_eq!(bb.intersect_ray(&ray).is_some(), mask & (1 << lane) != 0);
+            assert_eq!(wide.get(lane), *bb);
+        }
+        assert!(wide.get(3).is_empty());
+    }
+}
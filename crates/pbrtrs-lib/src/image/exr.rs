@@ -0,0 +1,653 @@
+use crate::geometry::transform::*;
+use crate::image::zlib::*;
+use crate::image::*;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+const MAGIC: u32 = 20_000_630;
+const VERSION: u32 = 2;
+// Version flag for attribute and channel names longer than 31 bytes.
+const LONG_NAMES: u32 = 0x400;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PixelType {
+    Uint = 0,
+    Half = 1,
+    Float = 2,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            _ => 4,
+        }
+    }
+
+    fn from_i32(v: i32) -> Option<Self> {
+        match v {
+            0 => Some(PixelType::Uint),
+            1 => Some(PixelType::Half),
+            2 => Some(PixelType::Float),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    // Deflate over blocks of 16 scanlines.
+    Zip,
+}
+
+impl Compression {
+    fn lines_per_block(self) -> usize {
+        match self {
+            Compression::None => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WriteOptions {
+    compression: Compression,
+    pixel_type: PixelType,
+    // Overrides of pixel_type for individual channels.
+    channel_types: Vec<(String, PixelType)>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            compression: Compression::Zip,
+            pixel_type: PixelType::Half,
+            channel_types: Vec::new(),
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_pixel_type(mut self, pixel_type: PixelType) -> Self {
+        self.pixel_type = pixel_type;
+        self
+    }
+
+    // E.g. full floats for depth or integers for object IDs, which half
+    // floats cannot hold exactly.
+    pub fn with_channel_type(mut self, channel: &str, pixel_type: PixelType) -> Self {
+        self.channel_types.push((channel.to_string(), pixel_type));
+        self
+    }
+
+    fn channel_type(&self, channel: &str) -> PixelType {
+        self.channel_types
+            .iter()
+            .rev()
+            .find(|(c, _)| c == channel)
+            .map_or(self.pixel_type, |&(_, t)| t)
+    }
+}
+
+// Rounds to the nearest half float, ties to even, with overflow going to
+// infinity and tiny values to subnormals or zero.
+pub fn f32_to_half(f: f32) -> u16 {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 {
+            0x200 | (mantissa >> 13) as u16
+        } else {
+            0
+        };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (mut h, rest, half_way) = if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+        (m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1))
+    } else {
+        (
+            ((e as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1fff,
+            0x1000,
+        )
+    };
+    // A carry out of the mantissa correctly bumps the exponent.
+    if rest > half_way || (rest == half_way && h & 1 == 1) {
+        h += 1;
+    }
+    sign | h as u16
+}
+
+pub fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h & 0x8000) as u32) << 16;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+    match exponent {
+        0 => {
+            let v = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -v
+            } else {
+                v
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+// The ZIP predictor: bytes are split into even and odd halves and then
+// replaced by their differences, which deflate compresses better.
+fn zip_encode(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut t = vec![0; raw.len()];
+    for (i, &b) in raw.iter().enumerate() {
+        t[if i % 2 == 0 { i / 2 } else { half + i / 2 }] = b;
+    }
+    for i in (1..t.len()).rev() {
+        t[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+    }
+    t
+}
+
+fn zip_decode(mut t: Vec<u8>) -> Vec<u8> {
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len().div_ceil(2);
+    (0..t.len())
+        .map(|i| t[if i % 2 == 0 { i / 2 } else { half + i / 2 }])
+        .collect()
+}
+
+fn put_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend(name.as_bytes());
+    out.push(0);
+    out.extend(kind.as_bytes());
+    out.push(0);
+    out.extend(&(value.len() as i32).to_le_bytes());
+    out.extend(value);
+}
+
+fn f32s_to_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn matrix_to_bytes(m: &Matrix4x4<f32>) -> Vec<u8> {
+    let values: Vec<f32> = (0..16).map(|i| m.get(i / 4, i % 4)).collect();
+    f32s_to_bytes(&values)
+}
+
+// Writes a scanline OpenEXR file with every channel of the image, plus the
+// metadata as the standard worldToCamera, worldToNDC and renderTimeSeconds
+// attributes and string attributes.
+pub fn write(
+    image: &Image,
+    metadata: &ImageMetadata,
+    options: &WriteOptions,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    // Channels are stored in name order.
+    let mut channels: Vec<(usize, &String, PixelType)> = image
+        .channel_names()
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (i, name, options.channel_type(name)))
+        .collect();
+    channels.sort_by(|a, b| a.1.cmp(b.1));
+    let (width, height) = (image.width(), image.height());
+
+    let long_names = channels
+        .iter()
+        .map(|(_, name, _)| name.as_str())
+        .chain(metadata.strings.iter().map(|(key, _)| key.as_str()))
+        .any(|name| name.len() > 31);
+    let version = if long_names {
+        VERSION | LONG_NAMES
+    } else {
+        VERSION
+    };
+
+    let mut header = Vec::new();
+    header.extend(&MAGIC.to_le_bytes());
+    header.extend(&version.to_le_bytes());
+    let mut chlist = Vec::new();
+    for (_, name, pixel_type) in &channels {
+        chlist.extend(name.as_bytes());
+        chlist.push(0);
+        chlist.extend(&(*pixel_type as i32).to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling.
+        chlist.extend(&[0, 0, 0, 0]);
+        chlist.extend(&1i32.to_le_bytes());
+        chlist.extend(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    put_attribute(&mut header, "channels", "chlist", &chlist);
+    let compression = match options.compression {
+        Compression::None => 0,
+        Compression::Zip => 3,
+    };
+    put_attribute(&mut header, "compression", "compression", &[compression]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    put_attribute(&mut header, "dataWindow", "box2i", &window);
+    put_attribute(&mut header, "displayWindow", "box2i", &window);
+    put_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    put_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &f32s_to_bytes(&[1.0]),
+    );
+    put_attribute(
+        &mut header,
+        "screenWindowCenter",
+        "v2f",
+        &f32s_to_bytes(&[0.0, 0.0]),
+    );
+    put_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &f32s_to_bytes(&[1.0]),
+    );
+    if let Some(m) = &metadata.camera_from_world {
+        put_attribute(&mut header, "worldToCamera", "m44f", &matrix_to_bytes(m));
+    }
+    if let Some(m) = &metadata.ndc_from_world {
+        put_attribute(&mut header, "worldToNDC", "m44f", &matrix_to_bytes(m));
+    }
+    if let Some(t) = metadata.render_time_seconds {
+        put_attribute(
+            &mut header,
+            "renderTimeSeconds",
+            "float",
+            &f32s_to_bytes(&[t]),
+        );
+    }
+    for (key, value) in &metadata.strings {
+        put_attribute(&mut header, key, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    let lines = options.compression.lines_per_block();
+    let mut blocks = Vec::new();
+    for y0 in (0..height).step_by(lines) {
+        let mut raw = Vec::new();
+        for y in y0..(y0 + lines).min(height) {
+            for &(c, _, pixel_type) in &channels {
+                for x in 0..width {
+                    let v = image.get_channel((x as i32, y as i32).into(), c);
+                    match pixel_type {
+                        PixelType::Half => raw.extend(&f32_to_half(v).to_le_bytes()),
+                        PixelType::Float => raw.extend(&v.to_le_bytes()),
+                        PixelType::Uint => raw.extend(&(v.max(0.0).round() as u32).to_le_bytes()),
+                    }
+                }
+            }
+        }
+        if options.compression == Compression::Zip {
+            // Blocks that do not shrink are stored as they are, which
+            // readers recognize by their size.
+            let packed = compress(&zip_encode(&raw));
+            if packed.len() < raw.len() {
+                raw = packed;
+            }
+        }
+        let mut block = (y0 as i32).to_le_bytes().to_vec();
+        block.extend(&(raw.len() as i32).to_le_bytes());
+        block.extend(raw);
+        blocks.push(block);
+    }
+
+    // The offset table holds the file position of every block.
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    for block in &blocks {
+        header.extend(&offset.to_le_bytes());
+        offset += block.len() as u64;
+    }
+    w.write_all(&header)?;
+    for block in &blocks {
+        w.write_all(block)?;
+    }
+    w.flush()
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let b = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| invalid_data("truncated EXR file"))?;
+        self.pos += n;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    // A byte count, which must not be negative.
+    fn size(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative EXR size"))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let b = self.bytes(8)?;
+        let mut v = [0; 8];
+        v.copy_from_slice(b);
+        Ok(u64::from_le_bytes(v))
+    }
+
+    // A null-terminated string.
+    fn string(&mut self) -> io::Result<String> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid_data("truncated EXR file"))?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+
+    fn matrix(&mut self) -> io::Result<Matrix4x4<f32>> {
+        let mut m = [[0.0; 4]; 4];
+        for row in m.iter_mut() {
+            for v in row.iter_mut() {
+                *v = self.f32()?;
+            }
+        }
+        Ok(Matrix4x4::new(m))
+    }
+}
+
+// Reads single-part scanline files that are uncompressed or use ZIP or ZIPS
+// compression. Channels come back in the file's (name) order.
+pub fn read(r: &mut impl Read) -> io::Result<(Image, ImageMetadata)> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    let mut cur = Cursor {
+        data: &data,
+        pos: 0,
+    };
+    if cur.u32()? != MAGIC {
+        return Err(invalid_data("not an OpenEXR file"));
+    }
+    let version = cur.u32()?;
+    // Tiled, deep and multi-part flags. Long names are read like any other.
+    if version & 0xff != VERSION || version & 0x1a00 != 0 {
+        return Err(invalid_data("only scanline OpenEXR files are supported"));
+    }
+
+    let mut channels: Vec<(String, PixelType)> = Vec::new();
+    let mut compression = None;
+    let mut window = None;
+    let mut metadata = ImageMetadata::default();
+    loop {
+        let name = cur.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = cur.string()?;
+        let size = cur.size()?;
+        let start = cur.pos;
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let channel = cur.string()?;
+                if channel.is_empty() {
+                    break;
+                }
+                let pixel_type = PixelType::from_i32(cur.i32()?)
+                    .ok_or_else(|| invalid_data("bad EXR pixel type"))?;
+                cur.bytes(4)?;
+                if cur.i32()? != 1 || cur.i32()? != 1 {
+                    return Err(invalid_data("subsampled EXR channels are not supported"));
+                }
+                channels.push((channel, pixel_type));
+            },
+            ("compression", _) => {
+                // Scanlines per block, and whether they are deflated.
+                compression = Some(match cur.u8()? {
+                    0 => (1, false),
+                    2 => (1, true),
+                    3 => (16, true),
+                    _ => return Err(invalid_data("unsupported EXR compression")),
+                })
+            }
+            ("dataWindow", _) => window = Some([cur.i32()?, cur.i32()?, cur.i32()?, cur.i32()?]),
+            ("worldToCamera", "m44f") => metadata.camera_from_world = Some(cur.matrix()?),
+            ("worldToNDC", "m44f") => metadata.ndc_from_world = Some(cur.matrix()?),
+            ("renderTimeSeconds", "float") => metadata.render_time_seconds = Some(cur.f32()?),
+            (_, "string") => {
+                let value = String::from_utf8_lossy(cur.bytes(size)?).into_owned();
+                metadata.strings.push((name, value));
+            }
+            _ => {}
+        }
+        cur.pos = start + size;
+    }
+    let (lines, compressed) =
+        compression.ok_or_else(|| invalid_data("EXR file has no compression"))?;
+    let [x_min, y_min, x_max, y_max] =
+        window.ok_or_else(|| invalid_data("EXR file has no data window"))?;
+    if x_max < x_min || y_max < y_min || channels.is_empty() {
+        return Err(invalid_data("empty EXR image"));
+    }
+    let extent = |min: i32, max: i32| i32::try_from(max as i64 - min as i64 + 1).ok();
+    let resolution = match (extent(x_min, x_max), extent(y_min, y_max)) {
+        // Four bytes per value at most.
+        (Some(w), Some(h)) if image_len((w, h).into(), 4 * channels.len()).is_some() => {
+            Point2::<i32>::elements(w, h)
+        }
+        _ => return Err(invalid_data("EXR image is too large")),
+    };
+    let (width, height) = (resolution.x() as usize, resolution.y() as usize);
+
+    let names: Vec<&str> = channels.iter().map(|(n, _)| n.as_str()).collect();
+    let mut image = Image::new(resolution, &names);
+    let line_size: usize = channels.iter().map(|(_, t)| t.size() * width).sum();
+    let n_blocks = height.div_ceil(lines);
+    let offsets: Vec<u64> = (0..n_blocks)
+        .map(|_| cur.u64())
+        .collect::<io::Result<_>>()?;
+    for offset in offsets {
+        cur.pos = offset as usize;
+        let y0 = cur.i32()? as i64 - y_min as i64;
+        let size = cur.size()?;
+        if y0 < 0 || y0 as usize >= height {
+            return Err(invalid_data("EXR block out of range"));
+        }
+        let y0 = y0 as usize;
+        let n_lines = lines.min(height - y0);
+        let expected = n_lines * line_size;
+        let block = cur.bytes(size)?;
+        let raw = if compressed && size < expected {
+            zip_decode(decompress(block)?)
+        } else {
+            block.to_vec()
+        };
+        if raw.len() != expected {
+            return Err(invalid_data("bad EXR block size"));
+        }
+        let mut values = Cursor { data: &raw, pos: 0 };
+        for y in y0..y0 + n_lines {
+            for (c, &(_, pixel_type)) in channels.iter().enumerate() {
+                for x in 0..width {
+                    let v = match pixel_type {
+                        PixelType::Half => {
+                            let b = values.bytes(2)?;
+                            half_to_f32(u16::from_le_bytes([b[0], b[1]]))
+                        }
+                        PixelType::Float => values.f32()?,
+                        PixelType::Uint => values.u32()? as f32,
+                    };
+                    image.set_channel((x as i32, y as i32).into(), c, v);
+                }
+            }
+        }
+    }
+    Ok((image, metadata))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_half() {
+        for &(f, h) in &[
+            (0.0, 0x0000),
+            (-0.0, 0x8000),
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            (65504.0, 0x7bff),
+            (65520.0, 0x7c00),
+            (f32::INFINITY, 0x7c00),
+            (6.103_515_6e-5, 0x0400),
+            (5.960_464_5e-8, 0x0001),
+            (2.0e-8, 0x0000),
+            // Halfway between 1 and the next half rounds to even.
+            (1.000_488_3, 0x3c00),
+            (1.001_464_8, 0x3c02),
+        ] {
+            assert_eq!(f32_to_half(f), h, "{}", f);
+        }
+        assert!(half_to_f32(f32_to_half(f32::NAN)).is_nan());
+        for h in 0..0x7c00u16 {
+            assert_eq!(f32_to_half(half_to_f32(h)), h);
+            assert_eq!(f32_to_half(-half_to_f32(h)), h | 0x8000);
+        }
+    }
+
+    fn test_image() -> Image {
+        let mut image = Image::new((19, 37).into(), &["R", "G", "B", "A", "Z", "id"]);
+        for y in 0..37 {
+            for x in 0..19 {
+                let p = (x, y).into();
+                image.set_rgb(p, Rgb::new(x as f32 / 8.0, y as f32 / 16.0, 0.25));
+                image.set_channel(p, 3, 1.0);
+                image.set_channel(p, 4, 1000.0 + x as f32 * 0.001 + y as f32);
+                image.set_channel(p, 5, (x * 100_000 + y) as f32);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = test_image();
+        let mut metadata = ImageMetadata {
+            render_time_seconds: Some(12.5),
+            camera_from_world: Some(Matrix4x4::new([
+                [1.0, 2.0, 3.0, 4.0],
+                [0.0, 1.0, 0.0, -2.5],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ])),
+            ndc_from_world: Some(Matrix4x4::identity()),
+            strings: Vec::new(),
+        };
+        metadata
+            .strings
+            .push(("renderer".to_string(), "pbrtrs".to_string()));
+
+        for &compression in &[Compression::None, Compression::Zip] {
+            let options = WriteOptions::default()
+                .with_compression(compression)
+                .with_channel_type("Z", PixelType::Float)
+                .with_channel_type("id", PixelType::Uint);
+            let mut buf = Vec::new();
+            write(&image, &metadata, &options, &mut buf).unwrap();
+            let (read_back, read_metadata) = read(&mut &buf[..]).unwrap();
+            assert_eq!(read_metadata, metadata);
+            assert_eq!(read_back.channel_names(), ["A", "B", "G", "R", "Z", "id"]);
+            // Half floats hold these colors exactly; Z and id are stored at
+            // full precision.
+            let reordered = image.select_channels(&["A", "B", "G", "R", "Z", "id"]);
+            assert_eq!(read_back, reordered.unwrap());
+        }
+
+        let mut buf = Vec::new();
+        let options = WriteOptions::default().with_compression(Compression::None);
+        write(&image, &metadata, &options, &mut buf).unwrap();
+        let mut zipped = Vec::new();
+        write(&image, &metadata, &WriteOptions::default(), &mut zipped).unwrap();
+        assert!(zipped.len() < buf.len());
+        assert!(read(&mut &buf[..8]).is_err());
+    }
+
+    #[test]
+    fn test_long_names() {
+        let name = "light.a_rather_long_light_name.R";
+        let image = Image::new((2, 2).into(), &["R", name]);
+        let mut buf = Vec::new();
+        let metadata = ImageMetadata::default();
+        write(&image, &metadata, &WriteOptions::default(), &mut buf).unwrap();
+        assert_eq!(buf[4..8], (VERSION | LONG_NAMES).to_le_bytes());
+        assert_eq!(read(&mut &buf[..]).unwrap().0.channel_names()[1], name);
+
+        let image = Image::new((2, 2).into(), &["R"]);
+        let mut buf = Vec::new();
+        write(&image, &metadata, &WriteOptions::default(), &mut buf).unwrap();
+        assert_eq!(buf[4..8], VERSION.to_le_bytes());
+    }
+
+    #[test]
+    fn test_bad_files() {
+        // One block holding a single half.
+        let image = Image::new((1, 1).into(), &["Y"]);
+        let options = WriteOptions::default().with_compression(Compression::None);
+        let mut buf = Vec::new();
+        write(&image, &ImageMetadata::default(), &options, &mut buf).unwrap();
+        let (offset, block_size) = (buf.len() - 18, buf.len() - 6);
+        assert!(read(&mut &buf[..]).is_ok());
+
+        let corrupt = |pos: usize, bytes: &[u8]| {
+            let mut file = buf.clone();
+            file[pos..pos + bytes.len()].copy_from_slice(bytes);
+            read(&mut &file[..])
+        };
+        // The size of the first attribute, "channels".
+        assert!(corrupt(24, &(-1i32).to_le_bytes()).is_err());
+        assert!(corrupt(offset, &u64::MAX.to_le_bytes()).is_err());
+        assert!(corrupt(block_size, &(-1i32).to_le_bytes()).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+#![allow(dead_code)]
+
+pub mod exr;
+pub mod pfm;
+pub mod png;
+pub mod pnm;
+mod zlib;
+
+use crate::color::*;
+use crate::geometry::point::*;
+use crate::geometry::transform::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+// A rectangular image of linear f32 values with any number of named
+// channels, interleaved per pixel and stored row by row from the top.
+// Color images use the channels R, G, B (and A); single-channel images Y.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    resolution: Point2<i32>,
+    channels: Vec<String>,
+    data: Vec<f32>,
+}
+
+impl Image {
+    pub fn new(resolution: Point2<i32>, channels: &[&str]) -> Self {
+        let n = image_len(resolution, channels.len()).expect("image is too large");
+        Self {
+            resolution,
+            channels: channels.iter().map(|c| c.to_string()).collect(),
+            data: vec![0.0; n],
+        }
+    }
+
+    pub fn from_rgb(resolution: Point2<i32>, rgb: &[Rgb]) -> Self {
+        assert_eq!(Some(rgb.len()), image_len(resolution, 1));
+        Self {
+            resolution,
+            channels: vec!["R".into(), "G".into(), "B".into()],
+            data: rgb.iter().flat_map(|c| [c.r, c.g, c.b]).collect(),
+        }
+    }
+
+    pub fn resolution(&self) -> Point2<i32> {
+        self.resolution
+    }
+
+    pub fn width(&self) -> usize {
+        self.resolution.x() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.resolution.y() as usize
+    }
+
+    pub fn n_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn channel_names(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub fn channel_index(&self, name: &str) -> Option<usize> {
+        self.channels.iter().position(|c| c == name)
+    }
+
+    fn offset(&self, p: Point2<i32>) -> usize {
+        assert!(
+            p.x() >= 0 && p.y() >= 0 && p.x() < self.resolution.x() && p.y() < self.resolution.y()
+        );
+        (p.y() as usize * self.width() + p.x() as usize) * self.n_channels()
+    }
+
+    pub fn get_channel(&self, p: Point2<i32>, c: usize) -> f32 {
+        self.data[self.offset(p) + c]
+    }
+
+    pub fn set_channel(&mut self, p: Point2<i32>, c: usize, v: f32) {
+        let i = self.offset(p) + c;
+        self.data[i] = v;
+    }
+
+    // All channel values of a pixel.
+    pub fn get_channels(&self, p: Point2<i32>) -> &[f32] {
+        let i = self.offset(p);
+        &self.data[i..i + self.n_channels()]
+    }
+
+    // The pixel's R, G, B channels, or Y repeated for single-channel
+    // images.
+    pub fn get_rgb(&self, p: Point2<i32>) -> Rgb {
+        match (
+            self.channel_index("R"),
+            self.channel_index("G"),
+            self.channel_index("B"),
+        ) {
+            (Some(r), Some(g), Some(b)) => Rgb::new(
+                self.get_channel(p, r),
+                self.get_channel(p, g),
+                self.get_channel(p, b),
+            ),
+            _ => Rgb::splat(self.get_channel(p, 0)),
+        }
+    }
+
+    pub fn set_rgb(&mut self, p: Point2<i32>, rgb: Rgb) {
+        let c = [
+            self.channel_index("R").expect("image has no R channel"),
+            self.channel_index("G").expect("image has no G channel"),
+            self.channel_index("B").expect("image has no B channel"),
+        ];
+        for (i, &c) in c.iter().enumerate() {
+            self.set_channel(p, c, rgb[i]);
+        }
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    // The named channels, in the given order, as a new image.
+    pub fn select_channels(&self, names: &[&str]) -> Option<Image> {
+        let indices: Option<Vec<usize>> = names.iter().map(|n| self.channel_index(n)).collect();
+        let indices = indices?;
+        let mut image = Image::new(self.resolution, names);
+        for (i, pixel) in self.data.chunks(self.n_channels()).enumerate() {
+            for (j, &c) in indices.iter().enumerate() {
+                image.data[i * names.len() + j] = pixel[c];
+            }
+        }
+        Some(image)
+    }
+
+    // Writes the image in the format given by the file extension: pfm, ppm,
+    // pgm, png, or exr. Only EXR stores the metadata.
+    pub fn write(&self, path: &Path, metadata: &ImageMetadata) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        match extension(path).as_str() {
+            "pfm" => pfm::write(self, &mut w),
+            "ppm" | "pgm" => pnm::write(self, &mut w),
+            "png" => png::write(self, &mut w, true),
+            "exr" => exr::write(self, metadata, &exr::WriteOptions::default(), &mut w),
+            ext => Err(unsupported(ext)),
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<(Image, ImageMetadata)> {
+        let mut r = BufReader::new(File::open(path)?);
+        match extension(path).as_str() {
+            "pfm" => Ok((pfm::read(&mut r)?, ImageMetadata::default())),
+            "ppm" | "pgm" => Ok((pnm::read(&mut r)?, ImageMetadata::default())),
+            "png" => Ok((png::read(&mut r)?, ImageMetadata::default())),
+            "exr" => exr::read(&mut r),
+            ext => Err(unsupported(ext)),
+        }
+    }
+}
+
+// The number of values in an image with the given resolution and values
+// per pixel, or None if the resolution is negative or the count overflows.
+pub(crate) fn image_len(resolution: Point2<i32>, per_pixel: usize) -> Option<usize> {
+    if resolution.x() < 0 || resolution.y() < 0 {
+        return None;
+    }
+    (resolution.x() as usize)
+        .checked_mul(resolution.y() as usize)?
+        .checked_mul(per_pixel)
+}
+
+// The file extension in lowercase, or an empty string if there is none.
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn unsupported(ext: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported image format \"{}\"", ext),
+    )
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Information about how an image was made, stored by formats that support
+// it.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ImageMetadata {
+    pub render_time_seconds: Option<f32>,
+    pub camera_from_world: Option<Matrix4x4<f32>>,
+    pub ndc_from_world: Option<Matrix4x4<f32>>,
+    // Free-form key/value pairs.
+    pub strings: Vec<(String, String)>,
+}
+
+// Maps a linear value to an 8-bit sRGB code, or, if srgb is false, a value
+// that is already display encoded to an 8-bit code. dither, in [-0.5,
+// 0.5), is added before rounding so that smooth gradients do not band.
+pub(crate) fn quantize(v: f32, dither: f32, srgb: bool) -> u8 {
+    let encoded = if srgb {
+        linear_to_srgb(v.max(0.0))
+    } else {
+        v.max(0.0)
+    };
+    (encoded * 255.0 + 0.5 + dither).clamp(0.0, 255.0) as u8
+}
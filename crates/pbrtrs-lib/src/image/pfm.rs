@@ -0,0 +1,125 @@
+use crate::image::*;
+use std::io::{BufRead, Write};
+
+// Portable float map: a short text header followed by raw f32 samples, one
+// (Pf) or three (PF) per pixel, with the bottom row first. A negative scale
+// in the header marks little-endian data.
+pub fn write(image: &Image, w: &mut impl Write) -> io::Result<()> {
+    let (rgb, tag) = match image.n_channels() {
+        1 => (false, "Pf"),
+        _ => (true, "PF"),
+    };
+    write!(w, "{}\n{} {}\n-1\n", tag, image.width(), image.height())?;
+    for y in (0..image.height() as i32).rev() {
+        for x in 0..image.width() as i32 {
+            let p = (x, y).into();
+            if rgb {
+                let c = image.get_rgb(p);
+                for v in &[c.r, c.g, c.b] {
+                    w.write_all(&v.to_le_bytes())?;
+                }
+            } else {
+                w.write_all(&image.get_channel(p, 0).to_le_bytes())?;
+            }
+        }
+    }
+    w.flush()
+}
+
+pub fn read(r: &mut impl BufRead) -> io::Result<Image> {
+    let mut header = Vec::new();
+    // Magic, width, height and scale, separated by whitespace; the single
+    // whitespace byte after the scale ends the header.
+    let tokens = read_tokens(r, 4, &mut header)?;
+    let channels: &[&str] = match tokens[0].as_str() {
+        "PF" => &["R", "G", "B"],
+        "Pf" => &["Y"],
+        _ => return Err(invalid_data("not a PFM file")),
+    };
+    let parse = |s: &str| s.parse::<i32>().ok().filter(|&v| v > 0);
+    let (width, height) = match (parse(&tokens[1]), parse(&tokens[2])) {
+        (Some(w), Some(h)) => (w, h),
+        _ => return Err(invalid_data("bad PFM resolution")),
+    };
+    let scale: f32 = tokens[3]
+        .parse()
+        .map_err(|_| invalid_data("bad PFM scale"))?;
+    let little_endian = scale < 0.0;
+
+    let n = channels.len();
+    if image_len((width, height).into(), n * 4).is_none() {
+        return Err(invalid_data("PFM image is too large"));
+    }
+
+    let mut image = Image::new((width, height).into(), channels);
+    let mut buf = vec![0; width as usize * n * 4];
+    for y in (0..height).rev() {
+        r.read_exact(&mut buf)?;
+        for (i, bytes) in buf.chunks(4).enumerate() {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let v = if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            image.set_channel(((i / n) as i32, y).into(), i % n, v * scale.abs());
+        }
+    }
+    Ok(image)
+}
+
+// Reads n whitespace-separated tokens and the single whitespace byte that
+// follows the last one. Lines starting with # are skipped.
+pub(crate) fn read_tokens(
+    r: &mut impl BufRead,
+    n: usize,
+    scratch: &mut Vec<u8>,
+) -> io::Result<Vec<String>> {
+    let mut tokens = Vec::with_capacity(n);
+    let mut byte = [0];
+    while tokens.len() < n {
+        scratch.clear();
+        loop {
+            r.read_exact(&mut byte)?;
+            match byte[0] {
+                b'#' if scratch.is_empty() => {
+                    let mut comment = Vec::new();
+                    r.read_until(b'\n', &mut comment)?;
+                }
+                c if c.is_ascii_whitespace() => {
+                    if !scratch.is_empty() {
+                        break;
+                    }
+                }
+                c => scratch.push(c),
+            }
+        }
+        tokens.push(String::from_utf8_lossy(scratch).into_owned());
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = Image::new((3, 2).into(), &["R", "G", "B"]);
+        for (i, v) in image.data_mut().iter_mut().enumerate() {
+            *v = i as f32 * 0.37 - 1.5;
+        }
+        let mut buf = Vec::new();
+        write(&image, &mut buf).unwrap();
+        assert!(buf.starts_with(b"PF\n3 2\n-1\n"));
+        assert_eq!(read(&mut &buf[..]).unwrap(), image);
+
+        let mut gray = Image::new((2, 2).into(), &["Y"]);
+        gray.set_channel((1, 0).into(), 0, 7.0);
+        let mut buf = Vec::new();
+        write(&gray, &mut buf).unwrap();
+        assert_eq!(read(&mut &buf[..]).unwrap(), gray);
+        assert!(read(&mut &b"P6\n1 1\n255\n"[..]).is_err());
+        assert!(read(&mut &b"PF\n2147483647 2147483647\n-1\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::color::*;
+use crate::image::zlib::*;
+use crate::image::*;
+use crate::rng::*;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+fn write_chunk(w: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&crc32(crc32(0, kind), data).to_be_bytes())
+}
+
+// Writes an 8-bit sRGB PNG: grayscale for single-channel images, otherwise
+// the R, G, B and (if present) A channels. Alpha is stored linearly. With
+// dither, a per-pixel offset of up to half a code is added before rounding
+// so that smooth gradients do not show bands.
+pub fn write(image: &Image, w: &mut impl Write, dither: bool) -> io::Result<()> {
+    write_quantized(image, w, dither, true)
+}
+
+// Like write, for color values that have already been display encoded (e.g.
+// by post-processing) and are stored as they are.
+pub fn write_encoded(image: &Image, w: &mut impl Write, dither: bool) -> io::Result<()> {
+    write_quantized(image, w, dither, false)
+}
+
+fn write_quantized(image: &Image, w: &mut impl Write, dither: bool, srgb: bool) -> io::Result<()> {
+    let rgb = ["R", "G", "B"]
+        .iter()
+        .map(|c| image.channel_index(c))
+        .collect::<Option<Vec<usize>>>();
+    let alpha = image.channel_index("A");
+    let (color_type, channels) = match (rgb, alpha) {
+        (Some(rgb), Some(a)) => (RGBA, [&rgb[..], &[a]].concat()),
+        (Some(rgb), None) => (RGB, rgb),
+        (None, _) if image.n_channels() == 1 => (GRAY, vec![0]),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PNG needs RGB channels or a single channel",
+            ))
+        }
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let mut raw = Vec::with_capacity(height * (1 + width * channels.len()));
+    for y in 0..height as i32 {
+        // Filter type 0: bytes are stored as they are.
+        raw.push(0);
+        for x in 0..width as i32 {
+            let p = Point2::<i32>::elements(x, y);
+            let d = if dither {
+                let h = hash_pixel(p, 0x5eed);
+                (h >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+            } else {
+                0.0
+            };
+            for &c in &channels {
+                let v = image.get_channel(p, c);
+                raw.push(if Some(c) == alpha {
+                    (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+                } else {
+                    quantize(v, d, srgb)
+                });
+            }
+        }
+    }
+
+    w.write_all(&SIGNATURE)?;
+    let mut header = Vec::with_capacity(13);
+    header.extend(&(width as u32).to_be_bytes());
+    header.extend(&(height as u32).to_be_bytes());
+    // Bit depth, color type, compression, filter and interlace methods.
+    header.extend(&[8, color_type, 0, 0, 0]);
+    write_chunk(w, b"IHDR", &header)?;
+    if srgb {
+        // Perceptual rendering intent.
+        write_chunk(w, b"sRGB", &[0])?;
+    }
+    write_chunk(w, b"IDAT", &compress(&raw))?;
+    write_chunk(w, b"IEND", &[])?;
+    w.flush()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Undoes the per-row filters in place, dropping the filter type bytes.
+fn unfilter(data: &[u8], row_bytes: usize, bpp: usize, height: usize) -> io::Result<Vec<u8>> {
+    let mut out = vec![0u8; row_bytes * height];
+    for y in 0..height {
+        let filter = data[y * (row_bytes + 1)];
+        let src = &data[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+        let (above, row) = out.split_at_mut(y * row_bytes);
+        let above = if y > 0 {
+            &above[(y - 1) * row_bytes..]
+        } else {
+            &[][..]
+        };
+        let row = &mut row[..row_bytes];
+        for i in 0..row_bytes {
+            let a = if i >= bpp { row[i - bpp] } else { 0 };
+            let b = above.get(i).cloned().unwrap_or(0);
+            let c = if i >= bpp {
+                above.get(i - bpp).cloned().unwrap_or(0)
+            } else {
+                0
+            };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid_data("bad PNG filter type")),
+            };
+            row[i] = src[i].wrapping_add(predictor);
+        }
+    }
+    Ok(out)
+}
+
+// Reads 8- and 16-bit non-interlaced PNGs of any color type (palettes at 8
+// bits only). Color values are decoded from sRGB to linear; alpha is kept
+// as is.
+pub fn read(r: &mut impl Read) -> io::Result<Image> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    if !data.starts_with(&SIGNATURE) {
+        return Err(invalid_data("not a PNG file"));
+    }
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut idat = Vec::new();
+    loop {
+        let chunk_header = data
+            .get(pos..pos + 8)
+            .ok_or_else(|| invalid_data("truncated PNG file"))?;
+        let len = u32::from_be_bytes([
+            chunk_header[0],
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+        ]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len + 4)
+            .ok_or_else(|| invalid_data("truncated PNG chunk"))?;
+        let (body, crc) = body.split_at(len);
+        if crc32(crc32(0, kind), body) != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(invalid_data("PNG chunk CRC mismatch"));
+        }
+        pos += 12 + len;
+        match kind {
+            b"IHDR" if len == 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid_data("PNG file has no header"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let (bit_depth, color_type) = (header[8], header[9]);
+    if header[12] != 0 {
+        return Err(invalid_data("interlaced PNGs are not supported"));
+    }
+    let (samples, channels): (usize, &[&str]) = match color_type {
+        GRAY => (1, &["Y"]),
+        RGB => (3, &["R", "G", "B"]),
+        PALETTE => (1, &["R", "G", "B"]),
+        GRAY_ALPHA => (2, &["Y", "A"]),
+        RGBA => (4, &["R", "G", "B", "A"]),
+        _ => return Err(invalid_data("bad PNG color type")),
+    };
+    if !(bit_depth == 8 || bit_depth == 16 && color_type != PALETTE) {
+        return Err(invalid_data("unsupported PNG bit depth"));
+    }
+    let resolution = match (i32::try_from(width), i32::try_from(height)) {
+        (Ok(w), Ok(h)) if w > 0 && h > 0 => Point2::<i32>::elements(w, h),
+        _ => return Err(invalid_data("bad PNG resolution")),
+    };
+
+    let bytes_per_sample = bit_depth as usize / 8;
+    let bpp = samples * bytes_per_sample;
+    let too_large = || invalid_data("PNG image is too large");
+    image_len(resolution, channels.len()).ok_or_else(too_large)?;
+    // Each row starts with its filter type byte.
+    let size = image_len(resolution, bpp)
+        .and_then(|n| n.checked_add(height))
+        .ok_or_else(too_large)?;
+    let raw = decompress(&idat)?;
+    if raw.len() < size {
+        return Err(invalid_data("not enough PNG image data"));
+    }
+    let pixels = unfilter(&raw, width * bpp, bpp, height)?;
+
+    let mut image = Image::new(resolution, channels);
+    let has_alpha = color_type == GRAY_ALPHA || color_type == RGBA;
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    for (i, pixel) in pixels.chunks(bpp).enumerate() {
+        let p = Point2::<i32>::elements((i % width) as i32, (i / width) as i32);
+        if color_type == PALETTE {
+            let entry = palette
+                .get(3 * pixel[0] as usize..3 * pixel[0] as usize + 3)
+                .ok_or_else(|| invalid_data("PNG palette index out of range"))?;
+            for (c, &v) in entry.iter().enumerate() {
+                image.set_channel(p, c, srgb_to_linear(v as f32 / 255.0));
+            }
+            continue;
+        }
+        for c in 0..samples {
+            let v = if bytes_per_sample == 2 {
+                u16::from_be_bytes([pixel[2 * c], pixel[2 * c + 1]]) as f32
+            } else {
+                pixel[c] as f32
+            } / max;
+            let is_alpha = has_alpha && c == samples - 1;
+            image.set_channel(p, c, if is_alpha { v } else { srgb_to_linear(v) });
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gradient(channels: &[&str]) -> Image {
+        let mut image = Image::new((37, 5).into(), channels);
+        let n = image.n_channels();
+        for (i, v) in image.data_mut().iter_mut().enumerate() {
+            let x = (i / n) % 37;
+            *v = srgb_to_linear(x as f32 / 36.0 * (i % n + 1) as f32 / n as f32);
+        }
+        image
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for channels in &[&["Y"][..], &["R", "G", "B"], &["R", "G", "B", "A"]] {
+            let image = gradient(channels);
+            let mut buf = Vec::new();
+            write(&image, &mut buf, false).unwrap();
+            let read_back = read(&mut &buf[..]).unwrap();
+            assert_eq!(read_back.channel_names(), image.channel_names());
+            assert_eq!(read_back.resolution(), image.resolution());
+            for (i, (a, b)) in read_back.data().iter().zip(image.data()).enumerate() {
+                let (a, b) = if channels.len() == 4 && i % 4 == 3 {
+                    (*a, *b)
+                } else {
+                    (linear_to_srgb(*a), linear_to_srgb(*b))
+                };
+                assert!((a - b).abs() <= 0.5 / 255.0 + 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dither() {
+        // A constant value a third of the way between two codes comes back
+        // as a mix of both that averages to it.
+        let v = srgb_to_linear(100.0 / 255.0 + 1.0 / 3.0 / 255.0);
+        let mut image = Image::new((64, 64).into(), &["Y"]);
+        image.data_mut().iter_mut().for_each(|p| *p = v);
+        let mut buf = Vec::new();
+        write(&image, &mut buf, true).unwrap();
+        let codes: Vec<f32> = read(&mut &buf[..])
+            .unwrap()
+            .data()
+            .iter()
+            .map(|&v| (linear_to_srgb(v) * 255.0).round())
+            .collect();
+        assert!(codes.iter().all(|&c| c == 100.0 || c == 101.0));
+        let mean = codes.iter().sum::<f32>() / codes.len() as f32;
+        assert!((mean - 100.333).abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn test_unfilter() {
+        // One row each of the Sub, Up, Average and Paeth filters, with two
+        // bytes per pixel.
+        let data = [
+            1, 10, 20, 5, 5, //
+            2, 1, 1, 1, 1, //
+            3, 0, 0, 0, 0, //
+            4, 1, 2, 3, 4,
+        ];
+        let out = unfilter(&data, 4, 2, 4).unwrap();
+        assert_eq!(&out[0..4], [10, 20, 15, 25]);
+        assert_eq!(&out[4..8], [11, 21, 16, 26]);
+        assert_eq!(&out[8..12], [5, 10, 10, 18]);
+        assert_eq!(&out[12..16], [6, 12, 13, 22]);
+    }
+
+    #[test]
+    fn test_bad_files() {
+        assert!(read(&mut &b"GIF89a"[..]).is_err());
+
+        // A header whose image size overflows.
+        let mut header = Vec::new();
+        header.extend(&0x7fff_ffffu32.to_be_bytes());
+        header.extend(&0x7fff_ffffu32.to_be_bytes());
+        header.extend(&[16, RGBA, 0, 0, 0]);
+        let mut buf = SIGNATURE.to_vec();
+        write_chunk(&mut buf, b"IHDR", &header).unwrap();
+        write_chunk(&mut buf, b"IDAT", &compress(&[])).unwrap();
+        write_chunk(&mut buf, b"IEND", &[]).unwrap();
+        assert!(read(&mut &buf[..]).is_err());
+    }
+}
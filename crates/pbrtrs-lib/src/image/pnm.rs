@@ -0,0 +1,103 @@
+use crate::color::*;
+use crate::image::pfm::read_tokens;
+use crate::image::*;
+use std::io::{BufRead, Write};
+
+// Binary PPM (P6) for color images and PGM (P5) for single-channel ones,
+// with 8-bit sRGB-encoded values.
+pub fn write(image: &Image, w: &mut impl Write) -> io::Result<()> {
+    write_quantized(image, w, true)
+}
+
+// Like write, for values that have already been display encoded.
+pub fn write_encoded(image: &Image, w: &mut impl Write) -> io::Result<()> {
+    write_quantized(image, w, false)
+}
+
+fn write_quantized(image: &Image, w: &mut impl Write, srgb: bool) -> io::Result<()> {
+    let gray = image.n_channels() == 1;
+    let tag = if gray { "P5" } else { "P6" };
+    write!(w, "{}\n{} {}\n255\n", tag, image.width(), image.height())?;
+    let mut row = Vec::with_capacity(image.width() * 3);
+    for y in 0..image.height() as i32 {
+        row.clear();
+        for x in 0..image.width() as i32 {
+            let p = (x, y).into();
+            if gray {
+                row.push(quantize(image.get_channel(p, 0), 0.0, srgb));
+            } else {
+                let c = image.get_rgb(p);
+                row.extend([c.r, c.g, c.b].iter().map(|&v| quantize(v, 0.0, srgb)));
+            }
+        }
+        w.write_all(&row)?;
+    }
+    w.flush()
+}
+
+// Reads P5 and P6 files, with maximum values up to 65535, into linear
+// values.
+pub fn read(r: &mut impl BufRead) -> io::Result<Image> {
+    let mut scratch = Vec::new();
+    let tokens = read_tokens(r, 4, &mut scratch)?;
+    let channels: &[&str] = match tokens[0].as_str() {
+        "P6" => &["R", "G", "B"],
+        "P5" => &["Y"],
+        _ => return Err(invalid_data("not a binary PPM or PGM file")),
+    };
+    let parse = |s: &str| s.parse::<i32>().ok().filter(|&v| v > 0);
+    let (width, height, max_value) = match (parse(&tokens[1]), parse(&tokens[2]), parse(&tokens[3]))
+    {
+        (Some(w), Some(h), Some(m)) if m <= 65535 => (w, h, m),
+        _ => return Err(invalid_data("bad PNM header")),
+    };
+    let wide = max_value > 255;
+
+    let bytes_per_value = if wide { 2 } else { 1 };
+    if image_len((width, height).into(), channels.len() * bytes_per_value).is_none() {
+        return Err(invalid_data("PNM image is too large"));
+    }
+
+    let mut image = Image::new((width, height).into(), channels);
+    let mut buf = vec![0; image.data().len() * bytes_per_value];
+    r.read_exact(&mut buf)?;
+    for (v, bytes) in image.data_mut().iter_mut().zip(buf.chunks(bytes_per_value)) {
+        let code = if wide {
+            u16::from_be_bytes([bytes[0], bytes[1]]) as f32
+        } else {
+            bytes[0] as f32
+        };
+        *v = srgb_to_linear(code / max_value as f32);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut image = Image::new((4, 3).into(), &["R", "G", "B"]);
+        for (i, v) in image.data_mut().iter_mut().enumerate() {
+            *v = srgb_to_linear(i as f32 / 35.0);
+        }
+        let mut buf = Vec::new();
+        write(&image, &mut buf).unwrap();
+        let read_back = read(&mut &buf[..]).unwrap();
+        assert_eq!(read_back.resolution(), image.resolution());
+        for (a, b) in read_back.data().iter().zip(image.data()) {
+            assert!((linear_to_srgb(*a) - linear_to_srgb(*b)).abs() <= 0.5 / 255.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_read_pgm() {
+        // A 16-bit file with a comment in the header.
+        let mut data = b"P5\n# comment\n2 1\n65535\n".to_vec();
+        data.extend(&[0xff, 0xff, 0x00, 0x00]);
+        let image = read(&mut &data[..]).unwrap();
+        assert_eq!(image.channel_names(), ["Y"]);
+        assert_eq!(image.data(), [1.0, 0.0]);
+    }
+}
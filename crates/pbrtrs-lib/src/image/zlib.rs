@@ -0,0 +1,461 @@
+// Just enough of zlib (RFC 1950) and deflate (RFC 1951) for PNG files. The
+// compressor finds matches greedily through a one-entry hash table and codes
+// them with the fixed Huffman tables; the decompressor handles all three
+// block types.
+
+use crate::image::invalid_data;
+use std::io;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW_SIZE: usize = 32768;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+// The CRC-32 used by PNG chunks, continued from a previous value (zero to
+// start).
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in data {
+        c = CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b overflows.
+    for chunk in data.chunks(5552) {
+        for &v in chunk {
+            a += v as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    n_bits: u32,
+}
+
+impl BitWriter {
+    // Appends the low n bits of v, least significant first.
+    fn put(&mut self, v: u32, n: u32) {
+        self.bits |= (v as u64) << self.n_bits;
+        self.n_bits += n;
+        while self.n_bits >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.n_bits -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit.
+    fn put_code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.n_bits > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+fn put_literal(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.put_code(0x30 + symbol, 8),
+        144..=255 => w.put_code(0x190 + symbol - 144, 9),
+        256..=279 => w.put_code(symbol - 256, 7),
+        _ => w.put_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn put_match(w: &mut BitWriter, length: usize, distance: usize) {
+    let l = LENGTH_BASE
+        .iter()
+        .rposition(|&b| b as usize <= length)
+        .unwrap();
+    put_literal(w, 257 + l as u32);
+    w.put(
+        (length - LENGTH_BASE[l] as usize) as u32,
+        LENGTH_EXTRA[l] as u32,
+    );
+    let d = DIST_BASE
+        .iter()
+        .rposition(|&b| b as usize <= distance)
+        .unwrap();
+    w.put_code(d as u32, 5);
+    w.put(
+        (distance - DIST_BASE[d] as usize) as u32,
+        DIST_EXTRA[d] as u32,
+    );
+}
+
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: vec![0x78, 0x01],
+        bits: 0,
+        n_bits: 0,
+    };
+    // A single final block with the fixed codes.
+    w.put(1, 1);
+    w.put(1, 2);
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut i = 0;
+    while i < data.len() {
+        let mut length = 0;
+        if i + 3 <= data.len() {
+            let h = hash(&data[i..]);
+            let candidate = head[h];
+            head[h] = i;
+            if candidate != usize::MAX && i - candidate <= WINDOW_SIZE {
+                let max = MAX_MATCH.min(data.len() - i);
+                while length < max && data[candidate + length] == data[i + length] {
+                    length += 1;
+                }
+            }
+            if length >= 3 {
+                put_match(&mut w, length, i - candidate);
+                for j in i + 1..(i + length).min(data.len().saturating_sub(2)) {
+                    head[hash(&data[j..])] = j;
+                }
+                i += length;
+                continue;
+            }
+        }
+        put_literal(&mut w, data[i] as u32);
+        i += 1;
+    }
+    put_literal(&mut w, 256);
+    let mut out = w.finish();
+    out.extend(&adler32(data).to_be_bytes());
+    out
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    n_bits: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.n_bits < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid_data("truncated deflate stream"))?;
+            self.pos += 1;
+            self.bits |= (byte as u32) << self.n_bits;
+            self.n_bits += 8;
+        }
+        let v = self.bits & ((1u64 << n) - 1) as u32;
+        self.bits >>= n;
+        self.n_bits -= n;
+        Ok(v)
+    }
+
+    fn align(&mut self) {
+        self.bits = 0;
+        self.n_bits = 0;
+    }
+}
+
+// A canonical Huffman code stored as the number of codes of each length
+// and the symbols ordered by code.
+struct Huffman {
+    count: [u16; 16],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut count = [0u16; 16];
+        for &l in lengths {
+            count[l as usize] += 1;
+        }
+        // Reject codes that use more than all of the code space.
+        let mut left = 1i32;
+        for &c in &count[1..] {
+            left = 2 * left - c as i32;
+            if left < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for l in 1..15 {
+            offsets[l + 1] = offsets[l] + count[l];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (s, &l) in lengths.iter().enumerate() {
+            if l != 0 {
+                symbol[offsets[l as usize] as usize] = s as u16;
+                offsets[l as usize] += 1;
+            }
+        }
+        count[0] = 0;
+        Ok(Self { count, symbol })
+    }
+
+    fn decode(&self, r: &mut BitReader) -> io::Result<usize> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= r.bits(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - count < first {
+                return Ok(self.symbol[(index + code - first) as usize] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("bad Huffman code"))
+    }
+}
+
+fn inflate_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = lengths.decode(r)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let l = symbol - 257;
+                if l >= 29 {
+                    return Err(invalid_data("bad deflate length"));
+                }
+                let length = LENGTH_BASE[l] as usize + r.bits(LENGTH_EXTRA[l] as u32)? as usize;
+                let d = distances.decode(r)?;
+                if d >= 30 {
+                    return Err(invalid_data("bad deflate distance"));
+                }
+                let distance = DIST_BASE[d] as usize + r.bits(DIST_EXTRA[d] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid_data("deflate distance too far back"));
+                }
+                let start = out.len() - distance;
+                // Copies may overlap their own output.
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].iter_mut().for_each(|l| *l = 9);
+    lengths[256..280].iter_mut().for_each(|l| *l = 7);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(r: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+    let n_len = r.bits(5)? as usize + 257;
+    let n_dist = r.bits(5)? as usize + 1;
+    let n_code = r.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in &ORDER[..n_code] {
+        code_lengths[i] = r.bits(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(n_len + n_dist);
+    while lengths.len() < n_len + n_dist {
+        let symbol = code.decode(r)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid_data("repeat with no previous length"))?;
+                (previous, 3 + r.bits(2)?)
+            }
+            17 => (0, 3 + r.bits(3)?),
+            _ => (0, 11 + r.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > n_len + n_dist || lengths[256] == 0 {
+        return Err(invalid_data("bad dynamic Huffman lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..n_len])?,
+        Huffman::new(&lengths[n_len..])?,
+    ))
+}
+
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 6
+        || data[0] & 0x0f != 8
+        || !(data[0] as u32 * 256 + data[1] as u32).is_multiple_of(31)
+    {
+        return Err(invalid_data("bad zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid_data("zlib preset dictionaries are not supported"));
+    }
+    let mut r = BitReader {
+        data: &data[2..],
+        pos: 0,
+        bits: 0,
+        n_bits: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align();
+                let header = r
+                    .data
+                    .get(r.pos..r.pos + 4)
+                    .ok_or_else(|| invalid_data("truncated deflate stream"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                if len != !u16::from_le_bytes([header[2], header[3]]) as usize {
+                    return Err(invalid_data("bad stored block length"));
+                }
+                r.pos += 4;
+                let block = r
+                    .data
+                    .get(r.pos..r.pos + len)
+                    .ok_or_else(|| invalid_data("truncated deflate stream"))?;
+                out.extend_from_slice(block);
+                r.pos += len;
+            }
+            1 => {
+                let (lengths, distances) = fixed_codes()?;
+                inflate_block(&mut r, &mut out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut r)?;
+                inflate_block(&mut r, &mut out, &lengths, &distances)?;
+            }
+            _ => return Err(invalid_data("bad deflate block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    let checksum = r
+        .data
+        .get(r.pos..r.pos + 4)
+        .ok_or_else(|| invalid_data("missing zlib checksum"))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(invalid_data("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut data: Vec<u8> = b"abcabcabcabcabcd"
+            .iter()
+            .cycle()
+            .take(1000)
+            .cloned()
+            .collect();
+        data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+        data.extend(vec![7; 600]);
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert_eq!(decompress(&compress(&[])).unwrap(), []);
+    }
+
+    #[test]
+    fn test_known_streams() {
+        // 200 pseudo-random letters compressed by zlib at level 9, which
+        // chose a dynamic block.
+        let dynamic = [
+            0x78, 0xda, 0x2d, 0x4e, 0x39, 0x12, 0x00, 0x21, 0x0c, 0x7a, 0x2b, 0x05, 0x05, 0x0d,
+            0x69, 0xf2, 0xff, 0x59, 0x88, 0xeb, 0x8c, 0xca, 0x61, 0x10, 0x89, 0xcb, 0xa1, 0x09,
+            0x0f, 0x03, 0xcd, 0x01, 0x54, 0x29, 0x04, 0xd9, 0x2c, 0x24, 0x54, 0x0d, 0xa5, 0xb7,
+            0xc4, 0x99, 0x75, 0x91, 0x35, 0x06, 0x28, 0xe4, 0xd1, 0xb3, 0x91, 0x90, 0x1c, 0x8c,
+            0xbf, 0xf5, 0x79, 0x19, 0x8b, 0x6a, 0x7a, 0xc3, 0x74, 0x58, 0x73, 0x8d, 0xbd, 0xa1,
+            0x3e, 0xe4, 0x6e, 0x49, 0xf4, 0x49, 0x17, 0x67, 0x80, 0x87, 0x12, 0x8d, 0x74, 0xca,
+            0x15, 0xe7, 0x4f, 0x68, 0x51, 0xac, 0xae, 0x70, 0x3f, 0xc6, 0x07, 0xff, 0x51, 0x51,
+            0x76,
+        ];
+        let mut x = 1u32;
+        let letters: Vec<u8> = (0..200)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345) & 0x7fff_ffff;
+                b"eeeeetaoin"[(x >> 16) as usize % 10]
+            })
+            .collect();
+        assert_eq!(decompress(&dynamic).unwrap(), letters);
+
+        // A stored block.
+        let text = b"hello hello hello";
+        let mut stored = vec![0x78, 0x01, 0x01, 17, 0, !17, 0xff];
+        stored.extend(text);
+        stored.extend(&adler32(text).to_be_bytes());
+        assert_eq!(decompress(&stored).unwrap(), text);
+
+        assert_eq!(crc32(0, b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+}
@@ -1 +1,12 @@
+pub mod camera;
+pub mod color;
+pub mod colorspace;
+pub mod film;
+pub mod filter;
 pub mod geometry;
+pub mod image;
+pub mod postprocess;
+pub mod rng;
+pub mod sampler;
+pub mod sampling;
+pub mod spectrum;
@@ -0,0 +1,503 @@
+#![allow(dead_code)]
+
+use crate::color::*;
+use crate::colorspace::*;
+use crate::image::*;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+// Curves that compress scene-referred HDR values into the displayable
+// [0, 1] range. All of them produce linear values; the display encoding is
+// applied separately.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMap {
+    // Values above one are left for the output to clip.
+    None,
+    // L / (1 + L) on luminance, keeping hue. With a white point, luminance
+    // at or above it maps to one.
+    Reinhard { white: Option<f32> },
+    // Stephen Hill's fit of the ACES reference rendering and sRGB output
+    // transforms.
+    AcesFilmic,
+    // A log-encoded curve after Troy Sobotka's AgX, which takes very bright
+    // saturated colors towards white without skewing their hue.
+    AgX,
+}
+
+// The transfer function applied last, for display.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    Linear,
+    Srgb,
+    Gamma(f32),
+}
+
+// Glow around bright areas: the part of each color above threshold is
+// blurred with a Gaussian and added back, scaled by strength.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Bloom {
+    pub strength: f32,
+    // Standard deviation of the blur, in pixels.
+    pub radius: f32,
+    pub threshold: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            strength: 0.05,
+            radius: 8.0,
+            threshold: 1.0,
+        }
+    }
+}
+
+// Post-processing of a film's RGB output, applied in order: exposure,
+// bloom, tone mapping, and display encoding. Only the R, G, B channels of
+// an image are changed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PostProcess {
+    // In stops.
+    exposure: f32,
+    bloom: Option<Bloom>,
+    tone_map: ToneMap,
+    encoding: Encoding,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            bloom: None,
+            tone_map: ToneMap::None,
+            encoding: Encoding::Linear,
+        }
+    }
+}
+
+impl PostProcess {
+    pub fn with_exposure(mut self, stops: f32) -> Self {
+        self.exposure = stops;
+        self
+    }
+
+    pub fn with_bloom(mut self, bloom: Bloom) -> Self {
+        self.bloom = Some(bloom);
+        self
+    }
+
+    pub fn with_tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        let mut image = image.clone();
+        let rgb_channels = ["R", "G", "B"]
+            .iter()
+            .map(|c| image.channel_index(c))
+            .collect::<Option<Vec<usize>>>();
+        let c = match rgb_channels {
+            Some(c) => [c[0], c[1], c[2]],
+            None => return image,
+        };
+        let n = image.n_channels();
+        let mut rgb: Vec<Rgb> = image
+            .data()
+            .chunks(n)
+            .map(|p| Rgb::new(p[c[0]], p[c[1]], p[c[2]]))
+            .collect();
+
+        let scale = 2f32.powf(self.exposure);
+        rgb.iter_mut().for_each(|v| *v *= scale);
+        if let Some(bloom) = &self.bloom {
+            apply_bloom(&mut rgb, image.width(), image.height(), bloom);
+        }
+        for v in rgb.iter_mut() {
+            *v = encode(tone_map(*v, self.tone_map), self.encoding);
+        }
+
+        for (p, v) in image.data_mut().chunks_mut(n).zip(&rgb) {
+            for i in 0..3 {
+                p[c[i]] = v[i];
+            }
+        }
+        image
+    }
+
+    // Applies the post-processing and writes the image. 8-bit formats store
+    // the encoded values as they are, unless the encoding is linear, in
+    // which case they apply sRGB as usual. Float formats hold linear values,
+    // so they are written without the display encoding.
+    pub fn write_image(
+        &self,
+        image: &Image,
+        path: &Path,
+        metadata: &ImageMetadata,
+    ) -> io::Result<()> {
+        let ext = extension(path);
+        if !matches!(ext.as_str(), "png" | "ppm" | "pgm") {
+            let linear = self.with_encoding(Encoding::Linear);
+            return linear.apply(image).write(path, metadata);
+        }
+        let processed = self.apply(image);
+        if self.encoding == Encoding::Linear {
+            return processed.write(path, metadata);
+        }
+        let mut w = BufWriter::new(File::create(path)?);
+        if ext == "png" {
+            png::write_encoded(&processed, &mut w, true)
+        } else {
+            pnm::write_encoded(&processed, &mut w)
+        }
+    }
+}
+
+pub fn reinhard(rgb: Rgb, white: Option<f32>) -> Rgb {
+    let l = RgbColorSpace::srgb().luminance(rgb);
+    if l <= 0.0 {
+        return Rgb::default();
+    }
+    let ld = match white {
+        Some(w) => l * (1.0 + l / (w * w)) / (1.0 + l),
+        None => l / (1.0 + l),
+    };
+    rgb * (ld / l)
+}
+
+pub fn aces_filmic(rgb: Rgb) -> Rgb {
+    // sRGB to the ACES reference space, with the RRT's saturation change.
+    const INPUT: [[f32; 3]; 3] = [
+        [0.597_19, 0.354_58, 0.048_23],
+        [0.076_00, 0.908_34, 0.015_66],
+        [0.028_40, 0.133_83, 0.837_77],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.604_75, -0.531_08, -0.073_67],
+        [-0.102_08, 1.108_13, -0.006_05],
+        [-0.003_27, -0.072_76, 1.076_02],
+    ];
+    let v = Matrix3x3::new(INPUT).apply(rgb.into());
+    let v = v.map(|x| {
+        let a = x * (x + 0.024_578_6) - 0.000_090_537;
+        let b = x * (0.983_729 * x + 0.432_951) + 0.238_081;
+        a / b
+    });
+    Rgb::from(Matrix3x3::new(OUTPUT).apply(v)).map(|x| x.clamp(0.0, 1.0))
+}
+
+pub fn agx(rgb: Rgb) -> Rgb {
+    // Pulls the primaries inwards so that bright colors desaturate.
+    const INSET: [[f32; 3]; 3] = [
+        [0.842_479, 0.078_434, 0.079_224],
+        [0.042_328, 0.878_469, 0.079_166],
+        [0.042_376, 0.078_434, 0.879_143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196_879, -0.098_021, -0.099_030],
+        [-0.052_897, 1.151_903, -0.098_961],
+        [-0.052_972, -0.098_043, 1.151_074],
+    ];
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+    let v = Matrix3x3::new(INSET).apply(rgb.map(|x| x.max(1e-10)).into());
+    let v = v.map(|x| {
+        // Log encoding of the covered range of stops, then a sigmoid fitted
+        // by a polynomial that yields display-encoded values.
+        let t = ((x.log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV)) as f64;
+        let (t2, t4) = (t * t, t * t * t * t);
+        let s = 15.5 * t4 * t2 - 40.14 * t4 * t + 31.96 * t4 - 6.868 * t2 * t
+            + 0.4298 * t2
+            + 0.1191 * t
+            - 0.00232;
+        s as f32
+    });
+    Rgb::from(Matrix3x3::new(OUTSET).apply(v)).map(|x| x.max(0.0).powf(2.2).min(1.0))
+}
+
+fn tone_map(rgb: Rgb, tone_map: ToneMap) -> Rgb {
+    match tone_map {
+        ToneMap::None => rgb,
+        ToneMap::Reinhard { white } => reinhard(rgb, white),
+        ToneMap::AcesFilmic => aces_filmic(rgb),
+        ToneMap::AgX => agx(rgb),
+    }
+}
+
+fn encode(rgb: Rgb, encoding: Encoding) -> Rgb {
+    match encoding {
+        Encoding::Linear => rgb,
+        Encoding::Srgb => rgb.map(|v| linear_to_srgb(v.max(0.0))),
+        Encoding::Gamma(g) => rgb.map(|v| v.max(0.0).powf(1.0 / g)),
+    }
+}
+
+fn apply_bloom(rgb: &mut [Rgb], width: usize, height: usize, bloom: &Bloom) {
+    let bright: Vec<Rgb> = rgb
+        .iter()
+        .map(|v| v.map(|x| (x - bloom.threshold).max(0.0)))
+        .collect();
+    let kernel = gaussian_kernel(bloom.radius);
+    let r = kernel.len() as isize / 2;
+    // Separable blur, clamping at the image edges.
+    let blur = |src: &[Rgb], horizontal: bool| -> Vec<Rgb> {
+        let mut dst = vec![Rgb::default(); src.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Rgb::default();
+                for (k, &w) in kernel.iter().enumerate() {
+                    let d = k as isize - r;
+                    let (sx, sy) = if horizontal {
+                        ((x as isize + d).clamp(0, width as isize - 1) as usize, y)
+                    } else {
+                        (x, (y as isize + d).clamp(0, height as isize - 1) as usize)
+                    };
+                    sum += src[sy * width + sx] * w;
+                }
+                dst[y * width + x] = sum;
+            }
+        }
+        dst
+    };
+    let glow = blur(&blur(&bright, true), false);
+    for (v, g) in rgb.iter_mut().zip(&glow) {
+        *v += *g * bloom.strength;
+    }
+}
+
+// Normalized weights out to three standard deviations.
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil().max(0.0) as i32;
+    let mut kernel: Vec<f32> = (-r..=r)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma).max(1e-6)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|w| *w /= sum);
+    kernel
+}
+
+// Parses settings such as "exposure=1.5,bloom=0.1:8,tonemap=aces,encoding=srgb",
+// the form built from the command-line options. Keys are exposure
+// (stops), tonemap (none, reinhard[:white], aces, agx), bloom
+// (strength[:radius[:threshold]]) and encoding (linear, srgb, gamma:g).
+impl FromStr for PostProcess {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut post = PostProcess::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got \"{}\"", setting))?;
+            let mut args = value.split(':');
+            let name = args.next().unwrap_or("");
+            let numbers: Vec<f32> = args
+                .map(|a| {
+                    a.parse::<f32>()
+                        .map_err(|_| format!("bad number \"{}\" in \"{}\"", a, setting))
+                })
+                .collect::<Result<_, _>>()?;
+            let number = |v: &str| {
+                v.parse::<f32>()
+                    .map_err(|_| format!("bad number \"{}\" in \"{}\"", v, setting))
+            };
+            match key.trim() {
+                "exposure" => post.exposure = number(value)?,
+                "tonemap" => {
+                    post.tone_map = match (name, numbers.as_slice()) {
+                        ("none", []) => ToneMap::None,
+                        ("reinhard", []) => ToneMap::Reinhard { white: None },
+                        ("reinhard", &[w]) => ToneMap::Reinhard { white: Some(w) },
+                        ("aces", []) => ToneMap::AcesFilmic,
+                        ("agx", []) => ToneMap::AgX,
+                        _ => return Err(format!("unknown tone map \"{}\"", value)),
+                    }
+                }
+                "bloom" => {
+                    let mut bloom = Bloom {
+                        strength: number(name)?,
+                        ..Bloom::default()
+                    };
+                    match numbers.as_slice() {
+                        [] => {}
+                        &[radius] => bloom.radius = radius,
+                        &[radius, threshold] => {
+                            bloom.radius = radius;
+                            bloom.threshold = threshold;
+                        }
+                        _ => return Err(format!("too many bloom values in \"{}\"", value)),
+                    }
+                    post.bloom = Some(bloom);
+                }
+                "encoding" => {
+                    post.encoding = match (name, numbers.as_slice()) {
+                        ("linear", []) => Encoding::Linear,
+                        ("srgb", []) => Encoding::Srgb,
+                        ("gamma", &[g]) if g > 0.0 => Encoding::Gamma(g),
+                        _ => return Err(format!("unknown encoding \"{}\"", value)),
+                    }
+                }
+                _ => return Err(format!("unknown post-processing setting \"{}\"", key)),
+            }
+        }
+        Ok(post)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gray_ramp(f: impl Fn(f32) -> f32) -> Vec<f32> {
+        (0..200)
+            .map(|i| f(2f32.powf(i as f32 / 10.0 - 10.0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_curves() {
+        assert_eq!(reinhard(Rgb::splat(1.0), None), Rgb::splat(0.5));
+        assert!((reinhard(Rgb::splat(4.0), Some(4.0)).g - 1.0).abs() < 1e-6);
+        let hue = reinhard(Rgb::new(4.0, 2.0, 1.0), None);
+        assert!((hue.r / hue.g - 2.0).abs() < 1e-5);
+
+        for curve in &[
+            ToneMap::Reinhard { white: None },
+            ToneMap::AcesFilmic,
+            ToneMap::AgX,
+        ] {
+            let ramp = gray_ramp(|v| tone_map(Rgb::splat(v), *curve).g);
+            assert!(ramp.windows(2).all(|w| w[0] <= w[1]), "{:?}", curve);
+            assert!(ramp.iter().all(|&v| (0.0..=1.0).contains(&v)));
+            assert!(ramp[0] < 0.01 && ramp[199] > 0.95, "{:?}", curve);
+            // Mid gray stays in the lower middle of the range.
+            let mid = tone_map(Rgb::splat(0.18), *curve).g;
+            assert!((0.05..0.3).contains(&mid), "{:?} {}", curve, mid);
+            // Gray stays gray.
+            let v = tone_map(Rgb::splat(0.7), *curve);
+            assert!((v.r - v.g).abs() < 2e-3 && (v.b - v.g).abs() < 2e-3);
+        }
+
+        // Both take very bright red towards white, but AgX keeps its hue
+        // where ACES skews it to yellow.
+        let red = Rgb::new(50.0, 0.0, 0.0);
+        let (a, b) = (agx(red), aces_filmic(red));
+        assert!(a.g > 0.5 && (a.g - a.b).abs() < 0.01);
+        assert!(b.g - b.b > 0.1);
+    }
+
+    fn test_image() -> Image {
+        let mut image = Image::new((15, 11).into(), &["R", "G", "B", "Z"]);
+        for (i, v) in image.data_mut().iter_mut().enumerate() {
+            *v = if i % 4 == 3 { 100.0 } else { 0.5 };
+        }
+        image
+    }
+
+    #[test]
+    fn test_apply() {
+        let image = test_image();
+        let post = PostProcess::default()
+            .with_exposure(1.0)
+            .with_encoding(Encoding::Gamma(2.0));
+        let out = post.apply(&image);
+        assert_eq!(out.get_rgb((3, 4).into()), Rgb::splat(1.0));
+        assert_eq!(out.get_channel((3, 4).into(), 3), 100.0);
+
+        let out = PostProcess::default()
+            .with_encoding(Encoding::Srgb)
+            .apply(&image);
+        assert_eq!(out.get_channel((0, 0).into(), 0), linear_to_srgb(0.5));
+    }
+
+    #[test]
+    fn test_bloom() {
+        let mut image = test_image();
+        image.set_rgb((7, 5).into(), Rgb::splat(101.0));
+        let bloom = Bloom {
+            strength: 0.5,
+            radius: 1.5,
+            threshold: 1.0,
+        };
+        let out = PostProcess::default().with_bloom(bloom).apply(&image);
+        // Half of the excess is spread around, mostly nearby.
+        let added: f32 = out
+            .data()
+            .iter()
+            .zip(image.data())
+            .map(|(a, b)| a - b)
+            .sum();
+        assert!((added - 3.0 * 50.0).abs() < 0.01, "{}", added);
+        let near = out.get_rgb((8, 5).into()).g - 0.5;
+        let far = out.get_rgb((11, 5).into()).g - 0.5;
+        assert!(near > 1.0 && far > 0.0 && far < near / 10.0);
+        assert_eq!(out.get_rgb((0, 0).into()), Rgb::splat(0.5));
+    }
+
+    #[test]
+    fn test_parse() {
+        let post: PostProcess =
+            "exposure=-1.5, tonemap=reinhard:8, bloom=0.2:4, encoding=gamma:2.2"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            post,
+            PostProcess::default()
+                .with_exposure(-1.5)
+                .with_tone_map(ToneMap::Reinhard { white: Some(8.0) })
+                .with_bloom(Bloom {
+                    strength: 0.2,
+                    radius: 4.0,
+                    threshold: 1.0
+                })
+                .with_encoding(Encoding::Gamma(2.2))
+        );
+        assert_eq!("".parse::<PostProcess>().unwrap(), PostProcess::default());
+        let aces: PostProcess = "tonemap=aces,encoding=srgb".parse().unwrap();
+        assert_eq!(aces.tone_map, ToneMap::AcesFilmic);
+        assert!("tonemap=filmic".parse::<PostProcess>().is_err());
+        assert!("exposure".parse::<PostProcess>().is_err());
+        assert!("bloom=a".parse::<PostProcess>().is_err());
+        assert!("encoding=gamma:-1".parse::<PostProcess>().is_err());
+    }
+
+    #[test]
+    fn test_encoded_png() {
+        // Encoding to sRGB here and storing the values as they are matches
+        // the PNG writer's own encoding.
+        let image = test_image().select_channels(&["R", "G", "B"]).unwrap();
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        png::write(&image, &mut a, false).unwrap();
+        let encoded = PostProcess::default()
+            .with_encoding(Encoding::Srgb)
+            .apply(&image);
+        png::write_encoded(&encoded, &mut b, false).unwrap();
+        assert_eq!(
+            png::read(&mut &a[..]).unwrap(),
+            png::read(&mut &b[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_float_output() {
+        let image = test_image().select_channels(&["R", "G", "B"]).unwrap();
+        let path = std::env::temp_dir().join("pbrtrs_postprocess_test.pfm");
+        let post = PostProcess::default().with_encoding(Encoding::Srgb);
+        post.write_image(&image, &path, &ImageMetadata::default())
+            .unwrap();
+        let (out, _) = Image::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(out.get_rgb((3, 4).into()), Rgb::splat(0.5));
+    }
+}
@@ -0,0 +1,203 @@
+#![allow(dead_code)]
+
+use crate::geometry::point::*;
+
+const PCG32_DEFAULT_STATE: u64 = 0x853c_49e6_748f_ea9b;
+const PCG32_DEFAULT_STREAM: u64 = 0xda3e_39cb_94b9_5bdb;
+const PCG32_MULT: u64 = 0x5851_f42d_4c95_7f2d;
+
+// Largest f32 below one, so that uniform samples stay in [0, 1).
+pub const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+pub const DOUBLE_ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// O'Neill's PCG32 generator: 64 bits of state, 32-bit outputs, and 2^63
+// selectable streams so that every pixel sample can have its own sequence.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl Rng {
+    pub fn new(sequence_index: u64, seed: u64) -> Self {
+        let mut rng = Self::default();
+        rng.set_sequence(sequence_index, seed);
+        rng
+    }
+
+    pub fn set_sequence(&mut self, sequence_index: u64, seed: u64) {
+        self.state = 0;
+        self.inc = (sequence_index << 1) | 1;
+        self.uniform_u32();
+        self.state = self.state.wrapping_add(seed);
+        self.uniform_u32();
+    }
+
+    pub fn uniform_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state.wrapping_mul(PCG32_MULT).wrapping_add(self.inc);
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rot = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rot)
+    }
+
+    pub fn uniform_u64(&mut self) -> u64 {
+        let hi = self.uniform_u32() as u64;
+        (hi << 32) | self.uniform_u32() as u64
+    }
+
+    pub fn uniform_f32(&mut self) -> f32 {
+        (self.uniform_u32() as f32 * 2.0_f32.powi(-32)).min(ONE_MINUS_EPSILON)
+    }
+
+    pub fn uniform_f64(&mut self) -> f64 {
+        (self.uniform_u64() as f64 * 2.0_f64.powi(-64)).min(DOUBLE_ONE_MINUS_EPSILON)
+    }
+
+    // Uniform integer in [0, bound). Outputs below 2^32 mod bound are
+    // rejected so that every value is equally likely.
+    pub fn uniform_u32_bounded(&mut self, bound: u32) -> u32 {
+        assert!(bound > 0);
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let r = self.uniform_u32();
+            if r >= threshold {
+                return r % bound;
+            }
+        }
+    }
+
+    // Uniform integer in [min, max).
+    pub fn uniform_i32_range(&mut self, min: i32, max: i32) -> i32 {
+        assert!(min < max);
+        let range = max.wrapping_sub(min) as u32;
+        min.wrapping_add(self.uniform_u32_bounded(range) as i32)
+    }
+
+    // Skips delta outputs ahead (or back, for negative delta) in O(log delta)
+    // steps.
+    pub fn advance(&mut self, delta: i64) {
+        let (mut cur_mult, mut cur_plus) = (PCG32_MULT, self.inc);
+        let (mut acc_mult, mut acc_plus) = (1u64, 0u64);
+        let mut delta = delta as u64;
+        while delta > 0 {
+            if delta & 1 != 0 {
+                acc_mult = acc_mult.wrapping_mul(cur_mult);
+                acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+            }
+            cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+            cur_mult = cur_mult.wrapping_mul(cur_mult);
+            delta /= 2;
+        }
+        self.state = acc_mult.wrapping_mul(self.state).wrapping_add(acc_plus);
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self {
+            state: PCG32_DEFAULT_STATE,
+            inc: PCG32_DEFAULT_STREAM,
+        }
+    }
+}
+
+// Finalizer of a 64-bit hash that spreads every input bit over the whole
+// output, for turning structured values like coordinates into seeds.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+// Seed for the random stream of a pixel under a global seed.
+pub fn hash_pixel(pixel: Point2<i32>, seed: u64) -> u64 {
+    let packed = ((pixel.x() as u32 as u64) << 32) | pixel.y() as u32 as u64;
+    mix_bits(packed ^ mix_bits(seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reference_outputs() {
+        // First outputs of the reference pcg32-demo seeded with
+        // pcg32_srandom_r(&rng, 42, 54).
+        let mut rng = Rng::new(54, 42);
+        let outputs: Vec<u32> = (0..6).map(|_| rng.uniform_u32()).collect();
+        assert_eq!(
+            outputs,
+            vec![
+                0xa15c_02b7,
+                0x7b47_f409,
+                0xba1d_3330,
+                0x83d2_f293,
+                0xbfa4_784b,
+                0xcbed_606e
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut rng = Rng::new(1, 2);
+        let mut counts = [0; 6];
+        for _ in 0..6000 {
+            counts[rng.uniform_u32_bounded(6) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&c| (900..1100).contains(&c)));
+        for _ in 0..1000 {
+            let i = rng.uniform_i32_range(-3, 4);
+            assert!((-3..4).contains(&i));
+        }
+        // Powers of two never reject, so they are the output's low bits.
+        let mut a = Rng::new(5, 5);
+        let mut b = a;
+        assert_eq!(a.uniform_u32_bounded(16), b.uniform_u32() & 15);
+    }
+
+    #[test]
+    fn test_uniform_f64() {
+        let mut a = Rng::new(8, 9);
+        let mut b = a;
+        let u = a.uniform_f64();
+        assert!((0.0..1.0).contains(&u));
+        assert_eq!(u, b.uniform_u64() as f64 / 2.0_f64.powi(64));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_hash_pixel() {
+        assert_eq!(mix_bits(0), 0);
+        let a = hash_pixel((1, 2).into(), 0);
+        assert_ne!(a, hash_pixel((2, 1).into(), 0));
+        assert_ne!(a, hash_pixel((1, 2).into(), 1));
+        assert_ne!(hash_pixel((-1, 0).into(), 0), hash_pixel((0, -1).into(), 0));
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut a = Rng::new(3, 7);
+        let mut b = a;
+        for _ in 0..1000 {
+            a.uniform_u32();
+        }
+        b.advance(1000);
+        assert_eq!(a, b);
+        b.advance(-1000);
+        assert_eq!(b, Rng::new(3, 7));
+    }
+
+    #[test]
+    fn test_uniform_f32() {
+        let mut rng = Rng::new(0, 0);
+        for _ in 0..1000 {
+            let u = rng.uniform_f32();
+            assert!((0.0..1.0).contains(&u));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::geometry::point::*;
+use crate::rng::*;
+use crate::sampler::lowdiscrepancy::*;
+use crate::sampler::*;
+
+// Scales above this would make the stride between a pixel's samples very
+// large, so the Halton pattern repeats every 128 pixels at most.
+const MAX_HALTON_RESOLUTION: i32 = 128;
+
+// The Halton sequence with one prime base per dimension. The first two
+// dimensions are spread over the whole image: the sequence indices whose
+// base-2 and base-3 radical inverses fall inside a pixel are exactly the ones
+// used for it, so the pixel samples of neighbouring pixels fit together.
+#[derive(Copy, Clone, Debug)]
+pub struct HaltonSampler {
+    samples_per_pixel: u32,
+    randomize: RandomizeStrategy,
+    seed: u64,
+    base_scales: [u64; 2],
+    base_exponents: [u32; 2],
+    mult_inverse: [u64; 2],
+    halton_index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(
+        samples_per_pixel: u32,
+        full_resolution: Point2<i32>,
+        randomize: RandomizeStrategy,
+        seed: u64,
+    ) -> Self {
+        // Find the smallest power of each base that covers the image (up to
+        // the maximum resolution).
+        let mut base_scales = [0; 2];
+        let mut base_exponents = [0; 2];
+        for i in 0..2 {
+            let base = PRIMES[i];
+            let (mut scale, mut exp) = (1, 0);
+            while scale < full_resolution[i].min(MAX_HALTON_RESOLUTION) as u64 {
+                scale *= base;
+                exp += 1;
+            }
+            base_scales[i] = scale;
+            base_exponents[i] = exp;
+        }
+        let mult_inverse = [
+            multiplicative_inverse(base_scales[1], base_scales[0]),
+            multiplicative_inverse(base_scales[0], base_scales[1]),
+        ];
+        Self {
+            samples_per_pixel,
+            randomize,
+            seed,
+            base_scales,
+            base_exponents,
+            mult_inverse,
+            halton_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample_dimension(&self, dimension: usize) -> f32 {
+        match self.randomize {
+            RandomizeStrategy::None => radical_inverse(dimension, self.halton_index),
+            RandomizeStrategy::PermuteDigits => {
+                scrambled_radical_inverse(dimension, self.halton_index, self.seed)
+            }
+            RandomizeStrategy::Owen => {
+                let hash = mix_bits((1 + ((dimension as u64) << 4)) ^ self.seed);
+                owen_scrambled_radical_inverse(dimension, self.halton_index, hash as u32)
+            }
+        }
+    }
+}
+
+// x such that a * x = 1 (mod n), for coprime a and n.
+fn multiplicative_inverse(a: u64, n: u64) -> u64 {
+    let (_, x, _) = extended_gcd(a as i64, n as i64);
+    x.rem_euclid(n as i64) as u64
+}
+
+// Returns (gcd, x, y) with a * x + b * y = gcd.
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+impl Sampler for HaltonSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        // The index of the pixel's first sample is the one whose leading
+        // base-2 and base-3 digits reverse to the pixel's coordinates (modulo
+        // the scales); the Chinese remainder theorem combines the two.
+        self.halton_index = 0;
+        let sample_stride = self.base_scales[0] * self.base_scales[1];
+        if sample_stride > 1 {
+            for i in 0..2 {
+                let pm = pixel[i].rem_euclid(MAX_HALTON_RESOLUTION) as u64;
+                let dim_offset = inverse_radical_inverse(pm, PRIMES[i], self.base_exponents[i]);
+                self.halton_index +=
+                    dim_offset * (sample_stride / self.base_scales[i]) * self.mult_inverse[i];
+            }
+            self.halton_index %= sample_stride;
+        }
+        self.halton_index += index as u64 * sample_stride;
+        self.dimension = (dimension as usize).max(2);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        if self.dimension >= PRIME_TABLE_SIZE {
+            self.dimension = 2;
+        }
+        self.dimension += 1;
+        self.sample_dimension(self.dimension - 1)
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        if self.dimension + 1 >= PRIME_TABLE_SIZE {
+            self.dimension = 2;
+        }
+        let dim = self.dimension;
+        self.dimension += 2;
+        Point2::<f32>::elements(self.sample_dimension(dim), self.sample_dimension(dim + 1))
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        // Drop the digits that select the pixel; what is left places the
+        // sample inside it.
+        Point2::<f32>::elements(
+            radical_inverse(0, self.halton_index >> self.base_exponents[0]),
+            radical_inverse(1, self.halton_index / self.base_scales[1]),
+        )
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pixel_indices() {
+        let mut sampler = HaltonSampler::new(8, (16, 16).into(), RandomizeStrategy::None, 0);
+        assert_eq!(sampler.base_scales, [16, 27]);
+        for &(x, y) in &[(0, 0), (5, 7), (15, 3), (9, 14)] {
+            for index in 0..8 {
+                sampler.start_pixel_sample((x, y).into(), index, 0);
+                // The full radical inverses of the index land in the pixel.
+                let u = radical_inverse(0, sampler.halton_index) * 16.0;
+                let v = radical_inverse(1, sampler.halton_index) * 27.0;
+                assert_eq!((u as i32, v as i32), (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        for &randomize in &[
+            RandomizeStrategy::None,
+            RandomizeStrategy::PermuteDigits,
+            RandomizeStrategy::Owen,
+        ] {
+            let mut a = HaltonSampler::new(16, (64, 32).into(), randomize, 3);
+            let mut b = a.clone_for_thread();
+            a.start_pixel_sample((7, 2).into(), 5, 0);
+            b.start_pixel_sample((7, 2).into(), 5, 0);
+            for _ in 0..300 {
+                let (u, v) = (a.get_2d(), b.get_2d());
+                assert_eq!(u, v);
+                assert!((0.0..1.0).contains(&u.x()) && (0.0..1.0).contains(&u.y()));
+            }
+            let p = a.get_pixel_2d();
+            assert!((0.0..1.0).contains(&p.x()) && (0.0..1.0).contains(&p.y()));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::geometry::point::*;
+use crate::rng::*;
+use crate::sampler::*;
+
+// Uniform random values with no correlation between samples or dimensions.
+// Mostly useful as a baseline for the better-distributed samplers.
+#[derive(Copy, Clone, Debug)]
+pub struct IndependentSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel,
+            seed,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        // Each pixel gets its own stream, and each sample within it a window
+        // of 2^16 values so that dimensions line up across samples.
+        self.rng.set_sequence(hash_pixel(pixel, self.seed), 0);
+        self.rng.advance(index as i64 * 65536 + dimension as i64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.uniform_f32()
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let x = self.rng.uniform_f32();
+        Point2::<f32>::elements(x, self.rng.uniform_f32())
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        self.get_2d()
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = IndependentSampler::new(4, 1);
+        let mut b = a.clone_for_thread();
+        a.start_pixel_sample((3, 4).into(), 2, 0);
+        let first: Vec<f32> = (0..8).map(|_| a.get_1d()).collect();
+        b.start_pixel_sample((3, 4).into(), 2, 0);
+        let second: Vec<f32> = (0..8).map(|_| b.get_1d()).collect();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|u| (0.0..1.0).contains(u)));
+
+        // Starting at a later dimension picks up the same sequence.
+        b.start_pixel_sample((3, 4).into(), 2, 5);
+        assert_eq!(b.get_1d(), first[5]);
+
+        a.start_pixel_sample((3, 5).into(), 2, 0);
+        assert_ne!(a.get_1d(), first[0]);
+    }
+}
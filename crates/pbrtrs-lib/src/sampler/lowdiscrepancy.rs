@@ -0,0 +1,326 @@
+use crate::rng::*;
+use crate::sampler::*;
+
+pub const PRIME_TABLE_SIZE: usize = 256;
+
+pub const PRIMES: [u64; PRIME_TABLE_SIZE] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311, 313, 317, 331, 337, 347, 349, 353, 359, 367, 373, 379, 383, 389, 397, 401, 409, 419, 421,
+    431, 433, 439, 443, 449, 457, 461, 463, 467, 479, 487, 491, 499, 503, 509, 521, 523, 541, 547,
+    557, 563, 569, 571, 577, 587, 593, 599, 601, 607, 613, 617, 619, 631, 641, 643, 647, 653, 659,
+    661, 673, 677, 683, 691, 701, 709, 719, 727, 733, 739, 743, 751, 757, 761, 769, 773, 787, 797,
+    809, 811, 821, 823, 827, 829, 839, 853, 857, 859, 863, 877, 881, 883, 887, 907, 911, 919, 929,
+    937, 941, 947, 953, 967, 971, 977, 983, 991, 997, 1009, 1013, 1019, 1021, 1031, 1033, 1039,
+    1049, 1051, 1061, 1063, 1069, 1087, 1091, 1093, 1097, 1103, 1109, 1117, 1123, 1129, 1151, 1153,
+    1163, 1171, 1181, 1187, 1193, 1201, 1213, 1217, 1223, 1229, 1231, 1237, 1249, 1259, 1277, 1279,
+    1283, 1289, 1291, 1297, 1301, 1303, 1307, 1319, 1321, 1327, 1361, 1367, 1373, 1381, 1399, 1409,
+    1423, 1427, 1429, 1433, 1439, 1447, 1451, 1453, 1459, 1471, 1481, 1483, 1487, 1489, 1493, 1499,
+    1511, 1523, 1531, 1543, 1549, 1553, 1559, 1567, 1571, 1579, 1583, 1597, 1601, 1607, 1609, 1613,
+    1619,
+];
+
+// How a low-discrepancy sequence is randomized. Scrambling keeps the
+// stratification of the sequence while decorrelating pixels and removing the
+// structured artifacts of the unscrambled points.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RandomizeStrategy {
+    None,
+    // A random permutation of the digit values at each digit position; for
+    // base 2 this is an XOR with a random bit pattern.
+    PermuteDigits,
+    // Nested uniform (Owen) scrambling, where the permutation of each digit
+    // also depends on all the digits before it.
+    Owen,
+}
+
+// Radical inverse of a in the base of the given prime, i.e. the digits of a
+// mirrored around the decimal point.
+pub fn radical_inverse(base_index: usize, mut a: u64) -> f32 {
+    let base = PRIMES[base_index];
+    let inv_base = 1.0 / base as f64;
+    let (mut reversed, mut inv_base_m) = (0u64, 1.0);
+    // Stop before reversed overflows; later digits are below f32 precision.
+    let limit = u64::MAX / base - base;
+    while a != 0 && reversed < limit {
+        let next = a / base;
+        reversed = reversed * base + (a - next * base);
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    ((reversed as f64 * inv_base_m) as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Recovers the index from the n_digits-digit radical inverse, given as the
+// integer formed by its reversed digits.
+pub fn inverse_radical_inverse(mut inverse: u64, base: u64, n_digits: u32) -> u64 {
+    let mut index = 0;
+    for _ in 0..n_digits {
+        let digit = inverse % base;
+        inverse /= base;
+        index = index * base + digit;
+    }
+    index
+}
+
+// Radical inverse with every digit position permuted independently by a
+// permutation derived from seed. Unlike the plain radical inverse, the
+// (infinitely many) trailing zero digits are permuted too, so digits are
+// generated until they no longer affect the result.
+pub fn scrambled_radical_inverse(base_index: usize, mut a: u64, seed: u64) -> f32 {
+    let base = PRIMES[base_index];
+    let inv_base = 1.0 / base as f32;
+    let (mut reversed, mut inv_base_m) = (0u64, 1.0f32);
+    let limit = u64::MAX / base - base;
+    let mut digit_index = 0u64;
+    while 1.0 - (base - 1) as f32 * inv_base_m < 1.0 && reversed < limit {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let p = mix_bits(seed ^ (base_index as u64) << 32 ^ digit_index) as u32;
+        reversed = reversed * base + permutation_element(digit, base as u32, p) as u64;
+        inv_base_m *= inv_base;
+        digit_index += 1;
+        a = next;
+    }
+    (inv_base_m * reversed as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Radical inverse with Owen scrambling: the permutation applied to each
+// digit is chosen by hashing the digits that precede it.
+pub fn owen_scrambled_radical_inverse(base_index: usize, mut a: u64, hash: u32) -> f32 {
+    let base = PRIMES[base_index];
+    let inv_base = 1.0 / base as f32;
+    let (mut reversed, mut inv_base_m) = (0u64, 1.0f32);
+    let limit = u64::MAX / base - base;
+    while 1.0 - (base - 1) as f32 * inv_base_m < 1.0 && reversed < limit {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let digit_hash = mix_bits(hash as u64 ^ reversed) as u32;
+        reversed = reversed * base + permutation_element(digit, base as u32, digit_hash) as u64;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (inv_base_m * reversed as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Primitive polynomials and initial direction numbers from Joe and Kuo,
+// "Constructing Sobol sequences with better two-dimensional projections"
+// (new-joe-kuo-6.21201), for dimensions 1 and up: degree s, the polynomial's
+// interior coefficients a, and m_1..m_s. The samplers only draw 2D Sobol
+// points, so only dimension 1 is needed.
+const JOE_KUO: [(u32, u32, [u32; 1]); 1] = [(1, 0, [1])];
+
+pub const N_SOBOL_DIMENSIONS: usize = JOE_KUO.len() + 1;
+
+// 32-bit generator matrix columns of every Sobol dimension, most significant
+// bit first, built at compile time. Dimension 0 is the van der Corput
+// sequence.
+static SOBOL_MATRICES: [[u32; 32]; N_SOBOL_DIMENSIONS] = sobol_matrices();
+
+const fn sobol_matrices() -> [[u32; 32]; N_SOBOL_DIMENSIONS] {
+    let mut matrices = [[0u32; 32]; N_SOBOL_DIMENSIONS];
+    let mut k = 0;
+    while k < 32 {
+        matrices[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+    let mut dim = 1;
+    while dim < N_SOBOL_DIMENSIONS {
+        let (s, a, m) = JOE_KUO[dim - 1];
+        let s = s as usize;
+        let v = &mut matrices[dim];
+        let mut k = 0;
+        while k < 32 {
+            v[k] = if k < s {
+                m[k] << (31 - k)
+            } else {
+                let mut vk = v[k - s] ^ (v[k - s] >> s);
+                let mut i = 1;
+                while i < s {
+                    if (a >> (s - 1 - i)) & 1 != 0 {
+                        vk ^= v[k - i];
+                    }
+                    i += 1;
+                }
+                vk
+            };
+            k += 1;
+        }
+        dim += 1;
+    }
+    matrices
+}
+
+pub fn sobol_generator_matrix(dim: usize) -> &'static [u32; 32] {
+    &SOBOL_MATRICES[dim]
+}
+
+// Scrambling of the 32-bit fixed-point value of a base-2 sample.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SobolScrambler {
+    None,
+    // XOR with a random bit pattern (random digit permutation in base 2).
+    BinaryPermute(u32),
+    // Laine and Karras' hash that approximates Owen scrambling cheaply.
+    FastOwen(u32),
+    // Exact nested uniform scrambling, one hashed bit flip per bit.
+    Owen(u32),
+}
+
+impl SobolScrambler {
+    pub fn from_strategy(strategy: RandomizeStrategy, seed: u32) -> Self {
+        match strategy {
+            RandomizeStrategy::None => SobolScrambler::None,
+            RandomizeStrategy::PermuteDigits => SobolScrambler::BinaryPermute(seed),
+            RandomizeStrategy::Owen => SobolScrambler::Owen(seed),
+        }
+    }
+
+    pub fn scramble(self, mut v: u32) -> u32 {
+        match self {
+            SobolScrambler::None => v,
+            SobolScrambler::BinaryPermute(seed) => v ^ seed,
+            SobolScrambler::FastOwen(seed) => {
+                v = v.reverse_bits();
+                v ^= v.wrapping_mul(0x3d20_adea);
+                v = v.wrapping_add(seed);
+                v = v.wrapping_mul((seed >> 16) | 1);
+                v ^= v.wrapping_mul(0x0552_6c56);
+                v ^= v.wrapping_mul(0x53a2_2864);
+                v.reverse_bits()
+            }
+            SobolScrambler::Owen(seed) => {
+                if seed & 1 != 0 {
+                    v ^= 1 << 31;
+                }
+                for b in 1..32 {
+                    let mask = !0u32 << (32 - b);
+                    if (mix_bits(((v & mask) ^ seed) as u64) as u32) & (1 << b) != 0 {
+                        v ^= 1 << (31 - b);
+                    }
+                }
+                v
+            }
+        }
+    }
+}
+
+// Sample a of Sobol dimension dim, scrambled.
+pub fn sobol_sample(mut a: u64, dim: usize, scrambler: SobolScrambler) -> f32 {
+    let matrix = sobol_generator_matrix(dim);
+    let mut v = 0u32;
+    let mut i = 0;
+    while a != 0 && i < 32 {
+        if a & 1 != 0 {
+            v ^= matrix[i];
+        }
+        a >>= 1;
+        i += 1;
+    }
+    let v = scrambler.scramble(v);
+    (v as f32 * 2.0_f32.powi(-32)).min(ONE_MINUS_EPSILON)
+}
+
+// Shuffles sample indices so that every aligned power-of-two block of
+// indices maps onto an aligned block of the same size, though not
+// necessarily one inside the original range. Every such block of a (0,2)
+// sequence is itself a (0,2)-net, so this decorrelates dimension pairs while
+// keeping every prefix of length 2^k well stratified (Burley, "Practical
+// Hash-based Owen Scrambling").
+pub fn nested_uniform_shuffle(index: u32, seed: u32) -> u32 {
+    SobolScrambler::FastOwen(seed).scramble(index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Checks that the first 2^m points of a 2D sequence form a (0, m, 2)-net:
+    // every elementary interval of area 2^-m holds exactly one point.
+    fn is_02_net(points: &[(f32, f32)]) -> bool {
+        let m = points.len().trailing_zeros();
+        (0..=m).all(|k| {
+            let (nx, ny) = (1usize << k, 1usize << (m - k));
+            let mut cells = vec![0; nx * ny];
+            for &(x, y) in points {
+                cells[(y * ny as f32) as usize * nx + (x * nx as f32) as usize] += 1;
+            }
+            cells.iter().all(|&c| c == 1)
+        })
+    }
+
+    fn is_1d_stratified(values: &[f32]) -> bool {
+        let n = values.len();
+        let mut bins = vec![0; n];
+        for &v in values {
+            bins[(v * n as f32) as usize] += 1;
+        }
+        bins.iter().all(|&c| c == 1)
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(0, 1), 0.5);
+        assert_eq!(radical_inverse(0, 6), 0.375);
+        assert!((radical_inverse(1, 5) - 7.0 / 9.0).abs() < 1e-6);
+        assert_eq!(inverse_radical_inverse(0b011, 2, 3), 0b110);
+    }
+
+    #[test]
+    fn test_scrambled_radical_inverse_stratified() {
+        for &base_index in &[0, 1, 2] {
+            let n = PRIMES[base_index].pow(3);
+            let permuted: Vec<f32> = (0..n)
+                .map(|i| scrambled_radical_inverse(base_index, i, 42))
+                .collect();
+            assert!(is_1d_stratified(&permuted));
+            let owen: Vec<f32> = (0..n)
+                .map(|i| owen_scrambled_radical_inverse(base_index, i, 42))
+                .collect();
+            assert!(is_1d_stratified(&owen));
+            assert_ne!(permuted[1], radical_inverse(base_index, 1));
+        }
+    }
+
+    #[test]
+    fn test_sobol_nets() {
+        let sobol = |i: u64, dim: usize, s: SobolScrambler| sobol_sample(i, dim, s);
+        // Dimension 1 of the sequence starts 0, 1/2, 3/4, 1/4, 5/8, 1/8, ...
+        let first: Vec<f32> = (0..6).map(|i| sobol(i, 1, SobolScrambler::None)).collect();
+        assert_eq!(first, vec![0.0, 0.5, 0.75, 0.25, 0.625, 0.125]);
+
+        for &scrambler in &[
+            SobolScrambler::None,
+            SobolScrambler::BinaryPermute(0x1234_5678),
+            SobolScrambler::FastOwen(0x9abc_def0),
+            SobolScrambler::Owen(0x0bad_f00d),
+        ] {
+            let points: Vec<(f32, f32)> = (0..64)
+                .map(|i| (sobol(i, 0, scrambler), sobol(i, 1, scrambler)))
+                .collect();
+            assert!(is_02_net(&points), "{:?}", scrambler);
+            for dim in 0..N_SOBOL_DIMENSIONS {
+                let values: Vec<f32> = (0..256).map(|i| sobol(i, dim, scrambler)).collect();
+                assert!(is_1d_stratified(&values), "dimension {}", dim);
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_uniform_shuffle() {
+        let shuffled: Vec<u32> = (0..64).map(|i| nested_uniform_shuffle(i, 77)).collect();
+        for block in 0..8 {
+            let mut ids: Vec<u32> = shuffled[block * 8..block * 8 + 8]
+                .iter()
+                .map(|i| i / 8)
+                .collect();
+            ids.dedup();
+            assert_eq!(ids.len(), 1);
+        }
+        let mut sorted = shuffled.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 64);
+    }
+}
@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+
+pub mod halton;
+pub mod independent;
+pub mod lowdiscrepancy;
+pub mod pmj02;
+pub mod sobol;
+pub mod stratified;
+pub mod zsobol;
+
+use crate::camera::CameraSample;
+use crate::filter::Filter;
+use crate::geometry::point::*;
+use crate::rng::*;
+
+// Source of sample values for one pixel sample at a time. Each pixel sample
+// is a point in a high-dimensional unit cube; the renderer consumes its
+// dimensions in order through get_1d/get_2d. Samplers are deterministic:
+// the same pixel, sample index, and dimension always produce the same value.
+pub trait Sampler: Send {
+    fn samples_per_pixel(&self) -> u32;
+
+    // Starts generating sample `index` of `pixel`, with the first value
+    // coming from `dimension`.
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32);
+
+    fn get_1d(&mut self) -> f32;
+
+    fn get_2d(&mut self) -> Point2<f32>;
+
+    // Sample for the position within the pixel. Samplers that distribute
+    // samples specially over the image plane can treat it differently from
+    // other 2D dimensions.
+    fn get_pixel_2d(&mut self) -> Point2<f32>;
+
+    // A fresh sampler with the same configuration for use on another
+    // thread.
+    fn clone_for_thread(&self) -> Box<dyn Sampler>;
+}
+
+// Draws the film, time, and lens values of a camera sample for pixel, in
+// that order. The film position is offset from the pixel center by a sample
+// of the reconstruction filter; the filter weight to record the sample with
+// is returned alongside.
+pub fn get_camera_sample(
+    sampler: &mut dyn Sampler,
+    pixel: Point2<i32>,
+    filter: &dyn Filter,
+) -> (CameraSample, f32) {
+    let fs = filter.sample(sampler.get_pixel_2d());
+    let sample = CameraSample {
+        p_film: Point2::<f32>::elements(pixel.x() as f32 + 0.5, pixel.y() as f32 + 0.5) + fs.p,
+        time: sampler.get_1d(),
+        p_lens: sampler.get_2d(),
+    };
+    (sample, fs.weight)
+}
+
+// Hash of a pixel, dimension, and seed, e.g. for choosing a permutation.
+pub(crate) fn hash_pixel_dimension(pixel: Point2<i32>, dimension: u32, seed: u64) -> u64 {
+    mix_bits(hash_pixel(pixel, seed) ^ dimension as u64)
+}
+
+// Element i of a random permutation of 0..len chosen by p, computed without
+// storing the permutation (Kensler, "Correlated Multi-Jittered Sampling").
+// len must be positive.
+pub fn permutation_element(mut i: u32, len: u32, p: u32) -> u32 {
+    debug_assert!(len > 0);
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    ((i as u64 + p as u64) % len as u64) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permutation_element() {
+        for &len in &[1, 2, 7, 16, 100] {
+            for &p in &[0, 1, 0xdead_beef] {
+                let mut seen = vec![false; len as usize];
+                for i in 0..len {
+                    let e = permutation_element(i, len, p);
+                    assert!(!seen[e as usize]);
+                    seen[e as usize] = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use crate::geometry::point::*;
+use crate::rng::*;
+use crate::sampler::*;
+use std::sync::Arc;
+
+// Number of independent point sets; dimension pairs and pixels choose
+// among them.
+const N_SETS: usize = 4;
+
+// Progressive multi-jittered (0,2) samples (Christensen et al., "Progressive
+// Multi-Jittered Sample Sequences"). The points are built once, when the
+// sampler is created: every power-of-two prefix of a set is stratified over
+// all elementary intervals and jittered within them. Pixel samples use the
+// sets in order, so partial renders stay well distributed; other dimensions
+// visit a set's points in a per-pixel shuffled order so that dimension pairs
+// are not correlated. Each pixel and dimension XORs the points with its own
+// random bits, which keeps the stratification. The sample count should be a
+// power of two; building the sets takes time quadratic in it.
+#[derive(Clone, Debug)]
+pub struct Pmj02Sampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    // 32-bit fixed-point coordinates.
+    sets: Arc<Vec<Vec<(u32, u32)>>>,
+    pixel: Point2<i32>,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl Pmj02Sampler {
+    // Returns None for a zero sample count or one too large to round up to
+    // a power of two.
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Option<Self> {
+        if samples_per_pixel == 0 {
+            return None;
+        }
+        let n = samples_per_pixel.checked_next_power_of_two()? as usize;
+        let sets = (0..N_SETS)
+            .map(|i| generate_pmj02(n, &mut Rng::new(i as u64, seed)))
+            .collect();
+        Some(Self {
+            samples_per_pixel,
+            seed,
+            sets: Arc::new(sets),
+            pixel: Point2::<i32>::default(),
+            sample_index: 0,
+            dimension: 0,
+        })
+    }
+
+    fn sample(&mut self, n_dimensions: u32, shuffle: bool) -> Point2<f32> {
+        let hash = hash_pixel_dimension(self.pixel, self.dimension, self.seed);
+        self.dimension += n_dimensions;
+        let set = &self.sets[(hash % N_SETS as u64) as usize];
+        let index = if shuffle {
+            permutation_element(self.sample_index, self.samples_per_pixel, hash as u32)
+        } else {
+            self.sample_index
+        } as usize;
+        let (x, y) = set[index % set.len()];
+        let scramble = mix_bits(hash);
+        let to_float = |v: u32| (v >> 8) as f32 * 2.0_f32.powi(-24);
+        Point2::<f32>::elements(
+            to_float(x ^ scramble as u32),
+            to_float(y ^ (scramble >> 32) as u32),
+        )
+    }
+}
+
+impl Sampler for Pmj02Sampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        self.pixel = pixel;
+        self.sample_index = index;
+        self.dimension = dimension;
+    }
+
+    // The points' 1D projections are stratified too.
+    fn get_1d(&mut self) -> f32 {
+        self.sample(1, true).x()
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        self.sample(2, true)
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        self.sample(2, false)
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// n points (a power of two) of a pmj02 sequence. Starting from one random
+// point, the sequence is doubled by placing a point in the diagonally
+// opposite quarter of each old point's cell, then doubled again by filling
+// the two remaining quarters. Each new point goes into a random fine
+// stratum that no earlier point shares in any elementary interval; should
+// that leave no choice, the sequence is built again.
+fn generate_pmj02(n: usize, rng: &mut Rng) -> Vec<(u32, u32)> {
+    'retry: loop {
+        let mut points = vec![(rng.uniform_u32(), rng.uniform_u32())];
+        // Points are placed into cells of a g x g grid, g = 2^log_g.
+        let mut log_g = 0;
+        while points.len() < n {
+            let len = points.len();
+            let mut strata = Strata::new(&points, 2 * len);
+            if len == 1 << (2 * log_g) {
+                for s in 0..len {
+                    let (qx, qy) = quarter(points[s], log_g);
+                    match strata.place(points[s], log_g, (1 - qx, 1 - qy), rng) {
+                        Some(p) => points.push(p),
+                        None => continue 'retry,
+                    }
+                }
+            } else {
+                let mut second = Vec::with_capacity(len / 2);
+                for s in 0..len / 2 {
+                    let (qx, qy) = quarter(points[s], log_g);
+                    let mut quarters = [(1 - qx, qy), (qx, 1 - qy)];
+                    if rng.uniform_u32() & 1 != 0 {
+                        quarters.swap(0, 1);
+                    }
+                    let a = strata.place(points[s], log_g, quarters[0], rng);
+                    let b = strata.place(points[s], log_g, quarters[1], rng);
+                    match (a, b) {
+                        (Some(a), Some(b)) => {
+                            points.push(a);
+                            second.push(b);
+                        }
+                        _ => continue 'retry,
+                    }
+                }
+                points.extend(second);
+                log_g += 1;
+            }
+        }
+        return points;
+    }
+}
+
+// Which quarter of its cell in the 2^log_g grid a point lies in.
+fn quarter(p: (u32, u32), log_g: u32) -> (u32, u32) {
+    ((p.0 >> (31 - log_g)) & 1, (p.1 >> (31 - log_g)) & 1)
+}
+
+// The first `bits` bits of a fixed-point value.
+fn top_bits(v: u32, bits: u32) -> usize {
+    if bits == 0 {
+        0
+    } else {
+        (v >> (32 - bits)) as usize
+    }
+}
+
+// Occupied elementary intervals of every shape for 2^m points.
+struct Strata {
+    m: u32,
+    // Indexed by k, for intervals 2^-k wide and 2^(k-m) high.
+    occupied: Vec<Vec<bool>>,
+}
+
+impl Strata {
+    fn new(points: &[(u32, u32)], count: usize) -> Self {
+        let m = count.trailing_zeros();
+        let mut strata = Self {
+            m,
+            occupied: vec![vec![false; count]; m as usize + 1],
+        };
+        for &p in points {
+            strata.mark(top_bits(p.0, m), top_bits(p.1, m));
+        }
+        strata
+    }
+
+    fn index(&self, k: u32, x: usize, y: usize) -> usize {
+        ((y >> k) << k) | (x >> (self.m - k))
+    }
+
+    fn is_free(&self, x: usize, y: usize) -> bool {
+        (0..=self.m).all(|k| !self.occupied[k as usize][self.index(k, x, y)])
+    }
+
+    // Fine strata are 2^-m on a side.
+    fn mark(&mut self, x: usize, y: usize) {
+        for k in 0..=self.m {
+            let i = self.index(k, x, y);
+            self.occupied[k as usize][i] = true;
+        }
+    }
+
+    // Places a jittered point in a free fine stratum of the given quarter of
+    // the cell holding p.
+    fn place(
+        &mut self,
+        p: (u32, u32),
+        log_g: u32,
+        (qx, qy): (u32, u32),
+        rng: &mut Rng,
+    ) -> Option<(u32, u32)> {
+        // The quarter spans 2^(m - log_g - 1) fine strata in each direction.
+        let shift = self.m - log_g - 1;
+        let x0 = ((top_bits(p.0, log_g) << 1) | qx as usize) << shift;
+        let y0 = ((top_bits(p.1, log_g) << 1) | qy as usize) << shift;
+        let free_x = (x0..x0 + (1 << shift)).filter(|&x| !self.occupied[self.m as usize][x]);
+        let candidates: Vec<(usize, usize)> = free_x
+            .flat_map(|x| {
+                (y0..y0 + (1 << shift))
+                    .filter(|&y| !self.occupied[0][y])
+                    .map(move |y| (x, y))
+            })
+            .filter(|&(x, y)| self.is_free(x, y))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let (x, y) = candidates[rng.uniform_u32_bounded(candidates.len() as u32) as usize];
+        self.mark(x, y);
+        let jitter = |i: usize, rng: &mut Rng| {
+            ((i as u64) << (32 - self.m)) as u32 | (rng.uniform_u32() >> self.m)
+        };
+        Some((jitter(x, rng), jitter(y, rng)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn is_02_net(points: &[Point2<f32>]) -> bool {
+        let m = points.len().trailing_zeros();
+        (0..=m).all(|k| {
+            let (nx, ny) = (1usize << k, 1usize << (m - k));
+            let mut cells = vec![0; nx * ny];
+            for p in points {
+                cells[(p.y() * ny as f32) as usize * nx + (p.x() * nx as f32) as usize] += 1;
+            }
+            cells.iter().all(|&c| c == 1)
+        })
+    }
+
+    #[test]
+    fn test_progressive_nets() {
+        for &n in &[64, 256, 512] {
+            let mut sampler = Pmj02Sampler::new(n, 5).unwrap();
+            let points: Vec<Point2<f32>> = (0..n)
+                .map(|index| {
+                    sampler.start_pixel_sample((8, 9).into(), index, 0);
+                    sampler.get_pixel_2d()
+                })
+                .collect();
+            // Every power-of-two prefix holds one point per elementary
+            // interval of every shape.
+            for m in 0..=n.trailing_zeros() {
+                assert!(is_02_net(&points[..1 << m]), "n = {}, m = {}", n, m);
+            }
+        }
+
+        // Shuffled dimensions keep the stratification of the whole set.
+        let mut sampler = Pmj02Sampler::new(128, 1).unwrap();
+        let mut points = Vec::new();
+        let mut bins = vec![0; 128];
+        for index in 0..128 {
+            sampler.start_pixel_sample((3, 2).into(), index, 4);
+            points.push(sampler.get_2d());
+            bins[(sampler.get_1d() * 128.0) as usize] += 1;
+        }
+        assert!(is_02_net(&points));
+        assert!(bins.iter().all(|&b| b == 1));
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = Pmj02Sampler::new(16, 2).unwrap();
+        let mut b = a.clone_for_thread();
+        a.start_pixel_sample((0, 0).into(), 7, 0);
+        b.start_pixel_sample((0, 0).into(), 7, 0);
+        for _ in 0..8 {
+            assert_eq!(a.get_2d(), b.get_2d());
+        }
+        a.start_pixel_sample((0, 0).into(), 7, 0);
+        b.start_pixel_sample((0, 0).into(), 7, 2);
+        // Dimension pairs are decorrelated.
+        assert_ne!(a.get_2d(), b.get_2d());
+    }
+
+    #[test]
+    fn test_no_samples() {
+        assert!(Pmj02Sampler::new(0, 0).is_none());
+        assert!(Pmj02Sampler::new(u32::MAX, 0).is_none());
+    }
+}
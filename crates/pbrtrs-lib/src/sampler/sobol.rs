@@ -0,0 +1,123 @@
+use crate::geometry::point::*;
+use crate::sampler::lowdiscrepancy::*;
+use crate::sampler::*;
+
+// Sobol points indexed per pixel and padded across dimensions: every 1D or
+// 2D request uses the first one or two Sobol dimensions, with the sample
+// index permuted and the values scrambled by a hash of the pixel and
+// dimension. Each pixel's samples are then well stratified in every
+// dimension on its own, without correlation between dimensions. The sample
+// count should be a power of two.
+#[derive(Copy, Clone, Debug)]
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    randomize: RandomizeStrategy,
+    seed: u64,
+    pixel: Point2<i32>,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, randomize: RandomizeStrategy, seed: u64) -> Self {
+        Self {
+            samples_per_pixel,
+            randomize,
+            seed,
+            pixel: Point2::<i32>::default(),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Permuted sample index and scrambling hash for the current dimension.
+    fn next_index(&mut self, n_dimensions: u32) -> (u64, u64) {
+        let hash = hash_pixel_dimension(self.pixel, self.dimension, self.seed);
+        self.dimension += n_dimensions;
+        let index = permutation_element(self.sample_index, self.samples_per_pixel, hash as u32);
+        (index as u64, hash)
+    }
+
+    fn sample_dimension(&self, dim: usize, index: u64, hash: u32) -> f32 {
+        sobol_sample(
+            index,
+            dim,
+            SobolScrambler::from_strategy(self.randomize, hash),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        self.pixel = pixel;
+        self.sample_index = index;
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (index, hash) = self.next_index(1);
+        self.sample_dimension(0, index, (hash >> 32) as u32)
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let (index, hash) = self.next_index(2);
+        Point2::<f32>::elements(
+            self.sample_dimension(0, index, hash as u32),
+            self.sample_dimension(1, index, (hash >> 32) as u32),
+        )
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        self.get_2d()
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pixel_stratified() {
+        for &randomize in &[
+            RandomizeStrategy::None,
+            RandomizeStrategy::PermuteDigits,
+            RandomizeStrategy::Owen,
+        ] {
+            let mut sampler = SobolSampler::new(16, randomize, 9);
+            let mut cells = [0; 16];
+            let mut bins = [0; 16];
+            for index in 0..16 {
+                sampler.start_pixel_sample((4, -3).into(), index, 0);
+                let p = sampler.get_pixel_2d();
+                cells[(p.y() * 4.0) as usize * 4 + (p.x() * 4.0) as usize] += 1;
+                sampler.start_pixel_sample((4, -3).into(), index, 7);
+                bins[(sampler.get_1d() * 16.0) as usize] += 1;
+            }
+            assert!(cells.iter().all(|&c| c == 1), "{:?}", randomize);
+            assert!(bins.iter().all(|&c| c == 1), "{:?}", randomize);
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = SobolSampler::new(8, RandomizeStrategy::Owen, 1);
+        let mut b = a.clone_for_thread();
+        a.start_pixel_sample((1, 2).into(), 3, 0);
+        b.start_pixel_sample((1, 2).into(), 3, 0);
+        let first: Vec<f32> = (0..6).map(|_| a.get_1d()).collect();
+        let second: Vec<f32> = (0..6).map(|_| b.get_1d()).collect();
+        assert_eq!(first, second);
+
+        // Different pixels are scrambled differently.
+        a.start_pixel_sample((2, 1).into(), 3, 0);
+        assert_ne!(a.get_1d(), first[0]);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::geometry::point::*;
+use crate::rng::*;
+use crate::sampler::*;
+
+// Splits every dimension of a pixel into x_samples * y_samples strata and
+// places one sample in each (1D dimensions use all of them as 1D strata).
+// The order in which samples visit the strata is shuffled independently
+// per pixel and dimension, so dimensions are not correlated.
+#[derive(Copy, Clone, Debug)]
+pub struct StratifiedSampler {
+    x_samples: u32,
+    y_samples: u32,
+    jitter: bool,
+    seed: u64,
+    rng: Rng,
+    pixel: Point2<i32>,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    // Without jitter, samples sit at the center of their strata. Returns
+    // None unless both counts are positive and their product fits in a u32.
+    pub fn new(x_samples: u32, y_samples: u32, jitter: bool, seed: u64) -> Option<Self> {
+        if x_samples == 0 || y_samples == 0 || x_samples.checked_mul(y_samples).is_none() {
+            return None;
+        }
+        Some(Self {
+            x_samples,
+            y_samples,
+            jitter,
+            seed,
+            rng: Rng::default(),
+            pixel: Point2::<i32>::default(),
+            sample_index: 0,
+            dimension: 0,
+        })
+    }
+
+    fn stratum(&self) -> u32 {
+        let hash = hash_pixel_dimension(self.pixel, self.dimension, self.seed);
+        permutation_element(self.sample_index, self.samples_per_pixel(), hash as u32)
+    }
+
+    fn offset(&mut self) -> f32 {
+        if self.jitter {
+            self.rng.uniform_f32()
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        self.x_samples * self.y_samples
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        self.pixel = pixel;
+        self.sample_index = index;
+        self.dimension = dimension;
+        self.rng.set_sequence(hash_pixel(pixel, self.seed), 0);
+        self.rng.advance(index as i64 * 65536 + dimension as i64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let stratum = self.stratum();
+        self.dimension += 1;
+        let delta = self.offset();
+        ((stratum as f32 + delta) / self.samples_per_pixel() as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let stratum = self.stratum();
+        self.dimension += 2;
+        let (x, y) = (stratum % self.x_samples, stratum / self.x_samples);
+        let (dx, dy) = (self.offset(), self.offset());
+        Point2::<f32>::elements(
+            ((x as f32 + dx) / self.x_samples as f32).min(ONE_MINUS_EPSILON),
+            ((y as f32 + dy) / self.y_samples as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        self.get_2d()
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::box_filter::*;
+
+    #[test]
+    fn test_strata_covered_once() {
+        let mut sampler = StratifiedSampler::new(4, 3, true, 7).unwrap();
+        let pixel = Point2::<i32>::elements(10, -2);
+        let mut cells = [0; 12];
+        let mut bins = [0; 12];
+        for index in 0..sampler.samples_per_pixel() {
+            sampler.start_pixel_sample(pixel, index, 0);
+            let p = sampler.get_pixel_2d();
+            cells[(p.y() * 3.0) as usize * 4 + (p.x() * 4.0) as usize] += 1;
+            bins[(sampler.get_1d() * 12.0) as usize] += 1;
+        }
+        assert!(cells.iter().all(|&c| c == 1));
+        assert!(bins.iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn test_no_jitter() {
+        let mut sampler = StratifiedSampler::new(2, 2, false, 0).unwrap();
+        sampler.start_pixel_sample((0, 0).into(), 0, 0);
+        let p = sampler.get_2d();
+        assert!(p.x() == 0.25 || p.x() == 0.75);
+        assert!(p.y() == 0.25 || p.y() == 0.75);
+    }
+
+    #[test]
+    fn test_camera_sample() {
+        let mut sampler = StratifiedSampler::new(2, 2, true, 0).unwrap();
+        sampler.start_pixel_sample((5, 6).into(), 1, 0);
+        let filter = BoxFilter::default();
+        let (cs, weight) = get_camera_sample(&mut sampler, (5, 6).into(), &filter);
+        assert_eq!(weight, 1.0);
+        assert!(cs.p_film.x() >= 5.0 && cs.p_film.x() < 6.0);
+        assert!(cs.p_film.y() >= 6.0 && cs.p_film.y() < 7.0);
+        assert!((0.0..1.0).contains(&cs.time));
+    }
+
+    #[test]
+    fn test_no_samples() {
+        assert!(StratifiedSampler::new(0, 4, true, 0).is_none());
+        assert!(StratifiedSampler::new(4, 0, true, 0).is_none());
+        assert!(StratifiedSampler::new(1 << 16, 1 << 16, true, 0).is_none());
+    }
+}
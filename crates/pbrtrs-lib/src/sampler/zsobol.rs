@@ -0,0 +1,265 @@
+use crate::geometry::point::*;
+use crate::rng::*;
+use crate::sampler::lowdiscrepancy::*;
+use crate::sampler::*;
+
+// All 24 orderings of the four base-4 digit values.
+const PERMUTATIONS: [[u64; 4]; 24] = [
+    [0, 1, 2, 3],
+    [0, 1, 3, 2],
+    [0, 2, 1, 3],
+    [0, 2, 3, 1],
+    [0, 3, 2, 1],
+    [0, 3, 1, 2],
+    [1, 0, 2, 3],
+    [1, 0, 3, 2],
+    [1, 2, 0, 3],
+    [1, 2, 3, 0],
+    [1, 3, 2, 0],
+    [1, 3, 0, 2],
+    [2, 1, 0, 3],
+    [2, 1, 3, 0],
+    [2, 0, 1, 3],
+    [2, 0, 3, 1],
+    [2, 3, 0, 1],
+    [2, 3, 1, 0],
+    [3, 1, 2, 0],
+    [3, 1, 0, 2],
+    [3, 2, 1, 0],
+    [3, 2, 0, 1],
+    [3, 0, 2, 1],
+    [3, 0, 1, 2],
+];
+
+// One global Sobol sequence shared by the whole image along a Morton (Z-order)
+// curve, after Ahmed and Wonka, "Screen-Space Blue-Noise Diffusion of Monte
+// Carlo Sampling Error via Hierarchical Ordering of Pixels". Each pixel gets
+// a consecutive block of sequence indices, so any aligned 2^k x 2^k block
+// of pixels jointly covers a well-stratified set of samples and the error
+// of neighbouring pixels is negatively correlated, i.e. blue noise.
+//
+// Rank scrambling randomly permutes the base-4 digits of the Morton index,
+// each permutation chosen by the digits above it and the dimension, which
+// hides the regular structure of the curve while keeping the
+// stratification. The sample count should be a power of two.
+#[derive(Copy, Clone, Debug)]
+pub struct ZSobolSampler {
+    randomize: RandomizeStrategy,
+    seed: u64,
+    rank_scrambling: bool,
+    log2_samples_per_pixel: u32,
+    n_base4_digits: u32,
+    morton_index: u64,
+    dimension: u32,
+}
+
+impl ZSobolSampler {
+    pub fn new(
+        samples_per_pixel: u32,
+        full_resolution: Point2<i32>,
+        randomize: RandomizeStrategy,
+        seed: u64,
+    ) -> Self {
+        let log2_samples_per_pixel = samples_per_pixel.next_power_of_two().trailing_zeros();
+        let res = full_resolution.x().max(full_resolution.y()).max(1) as u32;
+        let log4_samples_per_pixel = log2_samples_per_pixel.div_ceil(2);
+        Self {
+            randomize,
+            seed,
+            rank_scrambling: true,
+            log2_samples_per_pixel,
+            n_base4_digits: res.next_power_of_two().trailing_zeros() + log4_samples_per_pixel,
+            morton_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // Without rank scrambling, pixels take their blocks of the sequence in
+    // plain Morton order.
+    pub fn with_rank_scrambling(mut self, rank_scrambling: bool) -> Self {
+        self.rank_scrambling = rank_scrambling;
+        self
+    }
+
+    // Index into the global sequence for the current sample and dimension.
+    fn sample_index(&self) -> u64 {
+        if !self.rank_scrambling {
+            return self.morton_index;
+        }
+        // With an odd power of two samples per pixel the last digit is in
+        // base 2 rather than base 4.
+        let pow2_samples = self.log2_samples_per_pixel & 1;
+        let dimension_hash = 0x5555_5555 * self.dimension as u64;
+        let mut index = 0;
+        for i in (pow2_samples..self.n_base4_digits).rev() {
+            let digit_shift = 2 * i - pow2_samples;
+            let digit = (self.morton_index >> digit_shift) & 3;
+            let higher_digits = self.morton_index >> (digit_shift + 2);
+            let p = (mix_bits(higher_digits ^ dimension_hash) >> 24) % 24;
+            index |= PERMUTATIONS[p as usize][digit as usize] << digit_shift;
+        }
+        if pow2_samples != 0 {
+            let digit = self.morton_index & 1;
+            index |= digit ^ (mix_bits((self.morton_index >> 1) ^ dimension_hash) & 1);
+        }
+        index
+    }
+
+    fn scrambler(&self, hash: u32) -> SobolScrambler {
+        SobolScrambler::from_strategy(self.randomize, hash)
+    }
+}
+
+// Interleaves the bits of x and y, with x in the even bits.
+fn encode_morton2(x: u32, y: u32) -> u64 {
+    fn spread(v: u32) -> u64 {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    }
+    spread(x) | (spread(y) << 1)
+}
+
+impl Sampler for ZSobolSampler {
+    fn samples_per_pixel(&self) -> u32 {
+        1 << self.log2_samples_per_pixel
+    }
+
+    fn start_pixel_sample(&mut self, pixel: Point2<i32>, index: u32, dimension: u32) {
+        self.dimension = dimension;
+        self.morton_index = (encode_morton2(pixel.x() as u32, pixel.y() as u32)
+            << self.log2_samples_per_pixel)
+            | index as u64;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let index = self.sample_index();
+        let hash = mix_bits(self.dimension as u64 ^ mix_bits(self.seed));
+        self.dimension += 1;
+        sobol_sample(index, 0, self.scrambler(hash as u32))
+    }
+
+    fn get_2d(&mut self) -> Point2<f32> {
+        let index = self.sample_index();
+        let hash = mix_bits(self.dimension as u64 ^ mix_bits(self.seed));
+        self.dimension += 2;
+        Point2::<f32>::elements(
+            sobol_sample(index, 0, self.scrambler(hash as u32)),
+            sobol_sample(index, 1, self.scrambler((hash >> 32) as u32)),
+        )
+    }
+
+    fn get_pixel_2d(&mut self) -> Point2<f32> {
+        self.get_2d()
+    }
+
+    fn clone_for_thread(&self) -> Box<dyn Sampler> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampler::independent::*;
+
+    const N: usize = 64;
+
+    // Renders a 64x64 image of a pixel-independent integrand, the fraction
+    // of a unit square light that a quarter disk occludes, at one sample per
+    // pixel and returns the per-pixel error.
+    fn render_error(sampler: &mut dyn Sampler) -> Vec<f32> {
+        let reference = std::f32::consts::PI / 8.0;
+        let mut error = Vec::with_capacity(N * N);
+        for y in 0..N {
+            for x in 0..N {
+                sampler.start_pixel_sample((x as i32, y as i32).into(), 0, 2);
+                let u = sampler.get_2d();
+                let f = if u.x() * u.x() + u.y() * u.y() < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                };
+                error.push(f - reference);
+            }
+        }
+        error
+    }
+
+    // Fraction of the error's power (without the DC term) at frequencies
+    // below a quarter of the Nyquist limit along both axes.
+    fn low_frequency_power(error: &[f32]) -> f32 {
+        use std::f32::consts::PI;
+        let dft = |input: &[(f32, f32)], stride: usize, offset: usize| {
+            (0..N)
+                .map(|k| {
+                    (0..N).fold((0.0, 0.0), |(re, im), n| {
+                        let (a, b) = input[offset + n * stride];
+                        let (s, c) = (-2.0 * PI * (k * n) as f32 / N as f32).sin_cos();
+                        (re + a * c - b * s, im + a * s + b * c)
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let input: Vec<(f32, f32)> = error.iter().map(|&e| (e, 0.0)).collect();
+        let rows: Vec<(f32, f32)> = (0..N).flat_map(|y| dft(&input, 1, y * N)).collect();
+        let mut spectrum = vec![(0.0, 0.0); N * N];
+        for x in 0..N {
+            for (y, v) in dft(&rows, N, x).into_iter().enumerate() {
+                spectrum[y * N + x] = v;
+            }
+        }
+
+        let (mut low, mut total) = (0.0, 0.0);
+        for ky in 0..N {
+            for kx in 0..N {
+                if kx == 0 && ky == 0 {
+                    continue;
+                }
+                let (re, im) = spectrum[ky * N + kx];
+                let power = re * re + im * im;
+                let freq = |k: usize| k.min(N - k);
+                if freq(kx) < N / 8 && freq(ky) < N / 8 {
+                    low += power;
+                }
+                total += power;
+            }
+        }
+        low / total
+    }
+
+    #[test]
+    fn test_morton() {
+        assert_eq!(encode_morton2(0b11, 0b00), 0b0101);
+        assert_eq!(encode_morton2(0b10, 0b01), 0b0110);
+    }
+
+    #[test]
+    fn test_pixel_stratified() {
+        let mut sampler = ZSobolSampler::new(16, (8, 8).into(), RandomizeStrategy::Owen, 3);
+        let mut cells = [0; 16];
+        for index in 0..16 {
+            sampler.start_pixel_sample((5, 2).into(), index, 0);
+            let p = sampler.get_pixel_2d();
+            cells[(p.y() * 4.0) as usize * 4 + (p.x() * 4.0) as usize] += 1;
+        }
+        assert!(cells.iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn test_blue_noise_error() {
+        // White noise puts about 1/16 of its power below an eighth of the
+        // sampling rate; the Z-Sobol error should have much less there.
+        let mut independent = IndependentSampler::new(1, 0);
+        let white = low_frequency_power(&render_error(&mut independent));
+        assert!((0.03..0.1).contains(&white), "{}", white);
+
+        let mut zsobol =
+            ZSobolSampler::new(1, (N as i32, N as i32).into(), RandomizeStrategy::Owen, 0);
+        let blue = low_frequency_power(&render_error(&mut zsobol));
+        assert!(blue < white / 2.0, "{} vs {}", blue, white);
+    }
+}
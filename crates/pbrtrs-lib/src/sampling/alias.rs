@@ -0,0 +1,124 @@
+use crate::rng::ONE_MINUS_EPSILON;
+
+#[derive(Copy, Clone, Debug)]
+struct Bin {
+    // Probability of keeping this bin's own index rather than its alias.
+    q: f32,
+    p: f32,
+    alias: usize,
+}
+
+// Walker's alias method: a discrete distribution sampled in constant time
+// by picking a bin uniformly and then choosing between the bin's own index
+// and a single alias.
+#[derive(Clone, Debug)]
+pub struct AliasTable {
+    bins: Vec<Bin>,
+}
+
+impl AliasTable {
+    // Weights need not be normalized. Returns None if any is negative or not
+    // finite, or if they are all zero.
+    pub fn new(weights: &[f32]) -> Option<Self> {
+        if !weights.iter().all(|w| w.is_finite() && *w >= 0.0) {
+            return None;
+        }
+        let sum: f64 = weights.iter().map(|&w| w as f64).sum();
+        if weights.is_empty() || sum <= 0.0 {
+            return None;
+        }
+        let n = weights.len();
+        let mut bins: Vec<Bin> = weights
+            .iter()
+            .map(|&w| Bin {
+                q: 0.0,
+                p: (w as f64 / sum) as f32,
+                alias: 0,
+            })
+            .collect();
+
+        // Pair bins whose scaled probability is under one with ones above,
+        // moving the excess of the large bin over (Vose's algorithm).
+        let (mut under, mut over) = (Vec::new(), Vec::new());
+        for (i, w) in weights.iter().enumerate() {
+            let p_hat = *w as f64 / sum * n as f64;
+            if p_hat < 1.0 {
+                under.push((i, p_hat));
+            } else {
+                over.push((i, p_hat));
+            }
+        }
+        while let (Some(&(u, pu)), Some(&(o, po))) = (under.last(), over.last()) {
+            under.pop();
+            over.pop();
+            bins[u].q = pu as f32;
+            bins[u].alias = o;
+            let excess = pu + po - 1.0;
+            if excess < 1.0 {
+                under.push((o, excess));
+            } else {
+                over.push((o, excess));
+            }
+        }
+        // Leftovers are within round-off of one.
+        for (i, _) in under.into_iter().chain(over) {
+            bins[i].q = 1.0;
+            bins[i].alias = i;
+        }
+        Some(Self { bins })
+    }
+
+    pub fn size(&self) -> usize {
+        self.bins.len()
+    }
+
+    pub fn pmf(&self, index: usize) -> f32 {
+        self.bins[index].p
+    }
+
+    // Returns the sampled index, its probability, and a new uniform sample
+    // in [0, 1) recovered from the part of u that was not used.
+    pub fn sample(&self, u: f32) -> (usize, f32, f32) {
+        let n = self.size();
+        let offset = ((u * n as f32) as usize).min(n - 1);
+        let up = (u * n as f32 - offset as f32).min(ONE_MINUS_EPSILON);
+        let bin = &self.bins[offset];
+        if up < bin.q {
+            (offset, bin.p, (up / bin.q).min(ONE_MINUS_EPSILON))
+        } else {
+            let alias = bin.alias;
+            let remapped = ((up - bin.q) / (1.0 - bin.q)).min(ONE_MINUS_EPSILON);
+            (alias, self.bins[alias].p, remapped)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::*;
+    use crate::sampling::assert_chi_square;
+
+    #[test]
+    fn test_alias_table() {
+        let weights = [0.5, 0.0, 4.0, 1.5, 2.0, 0.25];
+        let table = AliasTable::new(&weights).unwrap();
+        let total: f32 = weights.iter().sum();
+
+        let n = 100_000;
+        let mut rng = Rng::new(0, 3);
+        let mut counts = [0; 6];
+        for _ in 0..n {
+            let (i, pmf, remapped) = table.sample(rng.uniform_f32());
+            assert!((pmf - weights[i] / total).abs() < 1e-6);
+            assert!((0.0..1.0).contains(&remapped));
+            counts[i] += 1;
+        }
+        let expected: Vec<f32> = weights.iter().map(|w| w / total * n as f32).collect();
+        assert_chi_square(&counts, &expected);
+
+        assert!(AliasTable::new(&[0.0, 0.0]).is_none());
+        assert!(AliasTable::new(&[1.0, -0.5]).is_none());
+        assert!(AliasTable::new(&[1.0, f32::INFINITY]).is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::rng::ONE_MINUS_EPSILON;
+
+// Veach's heuristics for weighting a sample from strategy f, which took
+// nf samples with density f_pdf, against strategy g with ng samples.
+pub fn balance_heuristic(nf: u32, f_pdf: f32, ng: u32, g_pdf: f32) -> f32 {
+    let (f, g) = (nf as f32 * f_pdf, ng as f32 * g_pdf);
+    if f == 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+// The power heuristic with exponent two, which favours the dominant
+// strategy more sharply than the balance heuristic.
+pub fn power_heuristic(nf: u32, f_pdf: f32, ng: u32, g_pdf: f32) -> f32 {
+    let (f, g) = (nf as f32 * f_pdf, ng as f32 * g_pdf);
+    if f.is_infinite() {
+        return 1.0;
+    }
+    if f == 0.0 {
+        return 0.0;
+    }
+    f * f / (f * f + g * g)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Heuristic {
+    Balance,
+    Power,
+}
+
+// One-sample MIS: each sample picks a single strategy at random with fixed
+// probabilities and is weighted against all of them. Strategies are chosen
+// by splitting the sample value u into consecutive intervals, so stratified
+// values of u also stratify which strategy is used.
+#[derive(Clone, Debug)]
+pub struct OneSampleMis {
+    // Selection probabilities and their running sums, the latter with one
+    // more entry, from 0 to 1.
+    probabilities: Vec<f32>,
+    cdf: Vec<f32>,
+}
+
+impl OneSampleMis {
+    // The weights need not be normalized; negative ones count as zero.
+    // Returns None if no strategy can be selected.
+    pub fn new(weights: &[f32]) -> Option<Self> {
+        let sum: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+        if !(sum > 0.0 && sum.is_finite()) {
+            return None;
+        }
+        let probabilities: Vec<f32> = weights.iter().map(|w| w.max(0.0) / sum).collect();
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for p in &probabilities {
+            cdf.push(cdf.last().unwrap() + p);
+        }
+        *cdf.last_mut().unwrap() = 1.0;
+        Some(Self { probabilities, cdf })
+    }
+
+    pub fn probability(&self, strategy: usize) -> f32 {
+        self.probabilities[strategy]
+    }
+
+    // Returns the chosen strategy, its probability, and u remapped to
+    // [0, 1) within the strategy's interval for reuse.
+    pub fn sample(&self, u: f32) -> (usize, f32, f32) {
+        let n = self.probabilities.len();
+        let i = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+        let p = self.probabilities[i];
+        let remapped = ((u - self.cdf[i]) / p).clamp(0.0, ONE_MINUS_EPSILON);
+        (i, p, remapped)
+    }
+
+    // Weight for a sample from strategy i, given the density of every
+    // strategy at the sample. The estimate is weight * f / (probability *
+    // pdfs[i]).
+    pub fn weight(&self, i: usize, pdfs: &[f32], heuristic: Heuristic) -> f32 {
+        let exponent = match heuristic {
+            Heuristic::Balance => 1,
+            Heuristic::Power => 2,
+        };
+        let term = |j: usize| (self.probabilities[j] * pdfs[j]).powi(exponent);
+        let sum: f32 = (0..pdfs.len()).map(term).sum();
+        if sum == 0.0 {
+            return 0.0;
+        }
+        term(i) / sum
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_heuristics() {
+        assert_eq!(balance_heuristic(1, 1.0, 1, 3.0), 0.25);
+        assert_eq!(balance_heuristic(3, 1.0, 1, 3.0), 0.5);
+        assert_eq!(power_heuristic(1, 1.0, 1, 3.0), 0.1);
+        assert_eq!(power_heuristic(1, f32::INFINITY, 1, 3.0), 1.0);
+        assert_eq!(balance_heuristic(1, 0.0, 1, 0.0), 0.0);
+        let (a, b) = (
+            power_heuristic(2, 0.3, 1, 0.7),
+            power_heuristic(1, 0.7, 2, 0.3),
+        );
+        assert!((a + b - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_one_sample_unbiased() {
+        // Integrate f(x) = 3x^2 over [0, 1] with a uniform strategy and one
+        // with pdf 2x, picked 1:3.
+        let mis = OneSampleMis::new(&[1.0, 3.0]).unwrap();
+        let f = |x: f32| 3.0 * x * x;
+        for &heuristic in &[Heuristic::Balance, Heuristic::Power] {
+            let n = 100_000;
+            let mut sum = 0.0;
+            let mut chosen = [0; 2];
+            for k in 0..n {
+                let u = (k as f32 + 0.5) / n as f32;
+                let (i, p, u) = mis.sample(u);
+                chosen[i] += 1;
+                let x = if i == 0 { u } else { u.sqrt() };
+                let pdfs = [1.0, 2.0 * x];
+                sum += mis.weight(i, &pdfs, heuristic) * f(x) / (p * pdfs[i]);
+            }
+            assert!((sum / n as f32 - 1.0).abs() < 1e-2);
+            // Stratified u picks each strategy exactly in proportion.
+            assert_eq!(chosen, [n / 4, 3 * n / 4]);
+        }
+    }
+
+    #[test]
+    fn test_one_sample_weights() {
+        let mis = OneSampleMis::new(&[-2.0, 1.0, 3.0]).unwrap();
+        assert_eq!(
+            [mis.probability(0), mis.probability(1), mis.probability(2)],
+            [0.0, 0.25, 0.75]
+        );
+        assert_eq!(mis.sample(0.2).0, 1);
+        assert!(OneSampleMis::new(&[]).is_none());
+        assert!(OneSampleMis::new(&[-1.0, 0.0]).is_none());
+    }
+}
@@ -0,0 +1,32 @@
+#![allow(dead_code)]
+
+pub mod alias;
+pub mod mis;
+pub mod piecewise;
+pub mod reservoir;
+pub mod warp;
+
+// Pearson's chi-square test of a histogram of samples against the expected
+// counts. The statistic must stay below a bound that a correctly
+// distributed histogram exceeds with negligible probability, and bins
+// expected to be empty must be.
+#[cfg(test)]
+pub(crate) fn assert_chi_square(observed: &[u32], expected: &[f32]) {
+    let mut chi2 = 0.0;
+    let mut dof = 0;
+    for (&o, &e) in observed.iter().zip(expected) {
+        if e == 0.0 {
+            assert_eq!(o, 0);
+            continue;
+        }
+        chi2 += (o as f32 - e) * (o as f32 - e) / e;
+        dof += 1;
+    }
+    let dof = (dof - 1) as f32;
+    assert!(
+        chi2 < dof + 5.0 * (2.0 * dof).sqrt(),
+        "{} ({} dof)",
+        chi2,
+        dof
+    );
+}
@@ -0,0 +1,262 @@
+use crate::geometry::aabb::*;
+use crate::geometry::point::*;
+use crate::rng::ONE_MINUS_EPSILON;
+
+// A piecewise-constant function over [min, max] with equally wide pieces,
+// normalized into a PDF that can be sampled by inverting its CDF. A
+// function that is zero everywhere is sampled uniformly.
+#[derive(Clone, Debug)]
+pub struct PiecewiseConstant1D {
+    func: Vec<f32>,
+    // One more entry than func, from 0 to 1.
+    cdf: Vec<f32>,
+    min: f32,
+    max: f32,
+    func_int: f32,
+}
+
+impl PiecewiseConstant1D {
+    // Returns None if f is empty, any value is negative or not finite, or
+    // the domain is empty.
+    pub fn new(f: &[f32], min: f32, max: f32) -> Option<Self> {
+        if f.is_empty() || !(min.is_finite() && max.is_finite() && min < max) {
+            return None;
+        }
+        if !f.iter().all(|v| v.is_finite() && *v >= 0.0) {
+            return None;
+        }
+        let func = f.to_vec();
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, v) in func.iter().enumerate() {
+            cdf.push(cdf[i] + v * (max - min) / n as f32);
+        }
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = i as f32 / n as f32);
+        } else {
+            cdf.iter_mut().for_each(|c| *c /= func_int);
+        }
+        Some(Self {
+            func,
+            cdf,
+            min,
+            max,
+            func_int,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.func.len()
+    }
+
+    // Integral of the function over the domain.
+    pub fn integral(&self) -> f32 {
+        self.func_int
+    }
+
+    // Returns the sampled value, its PDF, and the index of its piece.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.size();
+        let o = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+        let width = self.cdf[o + 1] - self.cdf[o];
+        let du = if width > 0.0 {
+            (u - self.cdf[o]) / width
+        } else {
+            0.0
+        };
+        let pdf = if self.func_int > 0.0 {
+            self.func[o] / self.func_int
+        } else {
+            1.0 / (self.max - self.min)
+        };
+        let t = (o as f32 + du) / n as f32;
+        (self.min + t * (self.max - self.min), pdf, o)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        if x < self.min || x > self.max {
+            return 0.0;
+        }
+        if self.func_int == 0.0 {
+            return 1.0 / (self.max - self.min);
+        }
+        self.func[self.offset(x)] / self.func_int
+    }
+
+    // The sample u that maps to x, or None if x is outside the domain.
+    pub fn invert(&self, x: f32) -> Option<f32> {
+        if x < self.min || x > self.max {
+            return None;
+        }
+        let o = self.offset(x);
+        let piece = (self.max - self.min) / self.size() as f32;
+        let delta = (x - (self.min + o as f32 * piece)) / piece;
+        let u = self.cdf[o] + delta * (self.cdf[o + 1] - self.cdf[o]);
+        Some(u.min(ONE_MINUS_EPSILON))
+    }
+
+    fn offset(&self, x: f32) -> usize {
+        let t = (x - self.min) / (self.max - self.min);
+        ((t * self.size() as f32) as usize).min(self.size() - 1)
+    }
+}
+
+// A piecewise-constant function over a 2D domain, sampled by first picking
+// a row from the marginal distribution of row integrals and then a position
+// within the row from its conditional distribution.
+#[derive(Clone, Debug)]
+pub struct PiecewiseConstant2D {
+    domain: Bounds2<f32>,
+    // One distribution along u per row of constant v.
+    conditional: Vec<PiecewiseConstant1D>,
+    marginal: PiecewiseConstant1D,
+}
+
+impl PiecewiseConstant2D {
+    // Values are given row by row: f[v * nu + u]. Returns None if there are
+    // not nu * nv of them, any is negative or not finite, or the domain is
+    // empty.
+    pub fn new(f: &[f32], nu: usize, nv: usize, domain: Bounds2<f32>) -> Option<Self> {
+        if nu == 0 || nv == 0 || Some(f.len()) != nu.checked_mul(nv) {
+            return None;
+        }
+        let (lo, hi) = (domain.p_min(), domain.p_max());
+        let conditional: Vec<PiecewiseConstant1D> = f
+            .chunks(nu)
+            .map(|row| PiecewiseConstant1D::new(row, lo.x(), hi.x()))
+            .collect::<Option<_>>()?;
+        let row_integrals: Vec<f32> = conditional.iter().map(|c| c.integral()).collect();
+        let marginal = PiecewiseConstant1D::new(&row_integrals, lo.y(), hi.y())?;
+        Some(Self {
+            domain,
+            conditional,
+            marginal,
+        })
+    }
+
+    // Tabulates f at the centers of an nu x nv grid over the domain.
+    pub fn from_function(
+        domain: Bounds2<f32>,
+        nu: usize,
+        nv: usize,
+        f: impl Fn(Point2<f32>) -> f32,
+    ) -> Option<Self> {
+        let (lo, d) = (domain.p_min(), domain.diagonal());
+        let values: Vec<f32> = (0..nv)
+            .flat_map(|v| (0..nu).map(move |u| (u, v)))
+            .map(|(u, v)| {
+                f(Point2::<f32>::elements(
+                    lo.x() + (u as f32 + 0.5) / nu as f32 * d.x(),
+                    lo.y() + (v as f32 + 0.5) / nv as f32 * d.y(),
+                ))
+            })
+            .collect();
+        Self::new(&values, nu, nv, domain)
+    }
+
+    pub fn domain(&self) -> Bounds2<f32> {
+        self.domain
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.marginal.integral()
+    }
+
+    // Returns the sampled point, its PDF with respect to area in the
+    // domain, and the (column, row) of its cell.
+    pub fn sample(&self, u: Point2<f32>) -> (Point2<f32>, f32, (usize, usize)) {
+        let (y, pdf_y, v) = self.marginal.sample(u.y());
+        let (x, pdf_x, u) = self.conditional[v].sample(u.x());
+        (Point2::<f32>::elements(x, y), pdf_x * pdf_y, (u, v))
+    }
+
+    pub fn pdf(&self, p: Point2<f32>) -> f32 {
+        if !self.domain.inside(&p) {
+            return 0.0;
+        }
+        let v = self.marginal.offset(p.y());
+        self.marginal.pdf(p.y()) * self.conditional[v].pdf(p.x())
+    }
+
+    pub fn invert(&self, p: Point2<f32>) -> Option<Point2<f32>> {
+        let v = self.marginal.invert(p.y())?;
+        let row = self.marginal.offset(p.y());
+        let u = self.conditional[row].invert(p.x())?;
+        Some(Point2::<f32>::elements(u, v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::*;
+    use crate::sampling::assert_chi_square;
+
+    #[test]
+    fn test_1d() {
+        let f = [1.0, 0.0, 3.0, 2.0, 6.0];
+        let dist = PiecewiseConstant1D::new(&f, -1.0, 4.0).unwrap();
+        assert_eq!(dist.integral(), 12.0);
+
+        let n = 100_000;
+        let mut rng = Rng::new(0, 1);
+        let mut counts = [0; 5];
+        for _ in 0..n {
+            let u = rng.uniform_f32();
+            let (x, pdf, o) = dist.sample(u);
+            assert_eq!(pdf, dist.pdf(x));
+            assert!((dist.invert(x).unwrap() - u).abs() < 1e-5);
+            counts[o] += 1;
+        }
+        let expected: Vec<f32> = f.iter().map(|v| v / 12.0 * n as f32).collect();
+        assert_chi_square(&counts, &expected);
+
+        assert_eq!(dist.pdf(5.0), 0.0);
+        assert!(dist.invert(-2.0).is_none());
+        let flat = PiecewiseConstant1D::new(&[0.0, 0.0], 0.0, 2.0).unwrap();
+        assert_eq!(flat.sample(0.75).0, 1.5);
+        assert!(PiecewiseConstant1D::new(&[1.0, -2.0], 0.0, 1.0).is_none());
+        assert!(PiecewiseConstant1D::new(&[1.0, f32::NAN], 0.0, 1.0).is_none());
+        assert!(PiecewiseConstant1D::new(&[], 0.0, 1.0).is_none());
+        assert!(PiecewiseConstant1D::new(&[1.0], 1.0, 1.0).is_none());
+        assert!(PiecewiseConstant1D::new(&[1.0], 0.0, f32::NAN).is_none());
+    }
+
+    #[test]
+    fn test_2d() {
+        let domain = Bounds2::<f32>::new((-1.0, 0.0).into(), (1.0, 4.0).into());
+        let f = |p: Point2<f32>| p.x() * p.x() + p.y();
+        let dist = PiecewiseConstant2D::from_function(domain, 8, 6, f).unwrap();
+
+        let n = 200_000;
+        let mut rng = Rng::new(0, 2);
+        let mut counts = vec![0; 48];
+        for _ in 0..n {
+            let u = Point2::<f32>::elements(rng.uniform_f32(), rng.uniform_f32());
+            let (p, pdf, (cu, cv)) = dist.sample(u);
+            assert!(domain.inside(&p));
+            assert!((pdf - dist.pdf(p)).abs() < 1e-4 * pdf);
+            assert!(dist.invert(p).unwrap().distance_to(&u) < 1e-4);
+            counts[cv * 8 + cu] += 1;
+        }
+
+        // Each cell's share is the tabulated value over the total.
+        let values: Vec<f32> = (0..6)
+            .flat_map(|v| (0..8).map(move |u| (u, v)))
+            .map(|(u, v)| f((-1.0 + (u as f32 + 0.5) / 4.0, (v as f32 + 0.5) / 1.5).into()))
+            .collect();
+        let total: f32 = values.iter().sum();
+        let expected: Vec<f32> = values.iter().map(|v| v / total * n as f32).collect();
+        assert_chi_square(&counts, &expected);
+        // Cells have area 1/6, so the integral is the sum over six.
+        assert!((dist.integral() - total / 6.0).abs() < 1e-3);
+        assert!(PiecewiseConstant2D::new(&[1.0; 6], 0, 6, domain).is_none());
+        assert!(PiecewiseConstant2D::new(&[1.0; 6], 2, 2, domain).is_none());
+        let flat = Bounds2::<f32>::new((0.0, 1.0).into(), (1.0, 1.0).into());
+        assert!(PiecewiseConstant2D::new(&[1.0; 4], 2, 2, flat).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::rng::*;
+
+// Picks one item from a stream in proportion to its weight while storing
+// only the current pick (weighted reservoir sampling). Reservoirs filled
+// from separate streams can be merged, e.g. to reuse light samples across
+// pixels.
+#[derive(Clone, Debug)]
+pub struct WeightedReservoirSampler<T> {
+    rng: Rng,
+    weight_sum: f32,
+    reservoir_weight: f32,
+    reservoir: Option<T>,
+}
+
+impl<T> WeightedReservoirSampler<T> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(mix_bits(seed), 0),
+            weight_sum: 0.0,
+            reservoir_weight: 0.0,
+            reservoir: None,
+        }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng.set_sequence(mix_bits(seed), 0);
+    }
+
+    // Offers an item; returns whether it replaced the current pick.
+    pub fn add(&mut self, sample: T, weight: f32) -> bool {
+        self.weight_sum += weight;
+        let p = weight / self.weight_sum;
+        if p > 0.0 && self.rng.uniform_f32() < p {
+            self.reservoir = Some(sample);
+            self.reservoir_weight = weight;
+            return true;
+        }
+        false
+    }
+
+    // Like add, but only builds the item if it is picked.
+    pub fn add_with(&mut self, sample: impl FnOnce() -> T, weight: f32) -> bool {
+        self.weight_sum += weight;
+        let p = weight / self.weight_sum;
+        if p > 0.0 && self.rng.uniform_f32() < p {
+            self.reservoir = Some(sample());
+            self.reservoir_weight = weight;
+            return true;
+        }
+        false
+    }
+
+    // Combines with a reservoir that saw a different stream, as if this one
+    // had seen both.
+    pub fn merge(&mut self, other: WeightedReservoirSampler<T>) {
+        if let Some(sample) = other.reservoir {
+            if self.add(sample, other.weight_sum) {
+                self.reservoir_weight = other.reservoir_weight;
+            }
+        }
+    }
+
+    pub fn has_sample(&self) -> bool {
+        self.weight_sum > 0.0 && self.reservoir.is_some()
+    }
+
+    pub fn get_sample(&self) -> Option<&T> {
+        self.reservoir.as_ref()
+    }
+
+    pub fn take_sample(&mut self) -> Option<T> {
+        self.reservoir.take()
+    }
+
+    // Probability that the current item was the one picked.
+    pub fn sample_probability(&self) -> f32 {
+        self.reservoir_weight / self.weight_sum
+    }
+
+    pub fn weight_sum(&self) -> f32 {
+        self.weight_sum
+    }
+
+    pub fn reset(&mut self) {
+        self.weight_sum = 0.0;
+        self.reservoir_weight = 0.0;
+        self.reservoir = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sampling::assert_chi_square;
+
+    fn expected(weights: &[f32], n: u32) -> Vec<f32> {
+        let total: f32 = weights.iter().sum();
+        weights.iter().map(|w| w / total * n as f32).collect()
+    }
+
+    #[test]
+    fn test_selection() {
+        let weights = [1.0, 2.0, 0.5, 4.0, 2.5];
+        let n = 20_000;
+        let mut counts = [0; 5];
+        let mut wrs = WeightedReservoirSampler::new(0);
+        for trial in 0..n {
+            wrs.seed(trial as u64);
+            wrs.reset();
+            for (i, &w) in weights.iter().enumerate() {
+                wrs.add(i, w);
+            }
+            let i = *wrs.get_sample().unwrap();
+            assert!((wrs.sample_probability() - weights[i] / 10.0).abs() < 1e-6);
+            counts[i] += 1;
+        }
+        assert_chi_square(&counts, &expected(&weights, n));
+    }
+
+    #[test]
+    fn test_merge() {
+        let weights = [3.0, 1.0, 2.0, 2.0];
+        let n = 20_000;
+        let mut counts = [0; 4];
+        for trial in 0..n {
+            let mut a = WeightedReservoirSampler::new(2 * trial as u64);
+            let mut b = WeightedReservoirSampler::new(2 * trial as u64 + 1);
+            a.add(0, weights[0]);
+            a.add(1, weights[1]);
+            b.add_with(|| 2, weights[2]);
+            b.add_with(|| 3, weights[3]);
+            a.merge(b);
+            assert_eq!(a.weight_sum(), 8.0);
+            let i = *a.get_sample().unwrap();
+            assert!((a.sample_probability() - weights[i] / 8.0).abs() < 1e-6);
+            counts[i] += 1;
+        }
+        assert_chi_square(&counts, &expected(&weights, n));
+
+        let empty = WeightedReservoirSampler::<u32>::new(0);
+        assert!(!empty.has_sample());
+    }
+}
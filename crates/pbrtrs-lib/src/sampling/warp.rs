@@ -0,0 +1,682 @@
+use crate::geometry::point::*;
+use crate::geometry::vector::*;
+use crate::rng::ONE_MINUS_EPSILON;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+// Maps [0, 1)^2 to the unit disk with Shirley and Chiu's concentric mapping,
+// which keeps strata compact and adjacent, unlike the polar mapping.
+pub fn sample_uniform_disk_concentric(u: Point2<f32>) -> Point2<f32> {
+    let (x, y) = (2.0 * u.x() - 1.0, 2.0 * u.y() - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return Point2::<f32>::new(0.0);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, 2.0 * FRAC_PI_4 - FRAC_PI_4 * (x / y))
+    };
+    Point2::<f32>::elements(r * theta.cos(), r * theta.sin())
+}
+
+pub fn invert_uniform_disk_concentric_sample(p: Point2<f32>) -> Point2<f32> {
+    if p.x() == 0.0 && p.y() == 0.0 {
+        return Point2::<f32>::new(0.5);
+    }
+    // The signed radius takes the sign of the dominant coordinate, so the
+    // angle it is measured from always lies within 45 degrees of its axis.
+    let (x, y) = if p.x().abs() >= p.y().abs() {
+        let r = p.mag().copysign(p.x());
+        let theta = (p.y() / r).atan2(p.x() / r);
+        (r, theta * r / FRAC_PI_4)
+    } else {
+        let r = p.mag().copysign(p.y());
+        let theta = (p.y() / r).atan2(p.x() / r);
+        ((FRAC_PI_2 - theta) * r / FRAC_PI_4, r)
+    };
+    Point2::<f32>::elements((x + 1.0) / 2.0, (y + 1.0) / 2.0)
+}
+
+// Azimuth of a direction in [0, 2pi).
+fn phi(w: Vec3<f32>) -> f32 {
+    let phi = w.y().atan2(w.x());
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+fn spherical_direction(cos_theta: f32, phi: f32) -> Vec3<f32> {
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    Vec3::<f32>::elements(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+// Directions about +z. The hemisphere, cosine-weighted hemisphere, and cone
+// samplers return directions with z >= 0; PDFs are with respect to solid
+// angle.
+pub fn sample_uniform_hemisphere(u: Point2<f32>) -> Vec3<f32> {
+    spherical_direction(u.x(), 2.0 * PI * u.y())
+}
+
+pub fn uniform_hemisphere_pdf() -> f32 {
+    1.0 / (2.0 * PI)
+}
+
+pub fn invert_uniform_hemisphere_sample(w: Vec3<f32>) -> Point2<f32> {
+    Point2::<f32>::elements(w.z(), phi(w) / (2.0 * PI))
+}
+
+pub fn sample_uniform_sphere(u: Point2<f32>) -> Vec3<f32> {
+    spherical_direction(1.0 - 2.0 * u.x(), 2.0 * PI * u.y())
+}
+
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+pub fn invert_uniform_sphere_sample(w: Vec3<f32>) -> Point2<f32> {
+    Point2::<f32>::elements((1.0 - w.z()) / 2.0, phi(w) / (2.0 * PI))
+}
+
+// Malley's method: points uniform on the disk, projected up onto the
+// hemisphere, are distributed according to cos(theta).
+pub fn sample_cosine_hemisphere(u: Point2<f32>) -> Vec3<f32> {
+    let d = sample_uniform_disk_concentric(u);
+    let z = (1.0 - d.x() * d.x() - d.y() * d.y()).max(0.0).sqrt();
+    Vec3::<f32>::elements(d.x(), d.y(), z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) / PI
+}
+
+pub fn invert_cosine_hemisphere_sample(w: Vec3<f32>) -> Point2<f32> {
+    invert_uniform_disk_concentric_sample(Point2::<f32>::elements(w.x(), w.y()))
+}
+
+// Directions within cos_theta_max of +z, uniform over the cone's solid
+// angle.
+pub fn sample_uniform_cone(u: Point2<f32>, cos_theta_max: f32) -> Vec3<f32> {
+    let cos_theta = (1.0 - u.x()) + u.x() * cos_theta_max;
+    spherical_direction(cos_theta, 2.0 * PI * u.y())
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+pub fn invert_uniform_cone_sample(w: Vec3<f32>, cos_theta_max: f32) -> Point2<f32> {
+    let u0 = (1.0 - w.z()) / (1.0 - cos_theta_max);
+    Point2::<f32>::elements(u0.clamp(0.0, 1.0), phi(w) / (2.0 * PI))
+}
+
+// Returns uniformly distributed barycentric coordinates (b0, b1, b2).
+pub fn sample_uniform_triangle(u: Point2<f32>) -> (f32, f32, f32) {
+    let (b0, b1) = if u.x() < u.y() {
+        let b0 = u.x() / 2.0;
+        (b0, u.y() - b0)
+    } else {
+        let b1 = u.y() / 2.0;
+        (u.x() - b1, b1)
+    };
+    (b0, b1, 1.0 - b0 - b1)
+}
+
+pub fn invert_uniform_triangle_sample(b: (f32, f32, f32)) -> Point2<f32> {
+    let (b0, b1, _) = b;
+    if b0 > b1 {
+        Point2::<f32>::elements(b0 + b1, 2.0 * b1)
+    } else {
+        Point2::<f32>::elements(2.0 * b0, b1 + b0)
+    }
+}
+
+// Angle between two unit vectors, accurate for nearly parallel vectors too.
+fn angle_between(a: Vec3<f32>, b: Vec3<f32>) -> f32 {
+    if a.dot(&b) < 0.0 {
+        PI - 2.0 * ((a + b).mag() / 2.0).min(1.0).asin()
+    } else {
+        2.0 * ((b - a).mag() / 2.0).min(1.0).asin()
+    }
+}
+
+// The part of v orthogonal to the unit vector w.
+fn gram_schmidt(v: Vec3<f32>, w: Vec3<f32>) -> Vec3<f32> {
+    v - w * v.dot(&w)
+}
+
+// Unit directions from p to the triangle's vertices, the normals of the
+// planes through p and each edge, and the triangle's interior angles on the
+// unit sphere around p. None if p lies in the triangle's plane.
+#[allow(clippy::type_complexity)]
+fn spherical_triangle(
+    v: &[Point3<f32>; 3],
+    p: Point3<f32>,
+) -> Option<([Vec3<f32>; 3], [Vec3<f32>; 3], [f32; 3])> {
+    let (a, b, c) = (
+        (v[0] - p).normalized(),
+        (v[1] - p).normalized(),
+        (v[2] - p).normalized(),
+    );
+    let (n_ab, n_bc, n_ca) = (a.cross(&b), b.cross(&c), c.cross(&a));
+    if n_ab.mag2() == 0.0 || n_bc.mag2() == 0.0 || n_ca.mag2() == 0.0 {
+        return None;
+    }
+    let (n_ab, n_bc, n_ca) = (n_ab.normalized(), n_bc.normalized(), n_ca.normalized());
+    let angles = [
+        angle_between(n_ab, -n_ca),
+        angle_between(n_bc, -n_ab),
+        angle_between(n_ca, -n_bc),
+    ];
+    Some(([a, b, c], [n_ab, n_bc, n_ca], angles))
+}
+
+// Solid angle the triangle subtends from p.
+pub fn spherical_triangle_area(v: &[Point3<f32>; 3], p: Point3<f32>) -> f32 {
+    spherical_triangle(v, p)
+        .map(|(_, _, [alpha, beta, gamma])| (alpha + beta + gamma - PI).max(0.0))
+        .unwrap_or(0.0)
+}
+
+// Arvo's method for sampling a triangle uniformly by the solid angle it
+// subtends from p. Returns the barycentric coordinates of the point on the
+// triangle and the solid angle PDF, or None if the triangle is degenerate
+// as seen from p.
+pub fn sample_spherical_triangle(
+    v: &[Point3<f32>; 3],
+    p: Point3<f32>,
+    u: Point2<f32>,
+) -> Option<([f32; 3], f32)> {
+    let ([a, b, c], _, [alpha, beta, gamma]) = spherical_triangle(v, p)?;
+    let area = alpha + beta + gamma - PI;
+    if area <= 0.0 {
+        return None;
+    }
+
+    // Pick the sub-triangle with vertices a, b, and c' on the arc from a to
+    // c whose area is a fraction u.x of the whole.
+    let area_pi = u.x() * area + PI;
+    let (sin_alpha, cos_alpha) = alpha.sin_cos();
+    let sin_phi = area_pi.sin() * cos_alpha - area_pi.cos() * sin_alpha;
+    let cos_phi = area_pi.cos() * cos_alpha + area_pi.sin() * sin_alpha;
+    let k1 = cos_phi + cos_alpha;
+    let k2 = sin_phi - sin_alpha * a.dot(&b);
+    let cos_bp = (k2 + (k2 * cos_phi - k1 * sin_phi) * cos_alpha)
+        / ((k2 * sin_phi + k1 * cos_phi) * sin_alpha);
+    let cos_bp = cos_bp.clamp(-1.0, 1.0);
+    let sin_bp = (1.0 - cos_bp * cos_bp).max(0.0).sqrt();
+    let cp = a * cos_bp + gram_schmidt(c, a).normalized() * sin_bp;
+
+    // Then a direction on the arc from b to c'.
+    let cos_theta = 1.0 - u.y() * (1.0 - cp.dot(&b));
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let w = b * cos_theta + gram_schmidt(cp, b).normalized() * sin_theta;
+
+    // Intersect the ray from p along w with the triangle's plane.
+    let (e1, e2) = (v[1] - v[0], v[2] - v[0]);
+    let s1 = w.cross(&e2);
+    let divisor = s1.dot(&e1);
+    if divisor == 0.0 {
+        return Some(([1.0, 0.0, 0.0], 1.0 / area));
+    }
+    let s = p - v[0];
+    let mut b1 = (s.dot(&s1) / divisor).clamp(0.0, 1.0);
+    let mut b2 = (w.dot(&s.cross(&e1)) / divisor).clamp(0.0, 1.0);
+    if b1 + b2 > 1.0 {
+        let sum = b1 + b2;
+        b1 /= sum;
+        b2 /= sum;
+    }
+    Some(([1.0 - b1 - b2, b1, b2], 1.0 / area))
+}
+
+// The sample that sample_spherical_triangle maps to direction w.
+pub fn invert_spherical_triangle_sample(
+    v: &[Point3<f32>; 3],
+    p: Point3<f32>,
+    w: Vec3<f32>,
+) -> Option<Point2<f32>> {
+    let ([a, b, c], [n_ab, _, _], [alpha, beta, gamma]) = spherical_triangle(v, p)?;
+
+    // c' is where the great circle through b and w crosses the arc a-c.
+    let mut cp = b.cross(&w).cross(&c.cross(&a)).normalized();
+    if cp.dot(&(a + c)) < 0.0 {
+        cp = -cp;
+    }
+
+    let u0 = if a.dot(&cp) > 0.999_998_5 {
+        0.0
+    } else {
+        let (n_cpb, n_acp) = (cp.cross(&b), a.cross(&cp));
+        if n_cpb.mag2() == 0.0 || n_acp.mag2() == 0.0 {
+            return Some(Point2::<f32>::new(0.5));
+        }
+        let (n_cpb, n_acp) = (n_cpb.normalized(), n_acp.normalized());
+        let sub_area = alpha + angle_between(n_ab, n_cpb) + angle_between(n_acp, -n_cpb) - PI;
+        sub_area / (alpha + beta + gamma - PI)
+    };
+    let u1 = (1.0 - w.dot(&b)) / (1.0 - cp.dot(&b));
+    Some(Point2::<f32>::elements(
+        u0.clamp(0.0, 1.0),
+        u1.clamp(0.0, 1.0),
+    ))
+}
+
+// A rectangle with corner s and perpendicular edges ex and ey as seen from
+// p_ref, in a frame centered at p_ref whose z axis points away from the
+// rectangle: it spans [x0, x1] x [y0, y1] in the plane z = z0 < 0. Keeps
+// the interior angles of its projection onto the unit sphere and the z
+// components b0, b1 of the normals of the planes through p_ref and its
+// bottom and top edges.
+struct SphericalRectangle {
+    axes: [Vec3<f32>; 3],
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+    z0: f32,
+    angles: [f32; 4],
+    b0: f32,
+    b1: f32,
+}
+
+impl SphericalRectangle {
+    fn new(p_ref: Point3<f32>, s: Point3<f32>, ex: Vec3<f32>, ey: Vec3<f32>) -> Self {
+        let (exl, eyl) = (ex.mag(), ey.mag());
+        let (x, y) = (ex / exl, ey / eyl);
+        let mut z = x.cross(&y);
+        let d = s - p_ref;
+        let mut z0 = d.dot(&z);
+        if z0 > 0.0 {
+            z = -z;
+            z0 = -z0;
+        }
+        let (x0, y0) = (d.dot(&x), d.dot(&y));
+        let (x1, y1) = (x0 + exl, y0 + eyl);
+
+        let v00 = Vec3::<f32>::elements(x0, y0, z0);
+        let v01 = Vec3::<f32>::elements(x0, y1, z0);
+        let v10 = Vec3::<f32>::elements(x1, y0, z0);
+        let v11 = Vec3::<f32>::elements(x1, y1, z0);
+        let n0 = v00.cross(&v10).normalized();
+        let n1 = v10.cross(&v11).normalized();
+        let n2 = v11.cross(&v01).normalized();
+        let n3 = v01.cross(&v00).normalized();
+        Self {
+            axes: [x, y, z],
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            angles: [
+                angle_between(-n0, n1),
+                angle_between(-n1, n2),
+                angle_between(-n2, n3),
+                angle_between(-n3, n0),
+            ],
+            b0: n0.z(),
+            b1: n2.z(),
+        }
+    }
+
+    fn solid_angle(&self) -> f32 {
+        self.angles.iter().sum::<f32>() - 2.0 * PI
+    }
+
+    // h values bounding the y extent along the line at x = xu.
+    fn h(&self, xu: f32) -> (f32, f32, f32) {
+        let dd = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (dd * dd + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (dd * dd + self.y1 * self.y1).sqrt();
+        (dd, h0, h1)
+    }
+}
+
+// Ureña et al.'s sampling of a rectangle by the solid angle it subtends
+// from p_ref. The rectangle has corner s and perpendicular edges ex and ey.
+// Returns the sampled point and its solid angle PDF; for tiny or degenerate
+// solid angles the point is sampled uniformly by area instead.
+pub fn sample_spherical_rectangle(
+    p_ref: Point3<f32>,
+    s: Point3<f32>,
+    ex: Vec3<f32>,
+    ey: Vec3<f32>,
+    u: Point2<f32>,
+) -> (Point3<f32>, f32) {
+    let rect = SphericalRectangle::new(p_ref, s, ex, ey);
+    let solid_angle = rect.solid_angle();
+    if solid_angle <= 0.0 {
+        return (s + ex * u.x() + ey * u.y(), 0.0);
+    }
+    if solid_angle < 1e-3 {
+        return (s + ex * u.x() + ey * u.y(), 1.0 / solid_angle);
+    }
+
+    // Pick the x coordinate so that the part of the rectangle left of it
+    // subtends a fraction u.x of the solid angle.
+    let [g0, g1, g2, g3] = rect.angles;
+    let au = u.x() * (g0 + g1 - 2.0 * PI) + (u.x() - 1.0) * (g2 + g3);
+    let fu = (au.cos() * rect.b0 - rect.b1) / au.sin();
+    let cu = (1.0 / (fu * fu + rect.b0 * rect.b0).sqrt())
+        .copysign(fu)
+        .clamp(-ONE_MINUS_EPSILON, ONE_MINUS_EPSILON);
+    let xu = (-(cu * rect.z0) / (1.0 - cu * cu).max(0.0).sqrt()).clamp(rect.x0, rect.x1);
+
+    // Then y along that line.
+    let (dd, h0, h1) = rect.h(xu);
+    let hv = h0 + u.y() * (h1 - h0);
+    let yv = if hv * hv < 1.0 - 1e-6 {
+        hv * dd / (1.0 - hv * hv).sqrt()
+    } else {
+        rect.y1
+    };
+
+    let [x, y, z] = rect.axes;
+    (p_ref + x * xu + y * yv + z * rect.z0, 1.0 / solid_angle)
+}
+
+// The sample that sample_spherical_rectangle maps to p_rect.
+pub fn invert_spherical_rectangle_sample(
+    p_ref: Point3<f32>,
+    s: Point3<f32>,
+    ex: Vec3<f32>,
+    ey: Vec3<f32>,
+    p_rect: Point3<f32>,
+) -> Point2<f32> {
+    let rect = SphericalRectangle::new(p_ref, s, ex, ey);
+    let solid_angle = rect.solid_angle();
+    let [x, y, _] = rect.axes;
+    let d = p_rect - p_ref;
+    let (xu, yv) = (d.dot(&x).clamp(rect.x0, rect.x1), d.dot(&y));
+    if solid_angle < 1e-3 {
+        return Point2::<f32>::elements(
+            (xu - rect.x0) / (rect.x1 - rect.x0),
+            (yv - rect.y0) / (rect.y1 - rect.y0),
+        );
+    }
+
+    let (dd, h0, h1) = rect.h(xu);
+    let hv = yv / (dd * dd + yv * yv).sqrt();
+    let u1 = (hv - h0) / (h1 - h0);
+
+    // Invert cu = cos of the angle at which the line x = xu is seen, then
+    // solve fu sin(au) - b0 cos(au) = -b1 for au. Of the two solutions,
+    // keep the one in the range the forward mapping covers.
+    let cu = xu / dd;
+    let fu = (1.0 / (cu * cu) - rect.b0 * rect.b0)
+        .max(0.0)
+        .sqrt()
+        .copysign(cu);
+    let r = (fu * fu + rect.b0 * rect.b0).sqrt();
+    let phi = rect.b0.atan2(fu);
+    let asin = (-rect.b1 / r).clamp(-1.0, 1.0).asin();
+    let [_, _, g2, g3] = rect.angles;
+    let (lo, hi) = (-(g2 + g3), solid_angle - (g2 + g3));
+    let u0 = [phi + asin, phi + PI - asin]
+        .iter()
+        .map(|&au| {
+            // Move au into [lo, lo + 2pi).
+            let au = au - 2.0 * PI * ((au - lo) / (2.0 * PI)).floor();
+            let distance = if au <= hi {
+                0.0
+            } else {
+                (au - hi).min(lo + 2.0 * PI - au)
+            };
+            (distance, (au + g2 + g3) / solid_angle)
+        })
+        .fold(
+            (f32::INFINITY, 0.0),
+            |best, c| if c.0 < best.0 { c } else { best },
+        )
+        .1;
+    Point2::<f32>::elements(u0.clamp(0.0, 1.0), u1.clamp(0.0, 1.0))
+}
+
+// Clarberg's equal-area mapping from [0, 1]^2 to the unit sphere. The
+// square is folded like an octahedron: the inner diamond covers the +z
+// hemisphere and the four corners cover -z.
+pub fn equal_area_square_to_sphere(p: Point2<f32>) -> Vec3<f32> {
+    let (u, v) = (2.0 * p.x() - 1.0, 2.0 * p.y() - 1.0);
+    let (up, vp) = (u.abs(), v.abs());
+
+    let signed_distance = 1.0 - (up + vp);
+    let r = 1.0 - signed_distance.abs();
+    let phi = if r == 0.0 { 1.0 } else { (vp - up) / r + 1.0 } * FRAC_PI_4;
+    let z = (1.0 - r * r).copysign(signed_distance);
+
+    let cos_phi = phi.cos().copysign(u);
+    let sin_phi = phi.sin().copysign(v);
+    let s = r * (2.0 - r * r).max(0.0).sqrt();
+    Vec3::<f32>::elements(cos_phi * s, sin_phi * s, z)
+}
+
+pub fn equal_area_sphere_to_square(d: Vec3<f32>) -> Point2<f32> {
+    let (x, y, z) = (d.x().abs(), d.y().abs(), d.z().abs());
+    let r = (1.0 - z).max(0.0).sqrt();
+
+    let (a, b) = (x.max(y), x.min(y));
+    let b = if a == 0.0 { 0.0 } else { b / a };
+    let mut phi = b.atan() / FRAC_PI_2;
+    if x < y {
+        phi = 1.0 - phi;
+    }
+
+    let mut v = phi * r;
+    let mut u = r - v;
+    if d.z() < 0.0 {
+        std::mem::swap(&mut u, &mut v);
+        u = 1.0 - u;
+        v = 1.0 - v;
+    }
+    u = u.copysign(d.x());
+    v = v.copysign(d.y());
+    Point2::<f32>::elements((u + 1.0) / 2.0, (v + 1.0) / 2.0)
+}
+
+// Folds points outside [0, 1]^2 back in so that the octahedral layout tiles
+// seamlessly, e.g. for film samples that fall just past the image edge.
+pub fn wrap_equal_area_square(p: Point2<f32>) -> Point2<f32> {
+    let (mut u, mut v) = (p.x(), p.y());
+    if u < 0.0 {
+        u = -u;
+        v = 1.0 - v;
+    } else if u > 1.0 {
+        u = 2.0 - u;
+        v = 1.0 - v;
+    }
+    if v < 0.0 {
+        u = 1.0 - u;
+        v = -v;
+    } else if v > 1.0 {
+        u = 1.0 - u;
+        v = 2.0 - v;
+    }
+    Point2::<f32>::elements(u, v)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_concentric_disk() {
+        assert_eq!(
+            sample_uniform_disk_concentric((0.5, 0.5).into()),
+            Point2::<f32>::new(0.0)
+        );
+        for i in 0..16 {
+            for j in 0..16 {
+                let u = Point2::<f32>::elements(i as f32 / 15.0, j as f32 / 15.0);
+                assert!(sample_uniform_disk_concentric(u).mag() <= 1.0 + 1e-6);
+            }
+        }
+        let p = sample_uniform_disk_concentric((1.0, 0.5).into());
+        assert!(p.distance_to(&(1.0, 0.0).into()) < 1e-6);
+    }
+
+    #[test]
+    fn test_equal_area_round_trip() {
+        for i in 0..16 {
+            for j in 0..16 {
+                let u = Point2::<f32>::elements((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0);
+                let d = equal_area_square_to_sphere(u);
+                assert!((d.mag() - 1.0).abs() < 1e-5);
+                assert!(equal_area_sphere_to_square(d).distance_to(&u) < 1e-4);
+            }
+        }
+        let center = equal_area_square_to_sphere((0.5, 0.5).into());
+        assert!(center.distance_to(&(0.0, 0.0, 1.0).into()) < 1e-6);
+    }
+
+    #[test]
+    fn test_uniform_triangle() {
+        for i in 0..16 {
+            for j in 0..16 {
+                let u = Point2::<f32>::elements(i as f32 / 16.0, j as f32 / 16.0);
+                let (b0, b1, b2) = sample_uniform_triangle(u);
+                assert!(b0 >= 0.0 && b1 >= 0.0 && b2 >= -1e-6);
+                assert!((b0 + b1 + b2 - 1.0).abs() < 1e-6);
+            }
+        }
+    }
+
+    fn grid(n: usize) -> impl Iterator<Item = Point2<f32>> {
+        (0..n).flat_map(move |i| {
+            (0..n).map(move |j| {
+                Point2::<f32>::elements((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32)
+            })
+        })
+    }
+
+    // Integral over the unit sphere of pdf, by summing it over directions
+    // from the equal-area mapping of a fine grid.
+    fn integrate_over_sphere(pdf: impl Fn(Vec3<f32>) -> f32) -> f32 {
+        let n = 512;
+        grid(n)
+            .map(|u| pdf(equal_area_square_to_sphere(u)))
+            .sum::<f32>()
+            * 4.0
+            * PI
+            / (n * n) as f32
+    }
+
+    fn assert_round_trip(u: Point2<f32>, inverse: Point2<f32>) {
+        assert!(inverse.distance_to(&u) < 2e-3, "{:?} != {:?}", inverse, u);
+    }
+
+    #[test]
+    fn test_disk_inverse() {
+        for u in grid(16) {
+            let p = sample_uniform_disk_concentric(u);
+            assert_round_trip(u, invert_uniform_disk_concentric_sample(p));
+        }
+    }
+
+    #[test]
+    fn test_hemisphere_and_sphere() {
+        for u in grid(16) {
+            let w = sample_uniform_hemisphere(u);
+            assert!((w.mag() - 1.0).abs() < 1e-5 && w.z() >= 0.0);
+            assert_round_trip(u, invert_uniform_hemisphere_sample(w));
+
+            let w = sample_uniform_sphere(u);
+            assert!((w.mag() - 1.0).abs() < 1e-5);
+            assert_round_trip(u, invert_uniform_sphere_sample(w));
+
+            let w = sample_cosine_hemisphere(u);
+            assert!((w.mag() - 1.0).abs() < 1e-5 && w.z() >= 0.0);
+            assert_round_trip(u, invert_cosine_hemisphere_sample(w));
+
+            let w = sample_uniform_cone(u, 0.8);
+            assert!(w.z() >= 0.8 - 1e-6);
+            assert_round_trip(u, invert_uniform_cone_sample(w, 0.8));
+        }
+
+        let hemisphere = |w: Vec3<f32>| if w.z() > 0.0 { 1.0 } else { 0.0 };
+        let integral = integrate_over_sphere(|w| hemisphere(w) * uniform_hemisphere_pdf());
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+        let integral = integrate_over_sphere(|_| uniform_sphere_pdf());
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+        let integral = integrate_over_sphere(|w| cosine_hemisphere_pdf(w.z()));
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+        let cone = |w: Vec3<f32>| if w.z() > 0.8 { 1.0 } else { 0.0 };
+        let integral = integrate_over_sphere(|w| cone(w) * uniform_cone_pdf(0.8));
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+
+    #[test]
+    fn test_triangle_inverse() {
+        for u in grid(16) {
+            let b = sample_uniform_triangle(u);
+            assert_round_trip(u, invert_uniform_triangle_sample(b));
+        }
+    }
+
+    #[test]
+    fn test_spherical_triangle() {
+        let v = [
+            Point3::<f32>::elements(-1.0, -0.5, 2.0),
+            Point3::<f32>::elements(1.5, -1.0, 2.5),
+            Point3::<f32>::elements(0.0, 1.0, 1.5),
+        ];
+        let p = Point3::<f32>::new(0.0);
+        let area = spherical_triangle_area(&v, p);
+        for u in grid(16) {
+            let (b, pdf) = sample_spherical_triangle(&v, p, u).unwrap();
+            assert!(b.iter().all(|&b| (0.0..=1.0).contains(&b)));
+            assert!((pdf - 1.0 / area).abs() < 1e-4);
+            let w = (v[0] * b[0] + v[1] * b[1] + v[2] * b[2] - p).normalized();
+            assert_round_trip(u, invert_spherical_triangle_sample(&v, p, w).unwrap());
+        }
+
+        // The PDF integrates to one over the directions that hit the
+        // triangle, i.e. area is the triangle's solid angle.
+        let n = (v[1] - v[0]).cross(&(v[2] - v[0]));
+        let inside = |w: Vec3<f32>| {
+            let hits = (0..3).all(|i| {
+                let (a, b) = (v[i] - p, v[(i + 1) % 3] - p);
+                a.cross(&b).dot(&w) * a.cross(&b).dot(&n) >= 0.0
+            });
+            hits && w.dot(&n) * (v[0] - p).dot(&n) > 0.0
+        };
+        let integral = integrate_over_sphere(|w| if inside(w) { 1.0 / area } else { 0.0 });
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+        assert!(
+            sample_spherical_triangle(&v, v[0] * 0.5 + v[1] * 0.5, (0.5, 0.5).into()).is_none()
+        );
+    }
+
+    #[test]
+    fn test_spherical_rectangle() {
+        let p_ref = Point3::<f32>::elements(0.2, -0.3, 0.0);
+        let s = Point3::<f32>::elements(-1.0, -0.5, 1.0);
+        let (ex, ey) = (
+            Vec3::<f32>::elements(2.0, 0.0, 0.0),
+            Vec3::<f32>::elements(0.0, 1.5, 0.5),
+        );
+        let normal = ex.cross(&ey).normalized();
+        let mut pdf = 0.0;
+        for u in grid(16) {
+            let (p, sample_pdf) = sample_spherical_rectangle(p_ref, s, ex, ey, u);
+            pdf = sample_pdf;
+            // On the rectangle.
+            assert!((p - s).dot(&normal).abs() < 1e-4);
+            let (a, b) = ((p - s).dot(&ex) / ex.mag2(), (p - s).dot(&ey) / ey.mag2());
+            assert!((-1e-4..=1.0 + 1e-4).contains(&a) && (-1e-4..=1.0 + 1e-4).contains(&b));
+            assert_round_trip(u, invert_spherical_rectangle_sample(p_ref, s, ex, ey, p));
+        }
+
+        let inside = |w: Vec3<f32>| {
+            let t = (s - p_ref).dot(&normal) / w.dot(&normal);
+            if t <= 0.0 {
+                return false;
+            }
+            let q = p_ref + w * t - s;
+            let (a, b) = (q.dot(&ex) / ex.mag2(), q.dot(&ey) / ey.mag2());
+            (0.0..1.0).contains(&a) && (0.0..1.0).contains(&b)
+        };
+        let integral = integrate_over_sphere(|w| if inside(w) { pdf } else { 0.0 });
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+}
@@ -0,0 +1,384 @@
+#![allow(dead_code)]
+
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul};
+
+// The range of visible wavelengths, in nanometers, that spectral quantities
+// are defined over.
+pub const LAMBDA_MIN: f32 = 360.0;
+pub const LAMBDA_MAX: f32 = 830.0;
+
+// Number of wavelengths carried by each camera path.
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+// A lobe with separate widths below and above its peak.
+fn piecewise_gaussian(lambda: f32, mu: f32, sigma_lo: f32, sigma_hi: f32) -> f32 {
+    let t = (lambda - mu) / if lambda < mu { sigma_lo } else { sigma_hi };
+    (-0.5 * t * t).exp()
+}
+
+// The CIE 1931 2° color matching functions, as the multi-lobe fit of Wyman,
+// Sloan and Shirley (2013), which is within the accuracy of the tabulated
+// data for rendering purposes.
+pub fn cie_x(lambda: f32) -> f32 {
+    1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(lambda: f32) -> f32 {
+    0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(lambda: f32) -> f32 {
+    1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+// Sums f at every whole nanometer of the visible range, which is how
+// spectra are integrated against the matching functions.
+pub fn integrate_visible(f: impl Fn(f32) -> f32) -> f32 {
+    (LAMBDA_MIN as i32..=LAMBDA_MAX as i32)
+        .map(|l| f(l as f32))
+        .sum()
+}
+
+pub trait Spectrum: Send + Sync {
+    fn evaluate(&self, lambda: f32) -> f32;
+
+    fn sample(&self, lambda: &SampledWavelengths) -> SampledSpectrum {
+        let mut s = SampledSpectrum::default();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            s[i] = self.evaluate(lambda[i]);
+        }
+        s
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ConstantSpectrum(pub f32);
+
+impl Spectrum for ConstantSpectrum {
+    fn evaluate(&self, _lambda: f32) -> f32 {
+        self.0
+    }
+}
+
+// Planck's law for a blackbody at temperature t (in kelvin), scaled to one
+// at its peak so that only its color matters.
+#[derive(Copy, Clone, Debug)]
+pub struct BlackbodySpectrum {
+    t: f32,
+    normalization: f32,
+}
+
+impl BlackbodySpectrum {
+    pub fn new(t: f32) -> Self {
+        // Wien's displacement law gives the peak wavelength.
+        let lambda_max = 2.897_772e-3 / t * 1e9;
+        Self {
+            t,
+            normalization: 1.0 / blackbody(lambda_max, t),
+        }
+    }
+}
+
+// Emitted radiance of a blackbody at wavelength lambda (nm).
+pub fn blackbody(lambda: f32, t: f32) -> f32 {
+    if t <= 0.0 {
+        return 0.0;
+    }
+    let (c, h, kb) = (299_792_458.0f64, 6.626_070_15e-34f64, 1.380_649e-23f64);
+    let l = lambda as f64 * 1e-9;
+    let le = 2.0 * h * c * c / (l.powi(5) * ((h * c / (l * kb * t as f64)).exp() - 1.0));
+    le as f32
+}
+
+impl Spectrum for BlackbodySpectrum {
+    fn evaluate(&self, lambda: f32) -> f32 {
+        blackbody(lambda, self.t) * self.normalization
+    }
+}
+
+// A spectrum given by (wavelength, value) pairs, interpolated linearly and
+// zero outside the covered range. Used for measured data such as sensor
+// sensitivities.
+#[derive(Clone, Debug)]
+pub struct PiecewiseLinearSpectrum {
+    lambdas: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl PiecewiseLinearSpectrum {
+    // Returns None unless there is a finite value per wavelength and the
+    // wavelengths are finite and increasing.
+    pub fn new(lambdas: &[f32], values: &[f32]) -> Option<Self> {
+        if lambdas.len() != values.len()
+            || !lambdas.iter().chain(values).all(|v| v.is_finite())
+            || !lambdas.windows(2).all(|w| w[0] < w[1])
+        {
+            return None;
+        }
+        Some(Self {
+            lambdas: lambdas.to_vec(),
+            values: values.to_vec(),
+        })
+    }
+
+    // Alternating wavelengths and values, as measured data is usually
+    // listed. Returns None for an odd count or if new would.
+    pub fn from_interleaved(data: &[f32]) -> Option<Self> {
+        if !data.len().is_multiple_of(2) {
+            return None;
+        }
+        let lambdas: Vec<f32> = data.iter().step_by(2).cloned().collect();
+        let values: Vec<f32> = data.iter().skip(1).step_by(2).cloned().collect();
+        Self::new(&lambdas, &values)
+    }
+
+    // Tabulates f at every whole nanometer of the visible range. Returns
+    // None if f is not finite there.
+    pub fn from_function(f: impl Fn(f32) -> f32) -> Option<Self> {
+        let lambdas: Vec<f32> = (LAMBDA_MIN as i32..=LAMBDA_MAX as i32)
+            .map(|l| l as f32)
+            .collect();
+        let values: Vec<f32> = lambdas.iter().map(|&l| f(l)).collect();
+        Self::new(&lambdas, &values)
+    }
+}
+
+impl Spectrum for PiecewiseLinearSpectrum {
+    fn evaluate(&self, lambda: f32) -> f32 {
+        let n = self.lambdas.len();
+        if n == 0 || lambda < self.lambdas[0] || lambda > self.lambdas[n - 1] {
+            return 0.0;
+        }
+        let i = self.lambdas.partition_point(|&l| l <= lambda).clamp(1, n) - 1;
+        if i == n - 1 {
+            return self.values[i];
+        }
+        let t = (lambda - self.lambdas[i]) / (self.lambdas[i + 1] - self.lambdas[i]);
+        self.values[i] + t * (self.values[i + 1] - self.values[i])
+    }
+}
+
+// The wavelengths carried by a camera path and their sampling densities.
+// Following hero wavelength sampling, they are evenly spaced in sample
+// space from a single random value, so together they cover the spectrum.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SampledWavelengths {
+    lambda: [f32; N_SPECTRUM_SAMPLES],
+    pdf: [f32; N_SPECTRUM_SAMPLES],
+}
+
+// Density and inverse CDF of a distribution over visible wavelengths that
+// roughly follows the sensitivity of the eye, which reduces color noise
+// compared to uniform sampling.
+pub fn visible_wavelengths_pdf(lambda: f32) -> f32 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let c = (0.0072 * (lambda - 538.0)).cosh();
+    0.003_939_804 / (c * c)
+}
+
+pub fn sample_visible_wavelengths(u: f32) -> f32 {
+    538.0 - 138.888_89 * (0.856_910_6 - 1.827_502 * u).atanh()
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f32, lambda_min: f32, lambda_max: f32) -> Self {
+        let mut s = Self::default();
+        let delta = (lambda_max - lambda_min) / N_SPECTRUM_SAMPLES as f32;
+        s.lambda[0] = lambda_min + u * (lambda_max - lambda_min);
+        for i in 1..N_SPECTRUM_SAMPLES {
+            s.lambda[i] = s.lambda[i - 1] + delta;
+            if s.lambda[i] > lambda_max {
+                s.lambda[i] = lambda_min + (s.lambda[i] - lambda_max);
+            }
+        }
+        s.pdf = [1.0 / (lambda_max - lambda_min); N_SPECTRUM_SAMPLES];
+        s
+    }
+
+    pub fn sample_visible(u: f32) -> Self {
+        let mut s = Self::default();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            let up = (u + i as f32 / N_SPECTRUM_SAMPLES as f32).fract();
+            s.lambda[i] = sample_visible_wavelengths(up);
+            s.pdf[i] = visible_wavelengths_pdf(s.lambda[i]);
+        }
+        s
+    }
+
+    pub fn pdf(&self) -> SampledSpectrum {
+        SampledSpectrum(self.pdf)
+    }
+
+    // Leaves only the first wavelength, for effects such as dispersion that
+    // send each wavelength in a different direction.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for i in 1..N_SPECTRUM_SAMPLES {
+            self.pdf[i] = 0.0;
+        }
+        self.pdf[0] /= N_SPECTRUM_SAMPLES as f32;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&p| p == 0.0)
+    }
+}
+
+impl Index<usize> for SampledWavelengths {
+    type Output = f32;
+    fn index(&self, i: usize) -> &f32 {
+        &self.lambda[i]
+    }
+}
+
+// Values of a spectral quantity at a path's sampled wavelengths.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SampledSpectrum(pub [f32; N_SPECTRUM_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn splat(v: f32) -> Self {
+        Self([v; N_SPECTRUM_SAMPLES])
+    }
+
+    pub fn average(&self) -> f32 {
+        self.0.iter().sum::<f32>() / N_SPECTRUM_SAMPLES as f32
+    }
+
+    pub fn max_component(&self) -> f32 {
+        self.0.iter().cloned().fold(f32::MIN, f32::max)
+    }
+
+    // Divides by d, with zero where d is zero (e.g. the pdf of a
+    // terminated wavelength).
+    pub fn safe_div(&self, d: &SampledSpectrum) -> Self {
+        let mut s = *self;
+        for i in 0..N_SPECTRUM_SAMPLES {
+            s.0[i] = if d.0[i] != 0.0 { s.0[i] / d.0[i] } else { 0.0 };
+        }
+        s
+    }
+}
+
+impl Index<usize> for SampledSpectrum {
+    type Output = f32;
+    fn index(&self, i: usize) -> &f32 {
+        &self.0[i]
+    }
+}
+
+impl IndexMut<usize> for SampledSpectrum {
+    fn index_mut(&mut self, i: usize) -> &mut f32 {
+        &mut self.0[i]
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+    fn add(mut self, o: Self) -> Self {
+        for i in 0..N_SPECTRUM_SAMPLES {
+            self.0[i] += o.0[i];
+        }
+        self
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, o: Self) {
+        *self = *self + o;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+    fn mul(mut self, o: Self) -> Self {
+        for i in 0..N_SPECTRUM_SAMPLES {
+            self.0[i] *= o.0[i];
+        }
+        self
+    }
+}
+
+impl Mul<f32> for SampledSpectrum {
+    type Output = Self;
+    fn mul(mut self, s: f32) -> Self {
+        self.0.iter_mut().for_each(|v| *v *= s);
+        self
+    }
+}
+
+impl Div<f32> for SampledSpectrum {
+    type Output = Self;
+    fn div(self, s: f32) -> Self {
+        self * (1.0 / s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matching_functions() {
+        // Peaks and integrals close to the tabulated CIE data.
+        assert!((cie_y(555.0) - 1.0).abs() < 0.01);
+        assert!((cie_x(600.0) - 1.06).abs() < 0.01);
+        assert!((cie_z(445.0) - 1.78).abs() < 0.03);
+        assert!((integrate_visible(cie_y) - 106.857).abs() < 1.0);
+        assert!((integrate_visible(cie_x) - integrate_visible(cie_y)).abs() < 1.5);
+    }
+
+    #[test]
+    fn test_wavelength_sampling() {
+        // The visible-wavelength density integrates to one and its inverse
+        // CDF matches it.
+        let total: f32 = integrate_visible(visible_wavelengths_pdf);
+        assert!((total - 1.0).abs() < 0.01);
+        let mut cdf = 0.0;
+        for l in LAMBDA_MIN as i32..LAMBDA_MAX as i32 {
+            cdf += visible_wavelengths_pdf(l as f32 + 0.5);
+            let lambda = sample_visible_wavelengths(cdf);
+            assert!((lambda - (l + 1) as f32).abs() < 0.5, "{} {}", l, lambda);
+        }
+
+        let s = SampledWavelengths::sample_visible(0.9);
+        for i in 0..N_SPECTRUM_SAMPLES {
+            assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&s[i]));
+            assert_eq!(s.pdf()[i], visible_wavelengths_pdf(s[i]));
+        }
+        let s = SampledWavelengths::sample_uniform(0.5, 400.0, 800.0);
+        assert_eq!(s.lambda, [600.0, 700.0, 800.0, 500.0]);
+
+        let mut s = SampledWavelengths::sample_visible(0.1);
+        let pdf = s.pdf()[0];
+        s.terminate_secondary();
+        assert!(s.secondary_terminated());
+        assert_eq!(s.pdf()[0], pdf / 4.0);
+    }
+
+    #[test]
+    fn test_spectra() {
+        let s = PiecewiseLinearSpectrum::from_interleaved(&[400.0, 1.0, 500.0, 3.0, 600.0, 2.0])
+            .unwrap();
+        assert_eq!(s.evaluate(450.0), 2.0);
+        assert_eq!(s.evaluate(600.0), 2.0);
+        assert_eq!(s.evaluate(399.0), 0.0);
+        assert_eq!(s.evaluate(700.0), 0.0);
+        assert!(PiecewiseLinearSpectrum::from_interleaved(&[400.0, 1.0, 500.0]).is_none());
+        assert!(PiecewiseLinearSpectrum::new(&[500.0, 400.0], &[1.0, 2.0]).is_none());
+        assert!(PiecewiseLinearSpectrum::new(&[400.0, 500.0], &[1.0]).is_none());
+        assert!(PiecewiseLinearSpectrum::new(&[400.0, 500.0], &[1.0, f32::NAN]).is_none());
+
+        // Wien's law: the peak of a 5000 K blackbody is near 580 nm.
+        let b = BlackbodySpectrum::new(5000.0);
+        assert!((b.evaluate(579.55) - 1.0).abs() < 1e-4);
+        assert!(b.evaluate(450.0) < 1.0 && b.evaluate(700.0) < 1.0);
+    }
+}
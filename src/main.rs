@@ -1,3 +1,56 @@
+use pbrtrs_lib::image::Image;
+use pbrtrs_lib::postprocess::PostProcess;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: pbrtrs [--exposure EV] [--tonemap NAME] [--bloom SPEC] \
+[--encoding NAME] [--post SPEC] <input> <output>
+
+Applies post-processing to a rendered image and writes the result.
+  --exposure EV    scale by 2^EV
+  --tonemap NAME   none, reinhard[:white], aces or agx
+  --bloom SPEC     strength[:radius[:threshold]]
+  --encoding NAME  linear, srgb or gamma:g
+  --post SPEC      all of the above at once, e.g. exposure=1,tonemap=aces";
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut spec = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let key = match arg.as_str() {
+            "--exposure" | "--tonemap" | "--bloom" | "--encoding" => &arg[2..],
+            "--post" => "",
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => {
+                paths.push(arg);
+                continue;
+            }
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        if key.is_empty() {
+            spec.push(value.clone());
+        } else {
+            spec.push(format!("{}={}", key, value));
+        }
+    }
+    let (input, output) = match paths.as_slice() {
+        [input, output] => (Path::new(input), Path::new(output)),
+        _ => return Err("expected an input and an output image".to_string()),
+    };
+    let post: PostProcess = spec.join(",").parse()?;
+    let (image, metadata) =
+        Image::read(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    post.write_image(&image, output, &metadata)
+        .map_err(|e| format!("{}: {}", output.display(), e))
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("pbrtrs: {}\n\n{}", e, USAGE);
+        process::exit(1);
+    }
 }